@@ -1,41 +1,356 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
 
-use serde::{Deserialize, Serialize};
+use serde::de::{self, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
 pub type Attributes = HashMap<String, Value>;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Span {
-    pub trace_id: String,
-    pub span_id: String,
-    pub trace_state: Option<String>,
-    pub parent_span_id: Option<String>,
-    pub name: String,
-    pub kind: Option<i32>,
+// The string fields of the spans, events and links borrow from the JSON input when they don't contain escape sequences
+// (`serde_json::from_str`, `serde_json::Deserializer::from_str`). Spans deserialized from a reader own their strings
+// (`Span<'static>`). The attribute values are `serde_json::Value`s, they always own their strings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Span<'a> {
+    #[serde(borrow)]
+    pub trace_id: Cow<'a, str>,
+    #[serde(borrow)]
+    pub span_id: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "optional_cow_str")]
+    pub trace_state: Option<Cow<'a, str>>,
+    #[serde(borrow, default, deserialize_with = "optional_cow_str")]
+    pub parent_span_id: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    pub kind: Option<SpanKind>,
     pub start_time_unix_nano: u64,
     pub end_time_unix_nano: Option<u64>,
     pub attributes: Option<Attributes>,
     pub dropped_attributes_count: Option<u32>,
-    pub events: Option<Vec<Event>>,
+    #[serde(borrow)]
+    pub events: Option<Vec<Event<'a>>>,
     pub dropped_events_count: Option<u32>,
-    pub links: Option<Vec<Link>>,
+    #[serde(borrow)]
+    pub links: Option<Vec<Link<'a>>>,
     pub dropped_links_count: Option<u32>,
+    #[serde(borrow)]
+    pub status: Option<Status<'a>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Event {
+// `Span.SpanKind` enum of trace.proto. Serialized as its number, deserialized from its number or its name
+// (`"SPAN_KIND_SERVER"`), any other value is an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SpanKind {
+    Unspecified = 0,
+    Internal = 1,
+    Server = 2,
+    Client = 3,
+    Producer = 4,
+    Consumer = 5,
+}
+
+impl SpanKind {
+    pub const ALL: [SpanKind; 6] = [
+        SpanKind::Unspecified,
+        SpanKind::Internal,
+        SpanKind::Server,
+        SpanKind::Client,
+        SpanKind::Producer,
+        SpanKind::Consumer,
+    ];
+
+    pub fn from_i64(value: i64) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| *kind as i64 == value)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SpanKind::Unspecified => "SPAN_KIND_UNSPECIFIED",
+            SpanKind::Internal => "SPAN_KIND_INTERNAL",
+            SpanKind::Server => "SPAN_KIND_SERVER",
+            SpanKind::Client => "SPAN_KIND_CLIENT",
+            SpanKind::Producer => "SPAN_KIND_PRODUCER",
+            SpanKind::Consumer => "SPAN_KIND_CONSUMER",
+        }
+    }
+}
+
+impl fmt::Display for SpanKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for SpanKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(*self as i32)
+    }
+}
+
+impl<'de> Deserialize<'de> for SpanKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpanKindVisitor;
+
+        impl<'de> Visitor<'de> for SpanKindVisitor {
+            type Value = SpanKind;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a span kind number (0 to 5) or name (SPAN_KIND_UNSPECIFIED to SPAN_KIND_CONSUMER)")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<SpanKind, E> {
+                SpanKind::from_i64(value).ok_or_else(|| E::invalid_value(Unexpected::Signed(value), &self))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<SpanKind, E> {
+                SpanKind::ALL
+                    .iter()
+                    .copied()
+                    .find(|kind| *kind as u64 == value)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<SpanKind, E> {
+                SpanKind::from_name(value).ok_or_else(|| E::invalid_value(Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(SpanKindVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Status<'a> {
+    pub code: Option<StatusCode>,
+    #[serde(borrow, default, deserialize_with = "optional_cow_str")]
+    pub message: Option<Cow<'a, str>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Event<'a> {
     pub time_unix_nano: u64,
-    pub name: String,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
     pub attributes: Attributes,
     pub dropped_attributes_count: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Link {
-    pub trace_id: String,
-    pub span_id: String,
-    pub trace_state: Option<String>,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Link<'a> {
+    #[serde(borrow)]
+    pub trace_id: Cow<'a, str>,
+    #[serde(borrow)]
+    pub span_id: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "optional_cow_str")]
+    pub trace_state: Option<Cow<'a, str>>,
     pub attributes: Attributes,
     pub dropped_attributes_count: Option<u32>,
 }
+
+impl Span<'_> {
+    // Copies the borrowed strings, the span doesn't depend on the input buffer anymore.
+    pub fn into_owned(self) -> Span<'static> {
+        Span {
+            trace_id: Cow::Owned(self.trace_id.into_owned()),
+            span_id: Cow::Owned(self.span_id.into_owned()),
+            trace_state: self.trace_state.map(|value| Cow::Owned(value.into_owned())),
+            parent_span_id: self.parent_span_id.map(|value| Cow::Owned(value.into_owned())),
+            name: Cow::Owned(self.name.into_owned()),
+            kind: self.kind,
+            start_time_unix_nano: self.start_time_unix_nano,
+            end_time_unix_nano: self.end_time_unix_nano,
+            attributes: self.attributes,
+            dropped_attributes_count: self.dropped_attributes_count,
+            events: self.events.map(|events| events.into_iter().map(Event::into_owned).collect()),
+            dropped_events_count: self.dropped_events_count,
+            links: self.links.map(|links| links.into_iter().map(Link::into_owned).collect()),
+            dropped_links_count: self.dropped_links_count,
+            status: self.status.map(Status::into_owned),
+        }
+    }
+}
+
+// `Status.StatusCode` enum of trace.proto, serialized and deserialized like `SpanKind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StatusCode {
+    Unset = 0,
+    Ok = 1,
+    Error = 2,
+}
+
+impl StatusCode {
+    pub const ALL: [StatusCode; 3] = [StatusCode::Unset, StatusCode::Ok, StatusCode::Error];
+
+    pub fn from_i64(value: i64) -> Option<Self> {
+        Self::ALL.iter().copied().find(|code| *code as i64 == value)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|code| code.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatusCode::Unset => "STATUS_CODE_UNSET",
+            StatusCode::Ok => "STATUS_CODE_OK",
+            StatusCode::Error => "STATUS_CODE_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for StatusCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(*self as i32)
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StatusCodeVisitor;
+
+        impl<'de> Visitor<'de> for StatusCodeVisitor {
+            type Value = StatusCode;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a status code number (0 to 2) or name (STATUS_CODE_UNSET, STATUS_CODE_OK or STATUS_CODE_ERROR)")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<StatusCode, E> {
+                StatusCode::from_i64(value).ok_or_else(|| E::invalid_value(Unexpected::Signed(value), &self))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<StatusCode, E> {
+                StatusCode::ALL
+                    .iter()
+                    .copied()
+                    .find(|code| *code as u64 == value)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<StatusCode, E> {
+                StatusCode::from_name(value).ok_or_else(|| E::invalid_value(Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(StatusCodeVisitor)
+    }
+}
+
+impl Status<'_> {
+    pub fn into_owned(self) -> Status<'static> {
+        Status {
+            code: self.code,
+            message: self.message.map(|value| Cow::Owned(value.into_owned())),
+        }
+    }
+}
+
+impl Event<'_> {
+    pub fn into_owned(self) -> Event<'static> {
+        Event {
+            time_unix_nano: self.time_unix_nano,
+            name: Cow::Owned(self.name.into_owned()),
+            attributes: self.attributes,
+            dropped_attributes_count: self.dropped_attributes_count,
+        }
+    }
+}
+
+impl Link<'_> {
+    pub fn into_owned(self) -> Link<'static> {
+        Link {
+            trace_id: Cow::Owned(self.trace_id.into_owned()),
+            span_id: Cow::Owned(self.span_id.into_owned()),
+            trace_state: self.trace_state.map(|value| Cow::Owned(value.into_owned())),
+            attributes: self.attributes,
+            dropped_attributes_count: self.dropped_attributes_count,
+        }
+    }
+}
+
+// `Cow<str>` only borrows when it is directly the type of a field marked with `#[serde(borrow)]`, not when it is wrapped
+// in an `Option`.
+fn optional_cow_str<'de: 'a, 'a, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Cow<'a, str>>, D::Error> {
+    #[derive(Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+
+    Ok(Option::<Borrowed<'a>>::deserialize(deserializer)?.map(|value| value.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{Span, SpanKind, StatusCode};
+
+    #[test]
+    fn strings_are_borrowed_from_the_input() {
+        let input = r#"{"trace_id":"t1","span_id":"s1","trace_state":"state","parent_span_id":"s0","name":"GET \"/\"","start_time_unix_nano":1,
+            "events":[{"time_unix_nano":2,"name":"event","attributes":{}}],"links":[{"trace_id":"t2","span_id":"s2","attributes":{}}],
+            "status":{"code":2,"message":"error"}}"#;
+        let span: Span = serde_json::from_str(input).unwrap();
+
+        assert!(matches!(span.trace_id, Cow::Borrowed("t1")));
+        assert!(matches!(span.trace_state, Some(Cow::Borrowed("state"))));
+        assert!(matches!(span.parent_span_id, Some(Cow::Borrowed("s0"))));
+        // escaped strings can't be borrowed
+        assert!(matches!(&span.name, Cow::Owned(name) if name == "GET \"/\""));
+        assert!(matches!(span.events.as_ref().unwrap()[0].name, Cow::Borrowed("event")));
+        assert!(span.links.as_ref().unwrap()[0].trace_state.is_none());
+        assert!(matches!(span.status.as_ref().unwrap().message, Some(Cow::Borrowed("error"))));
+
+        let span = span.into_owned();
+        assert!(matches!(span.trace_id, Cow::Owned(_)));
+        assert!(matches!(span.status.unwrap().message, Some(Cow::Owned(_))));
+    }
+
+    #[test]
+    fn span_kinds_are_validated() {
+        let span = |kind: &str| format!(r#"{{"trace_id":"t","span_id":"s","name":"n","start_time_unix_nano":1,"kind":{}}}"#, kind);
+        let kind = |kind: &str| serde_json::from_str::<Span>(&span(kind)).map(|span| span.kind);
+
+        assert_eq!(kind("2").unwrap(), Some(SpanKind::Server));
+        assert_eq!(kind(r#""SPAN_KIND_CONSUMER""#).unwrap(), Some(SpanKind::Consumer));
+        assert_eq!(kind("null").unwrap(), None);
+        for invalid in ["-1", "6", "256", "2.0", r#""SERVER""#, r#""2""#] {
+            assert!(kind(invalid).is_err(), "{}", invalid);
+        }
+
+        let input = span(r#""SPAN_KIND_CLIENT""#);
+        let span: Span = serde_json::from_str(&input).unwrap();
+        assert!(serde_json::to_string(&span).unwrap().contains(r#""kind":3"#));
+    }
+
+    #[test]
+    fn status_codes_are_validated() {
+        let span = |code: &str| {
+            format!(
+                r#"{{"trace_id":"t","span_id":"s","name":"n","start_time_unix_nano":1,"status":{{"code":{}}}}}"#,
+                code
+            )
+        };
+        let code = |code: &str| serde_json::from_str::<Span>(&span(code)).map(|span| span.status.unwrap().code);
+
+        assert_eq!(code("2").unwrap(), Some(StatusCode::Error));
+        assert_eq!(code(r#""STATUS_CODE_OK""#).unwrap(), Some(StatusCode::Ok));
+        assert_eq!(code("null").unwrap(), None);
+        for invalid in ["-1", "3", "258", "1.0", r#""OK""#] {
+            assert!(code(invalid).is_err(), "{}", invalid);
+        }
+
+        let input = span(r#""STATUS_CODE_OK""#);
+        let span: Span = serde_json::from_str(&input).unwrap();
+        assert!(serde_json::to_string(&span).unwrap().contains(r#""code":1"#));
+    }
+}
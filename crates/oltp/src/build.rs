@@ -1,7 +1,8 @@
 use std::error::Error;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    prost_build::compile_protos(
+    // Arrow payloads are decoded as `Bytes` slices of the protobuf buffer instead of being copied into `Vec<u8>`.
+    prost_build::Config::new().bytes([".opentelemetry.proto.events.v1.InstrumentationLibraryEvents"]).compile_protos(
         &[
             "proto/opentelemetry/proto/metrics/v1/metrics.proto",
             "proto/opentelemetry/proto/trace/v1/trace.proto",
@@ -1,3 +1,5 @@
+// Generated by prost from the protos (see build.rs), the generated doc comments are not linted.
+#[allow(clippy::all)]
 pub mod opentelemetry {
     pub mod proto {
         pub mod common {
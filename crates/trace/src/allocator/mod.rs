@@ -0,0 +1,152 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use serde::Serialize;
+
+// Allocation counters of the current thread. The live bytes are signed as a thread can free memory allocated by
+// another one.
+struct Counters {
+    allocations: Cell<u64>,
+    allocated_bytes: Cell<u64>,
+    live_bytes: Cell<i64>,
+    peak_live_bytes: Cell<i64>,
+}
+
+thread_local! {
+    // Const initialized without destructor, the allocator can use it without allocating.
+    static COUNTERS: Counters = const {
+        Counters {
+            allocations: Cell::new(0),
+            allocated_bytes: Cell::new(0),
+            live_bytes: Cell::new(0),
+            peak_live_bytes: Cell::new(0),
+        }
+    };
+}
+
+// Global allocator delegating to the system allocator and counting allocations, allocated bytes and live bytes.
+// The counters are per thread, a probe only measures the allocations made by the thread that started it.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record_deallocation(layout.size());
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record_deallocation(layout.size());
+            record_allocation(new_size);
+        }
+        new_ptr
+    }
+}
+
+// `try_with` as the allocator is still called while the thread locals of an exiting thread are torn down.
+fn record_allocation(size: usize) {
+    let _ = COUNTERS.try_with(|counters| {
+        counters.allocations.set(counters.allocations.get() + 1);
+        counters.allocated_bytes.set(counters.allocated_bytes.get() + size as u64);
+        let live_bytes = counters.live_bytes.get() + size as i64;
+        counters.live_bytes.set(live_bytes);
+        counters.peak_live_bytes.set(counters.peak_live_bytes.get().max(live_bytes));
+    });
+}
+
+fn record_deallocation(size: usize) {
+    let _ = COUNTERS.try_with(|counters| counters.live_bytes.set(counters.live_bytes.get() - size as i64));
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct AllocationStats {
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    // Maximum number of bytes allocated on top of the live bytes at the beginning of the phase.
+    pub peak_live_bytes: u64,
+}
+
+impl AllocationStats {
+    // Allocations and allocated bytes are accumulated, the peak is the highest peak observed.
+    pub fn add(&mut self, other: &AllocationStats) {
+        self.allocations += other.allocations;
+        self.allocated_bytes += other.allocated_bytes;
+        self.peak_live_bytes = self.peak_live_bytes.max(other.peak_live_bytes);
+    }
+
+    pub fn mean<'a>(stats: impl Iterator<Item = &'a AllocationStats>) -> AllocationStats {
+        let mut count = 0;
+        let mut total = AllocationStats::default();
+        for stats in stats {
+            total.add(stats);
+            count += 1;
+        }
+        total.allocations = total.allocations.checked_div(count).unwrap_or(0);
+        total.allocated_bytes = total.allocated_bytes.checked_div(count).unwrap_or(0);
+        total
+    }
+}
+
+// Measures the allocations made by the current thread between `start` and `stop`.
+pub struct AllocationProbe {
+    allocations: u64,
+    allocated_bytes: u64,
+    live_bytes: i64,
+}
+
+impl AllocationProbe {
+    pub fn start() -> Self {
+        COUNTERS.with(|counters| {
+            let live_bytes = counters.live_bytes.get();
+            counters.peak_live_bytes.set(live_bytes);
+            Self {
+                allocations: counters.allocations.get(),
+                allocated_bytes: counters.allocated_bytes.get(),
+                live_bytes,
+            }
+        })
+    }
+
+    pub fn stop(&self) -> AllocationStats {
+        COUNTERS.with(|counters| AllocationStats {
+            allocations: counters.allocations.get() - self.allocations,
+            allocated_bytes: counters.allocated_bytes.get() - self.allocated_bytes,
+            peak_live_bytes: (counters.peak_live_bytes.get() - self.live_bytes).max(0) as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_ignore_the_allocations_of_other_threads() {
+        let probe = AllocationProbe::start();
+        let local = vec![0u8; 1024];
+        std::thread::spawn(|| drop(vec![0u8; 1 << 20])).join().unwrap();
+        let stats = probe.stop();
+        drop(local);
+
+        assert!(stats.allocations >= 1);
+        assert!(stats.allocated_bytes >= 1024 && stats.allocated_bytes < 1 << 20, "{:?}", stats);
+        assert!(stats.peak_live_bytes >= 1024 && stats.peak_live_bytes < 1 << 20, "{:?}", stats);
+    }
+}
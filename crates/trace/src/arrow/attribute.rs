@@ -1,38 +1,42 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use arrow::array::{
-    ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, PrimitiveArray, PrimitiveBuilder, StringBuilder, StringDictionaryBuilder, UInt64Builder,
-};
-use arrow::datatypes::{ArrowDictionaryKeyType, ArrowPrimitiveType, DataType, Field, UInt16Type, UInt32Type, UInt8Type};
+use arrow::array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, PrimitiveArray, PrimitiveBuilder, UInt64Builder};
+use arrow::datatypes::{ArrowPrimitiveType, Field};
+use itertools::Itertools;
 use serde_json::{Number, Value};
 use twox_hash::RandomXxHashBuilder64;
 
 use common::{Attributes, Span};
 
+use crate::arrow::cardinality::Cardinality;
 use crate::arrow::schema::{FieldInfo, FieldType};
-use crate::arrow::{DataColumn, EntitySchema, u64_nullable_field, i64_nullable_field, f64_nullable_field, bool_nullable_field, string_nullable_field};
+use crate::arrow::{
+    array_field, bool_nullable_field, f64_nullable_field, i64_nullable_field, parsed_string_field, string_array, string_column_type, string_nullable_field,
+    u64_nullable_field, DataColumn, EncodingOptions,
+};
 
-pub fn infer_span_attribute_schema(spans: &[Span]) -> HashMap<String, FieldInfo, RandomXxHashBuilder64> {
+pub fn infer_span_attribute_schema(spans: &[Span], options: &EncodingOptions) -> HashMap<String, FieldInfo, RandomXxHashBuilder64> {
     let mut schema: HashMap<String, FieldInfo, RandomXxHashBuilder64> = Default::default();
 
     for span in spans {
         if let Some(attributes) = &span.attributes {
-            infer_attribute_types(attributes, &mut schema);
+            infer_attribute_types(attributes, spans.len(), options, &mut schema);
         }
     }
 
     schema
 }
 
-pub fn infer_event_attribute_schema(spans: &[Span]) -> (usize, HashMap<String, FieldInfo, RandomXxHashBuilder64>) {
+pub fn infer_event_attribute_schema(spans: &[Span], options: &EncodingOptions) -> (usize, HashMap<String, FieldInfo, RandomXxHashBuilder64>) {
     let mut attribute_types: HashMap<String, FieldInfo, RandomXxHashBuilder64> = Default::default();
+    let row_count = spans.iter().map(|span| span.events.as_ref().map_or(0, |events| events.len())).sum();
     let mut event_count = 0;
 
     for span in spans {
         if let Some(events) = &span.events {
             for event in events {
-                infer_attribute_types(&event.attributes, &mut attribute_types);
+                infer_attribute_types(&event.attributes, row_count, options, &mut attribute_types);
                 event_count += 1;
             }
         }
@@ -41,14 +45,15 @@ pub fn infer_event_attribute_schema(spans: &[Span]) -> (usize, HashMap<String, F
     (event_count, attribute_types)
 }
 
-pub fn infer_link_attribute_schema(spans: &[Span]) -> (usize, HashMap<String, FieldInfo, RandomXxHashBuilder64>) {
+pub fn infer_link_attribute_schema(spans: &[Span], options: &EncodingOptions) -> (usize, HashMap<String, FieldInfo, RandomXxHashBuilder64>) {
     let mut attribute_types: HashMap<String, FieldInfo, RandomXxHashBuilder64> = Default::default();
+    let row_count = spans.iter().map(|span| span.links.as_ref().map_or(0, |links| links.len())).sum();
     let mut link_count = 0;
 
     for span in spans {
         if let Some(links) = &span.links {
             for link in links {
-                infer_attribute_types(&link.attributes, &mut attribute_types);
+                infer_attribute_types(&link.attributes, row_count, options, &mut attribute_types);
                 link_count += 1;
             }
         }
@@ -57,7 +62,13 @@ pub fn infer_link_attribute_schema(spans: &[Span]) -> (usize, HashMap<String, Fi
     (link_count, attribute_types)
 }
 
-pub fn infer_attribute_types(attributes: &Attributes, attribute_types: &mut HashMap<String, FieldInfo, RandomXxHashBuilder64>) {
+// `row_count` is the number of spans, events or links the attributes belong to (see `Cardinality`).
+pub fn infer_attribute_types(
+    attributes: &Attributes,
+    row_count: usize,
+    options: &EncodingOptions,
+    attribute_types: &mut HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+) {
     for kv in attributes {
         match kv.1 {
             Value::Null | Value::Array(_) | Value::Object(_) => {}
@@ -73,7 +84,7 @@ pub fn infer_attribute_types(attributes: &Attributes, attribute_types: &mut Hash
                     .or_insert_with(|| FieldInfo {
                         non_null_count: 1,
                         field_type: FieldType::Bool,
-                        dictionary_values: Default::default(),
+                        cardinality: Cardinality::new(row_count, options),
                     });
             }
             Value::Number(number) => {
@@ -98,37 +109,37 @@ pub fn infer_attribute_types(attributes: &Attributes, attribute_types: &mut Hash
                             FieldInfo {
                                 non_null_count: 1,
                                 field_type: FieldType::U64,
-                                dictionary_values: Default::default(),
+                                cardinality: Cardinality::new(row_count, options),
                             }
                         } else if number.is_i64() {
                             FieldInfo {
                                 non_null_count: 1,
                                 field_type: FieldType::I64,
-                                dictionary_values: Default::default(),
+                                cardinality: Cardinality::new(row_count, options),
                             }
                         } else {
                             FieldInfo {
                                 non_null_count: 1,
                                 field_type: FieldType::F64,
-                                dictionary_values: Default::default(),
+                                cardinality: Cardinality::new(row_count, options),
                             }
                         }
                     });
             }
-            Value::String(_) => {
+            Value::String(value) => {
                 attribute_types
                     .entry(kv.0.clone())
                     .and_modify(|field_info| {
-                        field_info.dictionary_values.insert(kv.1.as_str().unwrap_or("").to_string());
+                        field_info.cardinality.insert(value);
                         field_info.non_null_count += 1;
                     })
                     .or_insert_with(|| {
-                        let mut cardinality = HashSet::new();
-                        cardinality.insert(kv.1.as_str().unwrap_or("").to_string());
+                        let mut cardinality = Cardinality::new(row_count, options);
+                        cardinality.insert(value);
                         FieldInfo {
                             non_null_count: 1,
                             field_type: FieldType::String,
-                            dictionary_values: cardinality,
+                            cardinality,
                         }
                     });
             }
@@ -162,177 +173,82 @@ where
     builder.finish()
 }
 
-pub fn add_attribute_columns(attributes: Vec<Option<&Attributes>>, schema: &EntitySchema, columns: &mut Vec<ArrayRef>) {
+// Builds the attribute columns of the row-oriented encoder, in the order of the attribute names. The string columns
+// use the same dictionary heuristic as the column-oriented encoder.
+pub fn add_attribute_columns(
+    attributes: Vec<Option<&Attributes>>,
+    attribute_types: &HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+    options: &EncodingOptions,
+    fields: &mut Vec<Field>,
+    columns: &mut Vec<ArrayRef>,
+) {
     let row_count = attributes.len();
 
-    for attribute in &schema.attribute_fields {
-        match attribute.1.field_type {
+    for (name, field_info) in attribute_types.iter().sorted_by(|(name, _), (other_name, _)| name.cmp(other_name)) {
+        let values = attributes.iter().map(|attrs| attrs.and_then(|attributes| attributes.get(name)));
+        let field_name = format!("attributes_{}", name);
+        let array: ArrayRef = match field_info.field_type {
             FieldType::U64 => {
                 let mut builder = UInt64Builder::new(row_count);
-                columns.push(Arc::new(build_primitive_array(attribute.0, &attributes, &mut builder, |number| {
-                    number.as_u64()
-                })));
+                Arc::new(build_primitive_array(name, &attributes, &mut builder, |number| number.as_u64()))
             }
             FieldType::I64 => {
                 let mut builder = Int64Builder::new(row_count);
-                columns.push(Arc::new(build_primitive_array(attribute.0, &attributes, &mut builder, |number| {
-                    number.as_i64()
-                })));
+                Arc::new(build_primitive_array(name, &attributes, &mut builder, |number| number.as_i64()))
             }
             FieldType::F64 => {
                 let mut builder = Float64Builder::new(row_count);
-                columns.push(Arc::new(build_primitive_array(attribute.0, &attributes, &mut builder, |number| {
-                    number.as_f64()
-                })));
+                Arc::new(build_primitive_array(name, &attributes, &mut builder, |number| number.as_f64()))
             }
             FieldType::String => {
-                if attribute.1.is_dictionary() {
-                    let min_num_bits = min_num_bits_to_represent(attribute.1.dictionary_values.len());
-                    if min_num_bits <= 8 {
-                        let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt8Type>::new(row_count), StringBuilder::new(row_count));
-                        build_dictionary(&attributes, attribute, &mut builder);
-                        columns.push(Arc::new(builder.finish()));
-                    } else if min_num_bits <= 16 {
-                        let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt16Type>::new(row_count), StringBuilder::new(row_count));
-                        build_dictionary(&attributes, attribute, &mut builder);
-                        columns.push(Arc::new(builder.finish()));
-                    } else {
-                        let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt32Type>::new(row_count), StringBuilder::new(row_count));
-                        build_dictionary(&attributes, attribute, &mut builder);
-
-                        let array = builder.finish();
-                        columns.push(Arc::new(array));
-                    };
-                } else {
-                    let mut builder = StringBuilder::new(row_count);
-                    attributes.iter().for_each(|attrs| match attrs {
-                        None => builder.append_null().unwrap(),
-                        Some(attributes) => match attributes.get(attribute.0) {
-                            None => builder.append_null().unwrap(),
-                            Some(value) => {
-                                if let Value::String(string) = value {
-                                    builder.append_value(string.clone()).unwrap();
-                                } else {
-                                    builder.append_null().unwrap();
-                                }
-                            }
-                        },
-                    });
-                    let array = builder.finish();
-                    columns.push(Arc::new(array));
+                let values = values.map(|value| value.and_then(|value| value.as_str()));
+                if options.numeric_strings && parsed_string_field(&field_name, values.clone(), fields, columns) {
+                    continue;
                 }
+                let data_type = string_column_type(field_info.cardinality.estimate(), field_info.non_null_count, options);
+                string_array(values, row_count, &data_type)
             }
             FieldType::Bool => {
                 let mut builder = BooleanBuilder::new(row_count);
-                attributes.iter().for_each(|attrs| match attrs {
+                values.for_each(|value| match value.and_then(|value| value.as_bool()) {
                     None => builder.append_null().unwrap(),
-                    Some(attributes) => match attributes.get(attribute.0) {
-                        None => builder.append_null().unwrap(),
-                        Some(value) => {
-                            if let Value::Bool(bool) = value {
-                                builder.append_value(*bool).unwrap();
-                            } else {
-                                builder.append_null().unwrap();
-                            }
-                        }
-                    },
+                    Some(value) => builder.append_value(value).unwrap(),
                 });
-                let array = builder.finish();
-                columns.push(Arc::new(array));
+                Arc::new(builder.finish())
             }
-        }
+        };
+        array_field(&field_name, array, fields, columns);
     }
 }
 
-pub fn attribute_fields(prefix: &str, attributes_column: &HashMap<String, DataColumn>, fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
-    attributes_column.iter().for_each(|(name, data_column)| match data_column {
-        DataColumn::U64Column { values, .. } => {
-            u64_nullable_field(&format!("{}{}", prefix, name), &values, fields, columns);
-        }
-        DataColumn::I64Column { values, .. } => {
-            i64_nullable_field(&format!("{}{}", prefix, name), &values, fields, columns);
-        }
-        DataColumn::F64Column { values, .. } => {
-            f64_nullable_field(&format!("{}{}", prefix, name), &values, fields, columns);
-        }
-        DataColumn::StringColumn { values, .. } => {
-            string_nullable_field(&format!("{}{}", prefix, name), &values, fields, columns);
-        }
-        DataColumn::BoolColumn { values, .. } => {
-            bool_nullable_field(&format!("{}{}", prefix, name), &values, fields, columns);
-        }
-    });
-}
-
-fn build_dictionary<K>(attributes: &[Option<&Attributes>], attribute: (&String, &FieldInfo), builder: &mut StringDictionaryBuilder<K>)
-where
-    K: ArrowDictionaryKeyType,
-{
-    attributes.iter().for_each(|attrs| match attrs {
-        None => builder.append_null().unwrap(),
-        Some(attributes) => match attributes.get(attribute.0) {
-            None => builder.append_null().unwrap(),
-            Some(value) => {
-                if let Value::String(string) = value {
-                    builder.append(string.clone()).unwrap();
-                } else {
-                    builder.append_null().unwrap();
-                }
-            }
-        },
-    });
-}
-
-pub fn add_attribute_fields(attribute_types: &HashMap<String, FieldInfo, RandomXxHashBuilder64>, fields: &mut Vec<Field>) {
-    for attribute_info in attribute_types.iter() {
-        match attribute_info.1.field_type {
-            FieldType::U64 => {
-                fields.push(Field::new(&format!("attributes_{}", attribute_info.0), DataType::UInt64, true));
-            }
-            FieldType::I64 => {
-                fields.push(Field::new(&format!("attributes_{}", attribute_info.0), DataType::Int64, true));
-            }
-            FieldType::F64 => {
-                fields.push(Field::new(&format!("attributes_{}", attribute_info.0), DataType::Float64, true));
-            }
-            FieldType::String => {
-                if attribute_info.1.is_dictionary() {
-                    let min_num_bits = min_num_bits_to_represent(attribute_info.1.dictionary_values.len());
-                    if min_num_bits <= 8 {
-                        fields.push(Field::new(
-                            &format!("attributes_{}", attribute_info.0),
-                            DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8)),
-                            true,
-                        ));
-                    } else if min_num_bits <= 16 {
-                        fields.push(Field::new(
-                            &format!("attributes_{}", attribute_info.0),
-                            DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8)),
-                            true,
-                        ));
-                    } else {
-                        fields.push(Field::new(
-                            &format!("attributes_{}", attribute_info.0),
-                            DataType::Dictionary(Box::new(DataType::UInt32), Box::new(DataType::Utf8)),
-                            true,
-                        ));
-                    };
-                } else {
-                    fields.push(Field::new(&format!("attributes_{}", attribute_info.0), DataType::Utf8, true));
+pub fn attribute_fields(
+    prefix: &str,
+    attributes_column: &HashMap<String, DataColumn>,
+    options: &EncodingOptions,
+    fields: &mut Vec<Field>,
+    columns: &mut Vec<ArrayRef>,
+) {
+    attributes_column
+        .iter()
+        .sorted_by(|(name, _), (other_name, _)| name.cmp(other_name))
+        .for_each(|(name, data_column)| match data_column {
+            DataColumn::U64Column { values, .. } => {
+                u64_nullable_field(&format!("{}{}", prefix, name), values, fields, columns);
+            }
+            DataColumn::I64Column { values, .. } => {
+                i64_nullable_field(&format!("{}{}", prefix, name), values, fields, columns);
+            }
+            DataColumn::F64Column { values, .. } => {
+                f64_nullable_field(&format!("{}{}", prefix, name), values, fields, columns);
+            }
+            DataColumn::StringColumn { values, .. } => {
+                let field_name = format!("{}{}", prefix, name);
+                if !(options.numeric_strings && parsed_string_field(&field_name, values.iter().map(|value| value.as_deref()), fields, columns)) {
+                    string_nullable_field(&field_name, values, options, fields, columns);
                 }
             }
-            FieldType::Bool => {
-                fields.push(Field::new(&format!("attributes_{}", attribute_info.0), DataType::Boolean, true));
+            DataColumn::BoolColumn { values, .. } => {
+                bool_nullable_field(&format!("{}{}", prefix, name), values, fields, columns);
             }
-        }
-    }
-}
-
-const fn num_bits<T>() -> usize {
-    std::mem::size_of::<T>() * 8
-}
-
-fn min_num_bits_to_represent(x: usize) -> u32 {
-    assert!(x > 0);
-    num_bits::<usize>() as u32 - x.leading_zeros()
+        });
 }
@@ -0,0 +1,557 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use arrow::error::ArrowError;
+use bytes::BytesMut;
+use prost::Message;
+use serde_json::Value;
+
+use common::{Attributes, Event, Link, Span, Status};
+use oltp::opentelemetry::proto::events::v1::{InstrumentationLibraryEvents, ResourceEvents};
+
+use crate::allocator::AllocationProbe;
+use crate::arrow::event::serialize_events_from_column_oriented_data_source;
+use crate::arrow::link::serialize_links_from_column_oriented_data_source;
+use crate::arrow::pool::BufferPool;
+use crate::arrow::span::serialize_spans_from_column_oriented_data_source;
+use crate::arrow::statistics::{BatchStatistics, ColumnsStatistics};
+use crate::arrow::{ArrowTraceEncoder, DataColumn, DataColumns, EncodingOptions, EventDataColumns, LinkDataColumns, SpanDataColumns};
+use crate::BenchmarkResult;
+
+// Incremental version of the column-oriented encoder for SDK exporters. Spans, events and links are appended one by one
+// as they end, their strings are moved into the columns instead of being cloned (borrowed strings, and the strings of
+// the spans appended by reference, stay borrowed until the flush), and attribute columns are created on the fly with
+// nulls for the rows appended before. `flush` encodes the buffered rows and resets the builder.
+pub struct ArrowSpanBatchBuilder<'a> {
+    options: EncodingOptions,
+    data_columns: DataColumns<'a>,
+}
+
+impl<'a> ArrowSpanBatchBuilder<'a> {
+    pub fn new(options: EncodingOptions) -> Self {
+        Self {
+            options,
+            data_columns: empty_data_columns(),
+        }
+    }
+
+    pub fn span_count(&self) -> usize {
+        self.data_columns.spans.trace_id_column.len()
+    }
+
+    // Appends a span outliving the builder with its events and links, its strings (attribute values included) stay
+    // borrowed. Returns the row of the span, i.e. the span reference to use with `append_event_ref` and `append_link_ref`.
+    pub fn append_span_ref(&mut self, span: &'a Span<'_>) -> u32 {
+        let borrowed_span = Span {
+            trace_id: Cow::Borrowed(&*span.trace_id),
+            span_id: Cow::Borrowed(&*span.span_id),
+            trace_state: span.trace_state.as_deref().map(Cow::Borrowed),
+            parent_span_id: span.parent_span_id.as_deref().map(Cow::Borrowed),
+            name: Cow::Borrowed(&*span.name),
+            kind: span.kind,
+            start_time_unix_nano: span.start_time_unix_nano,
+            end_time_unix_nano: span.end_time_unix_nano,
+            attributes: None,
+            dropped_attributes_count: span.dropped_attributes_count,
+            events: None,
+            dropped_events_count: span.dropped_events_count,
+            links: None,
+            dropped_links_count: span.dropped_links_count,
+            status: span.status.as_ref().map(|status| Status {
+                code: status.code,
+                message: status.message.as_deref().map(Cow::Borrowed),
+            }),
+        };
+        let row = self.append_span_row(borrowed_span, borrowed_attributes(span.attributes.as_ref()));
+
+        for event in span.events.iter().flatten() {
+            self.append_event_ref(row, event);
+        }
+        for link in span.links.iter().flatten() {
+            self.append_link_ref(row, link);
+        }
+
+        row
+    }
+
+    pub fn append_event_ref(&mut self, span: u32, event: &'a Event<'_>) {
+        let borrowed_event = Event {
+            time_unix_nano: event.time_unix_nano,
+            name: Cow::Borrowed(&*event.name),
+            attributes: Attributes::new(),
+            dropped_attributes_count: event.dropped_attributes_count,
+        };
+        self.append_event_row(span, borrowed_event, borrowed_attributes(Some(&event.attributes)));
+    }
+
+    pub fn append_link_ref(&mut self, span: u32, link: &'a Link<'_>) {
+        let borrowed_link = Link {
+            trace_id: Cow::Borrowed(&*link.trace_id),
+            span_id: Cow::Borrowed(&*link.span_id),
+            trace_state: link.trace_state.as_deref().map(Cow::Borrowed),
+            attributes: Attributes::new(),
+            dropped_attributes_count: link.dropped_attributes_count,
+        };
+        self.append_link_row(span, borrowed_link, borrowed_attributes(Some(&link.attributes)));
+    }
+
+    // The attributes, events and links of the span are ignored.
+    fn append_span_row(&mut self, span: Span<'a>, attributes: impl Iterator<Item = AttributeEntry<'a>>) -> u32 {
+        let row = self.span_count();
+        let spans = &mut self.data_columns.spans;
+
+        spans.trace_id_column.push(span.trace_id);
+        spans.span_id_column.push(span.span_id);
+        spans.trace_state_column.push(span.trace_state);
+        spans.parent_span_id_column.push(span.parent_span_id);
+        spans.name_column.push(span.name);
+        spans.kind_column.push(span.kind);
+        spans.start_time_unix_nano_column.push(span.start_time_unix_nano);
+        spans.end_time_unix_nano_column.push(span.end_time_unix_nano);
+        append_attributes(row, attributes, &mut spans.attributes_column);
+        spans.dropped_attrs_count_column.push(span.dropped_attributes_count);
+        spans.dropped_events_count_column.push(span.dropped_events_count);
+        spans.dropped_links_count_column.push(span.dropped_links_count);
+        let (status_code, status_message) = match span.status {
+            Some(status) => (status.code.map(|code| code as u8), status.message),
+            None => (None, None),
+        };
+        spans.status_code_column.push(status_code);
+        spans.status_message_column.push(status_message);
+
+        row as u32
+    }
+
+    // The attributes of the event are ignored.
+    fn append_event_row(&mut self, span: u32, event: Event<'a>, attributes: impl Iterator<Item = AttributeEntry<'a>>) {
+        let events = &mut self.data_columns.events;
+        let row = events.id_column.len();
+
+        events.id_column.push(span);
+        events.time_unix_nano_column.push(event.time_unix_nano);
+        events.name_column.push(event.name);
+        append_attributes(row, attributes, &mut events.attributes_column);
+        events.dropped_attributes_count_column.push(event.dropped_attributes_count);
+    }
+
+    // The attributes of the link are ignored.
+    fn append_link_row(&mut self, span: u32, link: Link<'a>, attributes: impl Iterator<Item = AttributeEntry<'a>>) {
+        let links = &mut self.data_columns.links;
+        let row = links.id_column.len();
+
+        links.id_column.push(span);
+        links.trace_id_column.push(link.trace_id);
+        links.span_id_column.push(link.span_id);
+        links.trace_state_column.push(link.trace_state);
+        append_attributes(row, attributes, &mut links.attributes_column);
+        links.dropped_attributes_count_column.push(link.dropped_attributes_count);
+    }
+
+    // Encodes the buffered spans, events and links (same schemas as the column-oriented encoder) and resets the builder.
+    pub fn flush(&mut self) -> Result<ResourceEvents, ArrowError> {
+        let data_columns = std::mem::replace(&mut self.data_columns, empty_data_columns());
+        let mut stats = ColumnsStatistics::new(false);
+
+        let mut buf = BytesMut::new();
+
+        let events_buf = serialize_events_from_column_oriented_data_source(&mut stats, &data_columns, &self.options, &mut buf)?;
+        let links_buf = serialize_links_from_column_oriented_data_source(&mut stats, &data_columns, &self.options, &mut buf)?;
+        let spans_buf = serialize_spans_from_column_oriented_data_source(&mut stats, &data_columns, &self.options, &mut buf)?;
+
+        Ok(ResourceEvents {
+            resource: None,
+            instrumentation_library_events: vec![InstrumentationLibraryEvents {
+                instrumentation_library: None,
+                spans: spans_buf,
+                events: events_buf,
+                links: links_buf,
+            }],
+            schema_url: "".to_string(),
+        })
+    }
+}
+
+// Appends of owned spans, events and links, for the SDK exporters (the benchmarks append the spans by reference).
+#[allow(dead_code)]
+impl<'a> ArrowSpanBatchBuilder<'a> {
+    // Appends a span with its events and links. Returns the row of the span, i.e. the span reference to use with
+    // `append_event` and `append_link`.
+    pub fn append_span(&mut self, mut span: Span<'a>) -> u32 {
+        let attributes = span.attributes.take();
+        let events = span.events.take();
+        let links = span.links.take();
+        let row = self.append_span_row(span, owned_attributes(attributes));
+
+        for event in events.into_iter().flatten() {
+            self.append_event(row, event);
+        }
+        for link in links.into_iter().flatten() {
+            self.append_link(row, link);
+        }
+
+        row
+    }
+
+    pub fn append_event(&mut self, span: u32, mut event: Event<'a>) {
+        let attributes = std::mem::take(&mut event.attributes);
+        self.append_event_row(span, event, owned_attributes(Some(attributes)));
+    }
+
+    pub fn append_link(&mut self, span: u32, mut link: Link<'a>) {
+        let attributes = std::mem::take(&mut link.attributes);
+        self.append_link_row(span, link, owned_attributes(Some(attributes)));
+    }
+}
+
+// Benchmark encoder appending the spans one by one to an `ArrowSpanBatchBuilder` and flushing it, like an SDK exporter.
+// The spans are appended by reference (see `append_span_ref`), nothing is copied. The appends are timed as the data
+// source conversion, the flush as the buffer creation. The builder has no column statistics.
+pub struct BuilderEncoder;
+
+impl ArrowTraceEncoder for BuilderEncoder {
+    fn name(&self) -> &'static str {
+        "arrow_builder"
+    }
+
+    fn encode_with_pool(
+        &self,
+        pool: &mut BufferPool,
+        _batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let probe = AllocationProbe::start();
+        let mut builder = ArrowSpanBatchBuilder::new(*options);
+        for span in spans {
+            builder.append_span_ref(span);
+        }
+        let elapse_time = Instant::now() - start;
+        bench_result.total_data_source_conversion_ns += elapse_time.as_nanos();
+        bench_result.data_source_conversion_allocs.add(&probe.stop());
+
+        let start = Instant::now();
+        let probe = AllocationProbe::start();
+        let resource_events = builder.flush()?;
+        let elapse_time = Instant::now() - start;
+        bench_result.total_buffer_creation_ns += elapse_time.as_nanos();
+        bench_result.buffer_creation_allocs.add(&probe.stop());
+
+        let start = Instant::now();
+        let probe = AllocationProbe::start();
+        let mut buf = pool.output_buf();
+        resource_events.encode(&mut buf)?;
+        let elapse_time = Instant::now() - start;
+        bench_result.total_buffer_serialization_ns += elapse_time.as_nanos();
+        bench_result.buffer_serialization_allocs.add(&probe.stop());
+        pool.record_sizes(0, buf.len());
+
+        Ok(buf)
+    }
+}
+
+fn empty_data_columns<'a>() -> DataColumns<'a> {
+    DataColumns {
+        spans: SpanDataColumns::default(),
+        events: EventDataColumns::default(),
+        links: LinkDataColumns::default(),
+    }
+}
+
+// Attribute of an appended span, event or link, owned or borrowed from a span outliving the builder.
+type AttributeEntry<'a> = (Cow<'a, str>, Cow<'a, Value>);
+
+fn owned_attributes<'a>(attributes: Option<Attributes>) -> impl Iterator<Item = AttributeEntry<'a>> {
+    attributes.into_iter().flatten().map(|(key, value)| (Cow::Owned(key), Cow::Owned(value)))
+}
+
+fn borrowed_attributes(attributes: Option<&Attributes>) -> impl Iterator<Item = AttributeEntry<'_>> {
+    attributes
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| (Cow::Borrowed(key.as_str()), Cow::Borrowed(value)))
+}
+
+// Appends the attributes of the row `row`. A new attribute column is backfilled with nulls for the previous rows, and
+// the columns without value for this row get a null.
+fn append_attributes<'a>(row: usize, attributes: impl Iterator<Item = AttributeEntry<'a>>, attributes_column: &mut HashMap<String, DataColumn<'a>>) {
+    for (key, value) in attributes {
+        if let Value::Null | Value::Array(_) | Value::Object(_) = *value {
+            continue;
+        }
+        match attributes_column.get_mut(&*key) {
+            Some(column) => push_value(column, value),
+            None => {
+                let mut column = null_column(&value, row);
+                push_value(&mut column, value);
+                attributes_column.insert(key.into_owned(), column);
+            }
+        }
+    }
+
+    for column in attributes_column.values_mut() {
+        if column_len(column) == row {
+            push_null(column);
+        }
+    }
+}
+
+fn null_column<'a>(value: &Value, row_count: usize) -> DataColumn<'a> {
+    match value {
+        Value::Number(number) if number.is_u64() => DataColumn::U64Column {
+            missing: row_count,
+            values: vec![None; row_count],
+        },
+        Value::Number(number) if number.is_i64() => DataColumn::I64Column {
+            missing: row_count,
+            values: vec![None; row_count],
+        },
+        Value::Number(_) => DataColumn::F64Column {
+            missing: row_count,
+            values: vec![None; row_count],
+        },
+        Value::Bool(_) => DataColumn::BoolColumn {
+            missing: row_count,
+            values: vec![None; row_count],
+        },
+        _ => DataColumn::StringColumn {
+            missing: row_count,
+            values: vec![None; row_count],
+        },
+    }
+}
+
+fn push_value<'a>(column: &mut DataColumn<'a>, value: Cow<'a, Value>) {
+    if !accepts(column, &value) {
+        let previous = std::mem::replace(column, DataColumn::BoolColumn { missing: 0, values: vec![] });
+        *column = promote(previous, &value);
+    }
+
+    match column {
+        DataColumn::U64Column { values, .. } => values.push(value.as_u64()),
+        DataColumn::I64Column { values, .. } => values.push(value.as_i64()),
+        DataColumn::F64Column { values, .. } => values.push(value.as_f64()),
+        DataColumn::BoolColumn { values, .. } => values.push(value.as_bool()),
+        DataColumn::StringColumn { values, .. } => values.push(Some(string_value(value))),
+    }
+}
+
+fn push_null(column: &mut DataColumn<'_>) {
+    match column {
+        DataColumn::U64Column { missing, values } => {
+            *missing += 1;
+            values.push(None);
+        }
+        DataColumn::I64Column { missing, values } => {
+            *missing += 1;
+            values.push(None);
+        }
+        DataColumn::F64Column { missing, values } => {
+            *missing += 1;
+            values.push(None);
+        }
+        DataColumn::StringColumn { missing, values } => {
+            *missing += 1;
+            values.push(None);
+        }
+        DataColumn::BoolColumn { missing, values } => {
+            *missing += 1;
+            values.push(None);
+        }
+    }
+}
+
+fn column_len(column: &DataColumn<'_>) -> usize {
+    match column {
+        DataColumn::U64Column { values, .. } => values.len(),
+        DataColumn::I64Column { values, .. } => values.len(),
+        DataColumn::F64Column { values, .. } => values.len(),
+        DataColumn::StringColumn { values, .. } => values.len(),
+        DataColumn::BoolColumn { values, .. } => values.len(),
+    }
+}
+
+fn accepts(column: &DataColumn<'_>, value: &Value) -> bool {
+    match (column, value) {
+        (DataColumn::U64Column { .. }, Value::Number(number)) => number.is_u64(),
+        (DataColumn::I64Column { .. }, Value::Number(number)) => number.as_i64().is_some(),
+        (DataColumn::F64Column { .. }, Value::Number(_)) => true,
+        (DataColumn::BoolColumn { .. }, Value::Bool(_)) => true,
+        (DataColumn::StringColumn { .. }, _) => true,
+        _ => false,
+    }
+}
+
+// Same promotions as the attribute schema inference (u64 -> i64 -> f64), any other mix is converted to strings. Like the
+// merge of batches, u64 values out of the i64 range are promoted to f64 instead of i64.
+fn promote<'a>(column: DataColumn<'a>, value: &Value) -> DataColumn<'a> {
+    fn convert<T, U>(values: Vec<Option<T>>, f: impl Fn(T) -> U) -> Vec<Option<U>> {
+        values.into_iter().map(|value| value.map(&f)).collect()
+    }
+    fn to_strings<'a, T: ToString>(values: Vec<Option<T>>) -> Vec<Option<Cow<'a, str>>> {
+        values.into_iter().map(|value| value.map(|value| Cow::Owned(value.to_string()))).collect()
+    }
+
+    match (column, value) {
+        (DataColumn::U64Column { missing, values }, Value::Number(number))
+            if number.as_i64().is_some() && values.iter().flatten().all(|v| *v <= i64::MAX as u64) =>
+        {
+            DataColumn::I64Column {
+                missing,
+                values: convert(values, |v| v as i64),
+            }
+        }
+        (DataColumn::U64Column { missing, values }, Value::Number(_)) => DataColumn::F64Column {
+            missing,
+            values: convert(values, |v| v as f64),
+        },
+        (DataColumn::I64Column { missing, values }, Value::Number(_)) => DataColumn::F64Column {
+            missing,
+            values: convert(values, |v| v as f64),
+        },
+        (DataColumn::U64Column { missing, values }, _) => DataColumn::StringColumn {
+            missing,
+            values: to_strings(values),
+        },
+        (DataColumn::I64Column { missing, values }, _) => DataColumn::StringColumn {
+            missing,
+            values: to_strings(values),
+        },
+        (DataColumn::F64Column { missing, values }, _) => DataColumn::StringColumn {
+            missing,
+            values: values
+                .into_iter()
+                .map(|value| value.map(|value| Cow::Owned(Value::from(value).to_string())))
+                .collect(),
+        },
+        (DataColumn::BoolColumn { missing, values }, _) => DataColumn::StringColumn {
+            missing,
+            values: to_strings(values),
+        },
+        (column, _) => column,
+    }
+}
+
+fn string_value(value: Cow<'_, Value>) -> Cow<'_, str> {
+    match value {
+        Cow::Borrowed(Value::String(string)) => Cow::Borrowed(string),
+        Cow::Owned(Value::String(string)) => Cow::Owned(string),
+        value => Cow::Owned(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Float64Array, Int64Array, StringArray, UInt32Array};
+    use serde_json::json;
+
+    use crate::arrow::statistics::StatisticsReporter;
+    use crate::arrow::tests::{assert_same_batches, encode, spans};
+    use crate::arrow::view::TraceBatch;
+    use crate::arrow::ColumnOrientedEncoder;
+
+    use super::*;
+
+    fn decode(resource_events: ResourceEvents) -> TraceBatch {
+        let mut buf = vec![];
+        resource_events.encode(&mut buf).unwrap();
+        let mut batches = TraceBatch::decode(buf.into()).unwrap();
+        assert_eq!(batches.len(), 1);
+        batches.remove(0)
+    }
+
+    fn span<'a>(name: &'a str, attributes: Vec<(&str, Value)>) -> Span<'a> {
+        let mut span: Span<'a> = spans(1).remove(0);
+        span.name = name.into();
+        span.attributes = Some(attributes.into_iter().map(|(key, value)| (key.to_string(), value)).collect());
+        span.events = None;
+        span.links = None;
+        span
+    }
+
+    #[test]
+    fn builder_produces_the_column_oriented_batches() {
+        let mut stats_reporter = StatisticsReporter::noop();
+        let buf = ColumnOrientedEncoder
+            .encode(
+                stats_reporter.next_batch(),
+                &spans(40),
+                &EncodingOptions::default(),
+                &mut BenchmarkResult::new(),
+            )
+            .unwrap();
+        let column_oriented = TraceBatch::decode(buf.into()).unwrap().remove(0);
+
+        let mut builder = ArrowSpanBatchBuilder::new(EncodingOptions::default());
+        for span in spans(40) {
+            builder.append_span(span);
+        }
+        assert_eq!(builder.span_count(), 40);
+        let built = decode(builder.flush().unwrap());
+        assert_eq!(builder.span_count(), 0);
+
+        assert_same_batches("span", Some(&built.spans), Some(&column_oriented.spans));
+        assert_same_batches("event", built.events.as_ref(), column_oriented.events.as_ref());
+        assert_same_batches("link", built.links.as_ref(), column_oriented.links.as_ref());
+    }
+
+    #[test]
+    fn builder_encoder_produces_the_column_oriented_batches() {
+        let built = encode(&BuilderEncoder, &spans(40));
+        let column_oriented = encode(&ColumnOrientedEncoder, &spans(40));
+
+        assert_same_batches("span", Some(&built.spans), Some(&column_oriented.spans));
+        assert_same_batches("event", built.events.as_ref(), column_oriented.events.as_ref());
+        assert_same_batches("link", built.links.as_ref(), column_oriented.links.as_ref());
+    }
+
+    #[test]
+    fn builder_backfills_and_promotes_attribute_columns() {
+        let mut builder = ArrowSpanBatchBuilder::new(EncodingOptions::default());
+        let first = builder.append_span(span("first", vec![]));
+        builder.append_span(span("second", vec![("value", json!(1))]));
+        builder.append_span(span("third", vec![("value", json!(-1)), ("label", json!("x"))]));
+        builder.append_span(span("fourth", vec![("value", json!(1.5))]));
+        builder.append_event(
+            first,
+            Event {
+                time_unix_nano: 1,
+                name: "late".into(),
+                attributes: Attributes::new(),
+                dropped_attributes_count: None,
+            },
+        );
+
+        let batch = decode(builder.flush().unwrap());
+
+        let values = batch.spans.column(batch.spans.schema().index_of("attributes_value").unwrap());
+        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(values.iter().collect::<Vec<_>>(), vec![None, Some(1.0), Some(-1.0), Some(1.5)]);
+
+        let labels = batch.spans.column(batch.spans.schema().index_of("attributes_label").unwrap());
+        let labels = labels.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), vec![None, None, Some("x"), None]);
+
+        let events = batch.events.unwrap();
+        let ids = events.column(events.schema().index_of("id").unwrap());
+        assert_eq!(ids.as_any().downcast_ref::<UInt32Array>().unwrap().values(), &[first]);
+    }
+    #[test]
+    fn builder_promotes_u64_out_of_the_i64_range_to_f64() {
+        let mut builder = ArrowSpanBatchBuilder::new(EncodingOptions::default());
+        builder.append_span(span("first", vec![("value", json!(u64::MAX)), ("small", json!(1))]));
+        builder.append_span(span("second", vec![("value", json!(-1)), ("small", json!(-1))]));
+
+        let batch = decode(builder.flush().unwrap());
+
+        let values = batch.spans.column(batch.spans.schema().index_of("attributes_value").unwrap());
+        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(values.iter().collect::<Vec<_>>(), vec![Some(u64::MAX as f64), Some(-1.0)]);
+
+        let small = batch.spans.column(batch.spans.schema().index_of("attributes_small").unwrap());
+        let small = small.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(small.iter().collect::<Vec<_>>(), vec![Some(1), Some(-1)]);
+    }
+}
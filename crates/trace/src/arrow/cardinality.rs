@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::hash::Hasher;
+
+use clap::ArgEnum;
+use itertools::Itertools;
+use serde::Serialize;
+use twox_hash::{RandomXxHashBuilder64, XxHash64};
+
+use crate::arrow::EncodingOptions;
+
+// A string column with this cardinality or more is never dictionary encoded (see `string_column_type`).
+const MAX_DICTIONARY_CARDINALITY: usize = 1 << 16;
+// 4096 registers, i.e. a standard error of 1.6%.
+const HLL_PRECISION: u32 = 12;
+// Margin added to the HyperLogLog estimate (about 6 standard errors) so the estimate is almost always an upper bound of
+// the cardinality. `string_array` falls back to a larger key type when the dictionary keys overflow anyway.
+const HLL_MARGIN: f64 = 0.1;
+
+// How the cardinality of the string columns is computed to choose between a dictionary and a plain Utf8 column:
+// - exact: every distinct value is kept,
+// - early-exit: the distinct value hashes are counted until the column can't be dictionary encoded whatever the other
+//   values are (cardinality/row count ratio above the dictionary threshold or more than 16 bits), the encoding is the
+//   same as with the exact count,
+// - hyper-log-log: constant memory estimate, rounded up. A column close to the threshold may be encoded as Utf8 instead
+//   of a dictionary.
+#[derive(ArgEnum, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CardinalityEstimator {
+    Exact,
+    EarlyExit,
+    HyperLogLog,
+}
+
+impl std::fmt::Display for CardinalityEstimator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CardinalityEstimator::Exact => "exact",
+            CardinalityEstimator::EarlyExit => "early-exit",
+            CardinalityEstimator::HyperLogLog => "hyper-log-log",
+        };
+        f.write_str(name)
+    }
+}
+
+// Cardinality of a string column being inferred, `row_count` is an upper bound of its non-null count.
+#[derive(PartialEq, Debug)]
+pub enum Cardinality {
+    Exact(HashSet<String>),
+    EarlyExit {
+        hashes: HashSet<u64, RandomXxHashBuilder64>,
+        limit: usize,
+    },
+    HyperLogLog(HyperLogLog),
+}
+
+impl Cardinality {
+    pub fn new(row_count: usize, options: &EncodingOptions) -> Self {
+        match options.cardinality_estimator {
+            CardinalityEstimator::Exact => Cardinality::Exact(HashSet::new()),
+            CardinalityEstimator::EarlyExit => Cardinality::EarlyExit {
+                hashes: Default::default(),
+                limit: early_exit_limit(row_count, options.dictionary_threshold),
+            },
+            CardinalityEstimator::HyperLogLog => Cardinality::HyperLogLog(HyperLogLog::default()),
+        }
+    }
+
+    pub fn insert(&mut self, value: &str) {
+        match self {
+            Cardinality::Exact(values) => {
+                values.insert(value.to_string());
+            }
+            Cardinality::EarlyExit { hashes, limit } => {
+                if hashes.len() < *limit {
+                    hashes.insert(hash(value));
+                }
+            }
+            Cardinality::HyperLogLog(hll) => hll.insert(hash(value)),
+        }
+    }
+
+    pub fn estimate(&self) -> usize {
+        match self {
+            Cardinality::Exact(values) => values.len(),
+            Cardinality::EarlyExit { hashes, .. } => hashes.len(),
+            Cardinality::HyperLogLog(hll) => hll.estimate(),
+        }
+    }
+}
+
+// Cardinality of the values of a string column of `row_count` rows.
+pub fn string_cardinality<'a, I>(values: I, row_count: usize, options: &EncodingOptions) -> usize
+where
+    I: Iterator<Item = &'a str>,
+{
+    match options.cardinality_estimator {
+        // the values are borrowed, no need to copy them
+        CardinalityEstimator::Exact => values.unique().count(),
+        _ => {
+            let mut cardinality = Cardinality::new(row_count, options);
+            values.for_each(|value| cardinality.insert(value));
+            cardinality.estimate()
+        }
+    }
+}
+
+// Smallest cardinality for which a column of at most `row_count` non-null values is a Utf8 column.
+fn early_exit_limit(row_count: usize, dictionary_threshold: f64) -> usize {
+    let mut limit = ((dictionary_threshold * row_count as f64).ceil().max(1.0) as usize).min(MAX_DICTIONARY_CARDINALITY);
+    // guards against the rounding of the product
+    while limit < MAX_DICTIONARY_CARDINALITY && (limit as f64 / row_count as f64) < dictionary_threshold {
+        limit += 1;
+    }
+    limit
+}
+
+fn hash(value: &str) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(value.as_bytes());
+    hasher.finish()
+}
+
+// The registers are allocated on the first insert, most attributes aren't strings.
+#[derive(PartialEq, Debug, Default)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    inserted: usize,
+}
+
+impl HyperLogLog {
+    fn insert(&mut self, hash: u64) {
+        if self.registers.is_empty() {
+            self.registers = vec![0; 1 << HLL_PRECISION];
+        }
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+        self.inserted += 1;
+    }
+
+    fn estimate(&self) -> usize {
+        if self.inserted == 0 {
+            return 0;
+        }
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|register| 2f64.powi(-(*register as i32))).sum();
+        let zeros = self.registers.iter().filter(|register| **register == 0).count();
+        let mut estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        // linear counting for small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            estimate = m * (m / zeros as f64).ln();
+        }
+        ((estimate * (1.0 + HLL_MARGIN)).ceil() as usize).min(self.inserted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arrow::cardinality::{string_cardinality, CardinalityEstimator};
+    use crate::arrow::{string_column_type, EncodingOptions};
+
+    fn options(cardinality_estimator: CardinalityEstimator) -> EncodingOptions {
+        EncodingOptions {
+            cardinality_estimator,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn estimators_choose_the_same_column_types_as_the_exact_count() {
+        for (row_count, distinct_count) in [(1000, 1), (1000, 150), (1000, 250), (100000, 300), (100000, 30000)] {
+            let values: Vec<String> = (0..row_count).map(|i| format!("value_{}", i % distinct_count)).collect();
+            let exact = string_column_type(distinct_count, row_count, &options(CardinalityEstimator::Exact));
+
+            for estimator in [CardinalityEstimator::Exact, CardinalityEstimator::EarlyExit, CardinalityEstimator::HyperLogLog] {
+                let options = options(estimator);
+                let cardinality = string_cardinality(values.iter().map(|value| value.as_str()), row_count, &options);
+                assert_eq!(
+                    string_column_type(cardinality, row_count, &options),
+                    exact,
+                    "{} rows, {} values, {}",
+                    row_count,
+                    distinct_count,
+                    estimator
+                );
+                if estimator == CardinalityEstimator::HyperLogLog {
+                    assert!(cardinality >= distinct_count);
+                }
+            }
+        }
+    }
+}
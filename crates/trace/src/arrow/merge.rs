@@ -0,0 +1,613 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use arrow::array::{new_null_array, Array, ArrayRef, BooleanArray, StringArray, UInt32Array, UInt64Array};
+use arrow::compute::{cast, concat};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use prost::Message;
+
+use oltp::opentelemetry::proto::common::v1::InstrumentationLibrary;
+use oltp::opentelemetry::proto::events::v1::{InstrumentationLibraryEvents, ResourceEvents};
+use oltp::opentelemetry::proto::resource::v1::Resource;
+
+use crate::arrow::cardinality::string_cardinality;
+use crate::arrow::statistics::ColumnsStatistics;
+use crate::arrow::view::{read_batch, AttributeColumn, TraceBatch};
+use crate::arrow::{new_field, serialize, span_kind_type, string_column_type, EncodingOptions, ORIGINAL_TYPE_KEY};
+
+// Payloads sharing the same resource, schema url and instrumentation library are merged together.
+struct MergeGroup {
+    resource: Option<Resource>,
+    schema_url: String,
+    instrumentation_library: Option<InstrumentationLibrary>,
+    batches: Vec<TraceBatch>,
+}
+
+// Merges OTLP-Arrow payloads into one `ResourceEvents` per distinct resource (and schema url), with one
+// `InstrumentationLibraryEvents` per distinct instrumentation library.
+pub fn merge_payloads(payloads: &[bytes::Bytes], options: &EncodingOptions) -> Result<Vec<ResourceEvents>, Box<dyn std::error::Error>> {
+    let mut groups: Vec<MergeGroup> = vec![];
+
+    for payload in payloads {
+        let resource_events = ResourceEvents::decode(payload.clone())?;
+
+        for library_events in resource_events.instrumentation_library_events.iter() {
+            let spans = match read_batch(&library_events.spans)? {
+                Some(spans) => spans,
+                None => continue,
+            };
+            let batch = TraceBatch {
+                spans,
+                events: read_batch(&library_events.events)?,
+                links: read_batch(&library_events.links)?,
+            };
+
+            let group = groups.iter_mut().find(|group| {
+                group.resource == resource_events.resource
+                    && group.schema_url == resource_events.schema_url
+                    && group.instrumentation_library == library_events.instrumentation_library
+            });
+            match group {
+                Some(group) => group.batches.push(batch),
+                None => groups.push(MergeGroup {
+                    resource: resource_events.resource.clone(),
+                    schema_url: resource_events.schema_url.clone(),
+                    instrumentation_library: library_events.instrumentation_library.clone(),
+                    batches: vec![batch],
+                }),
+            }
+        }
+    }
+
+    let mut merged: Vec<ResourceEvents> = vec![];
+    for group in groups {
+        let MergeGroup {
+            resource,
+            schema_url,
+            instrumentation_library,
+            batches,
+        } = group;
+        let batch = merge_trace_batches(&batches, options)?;
+        let library_events = InstrumentationLibraryEvents {
+            instrumentation_library,
+            spans: encode(Some(&batch.spans))?.into(),
+            events: encode(batch.events.as_ref())?.into(),
+            links: encode(batch.links.as_ref())?.into(),
+        };
+
+        match merged
+            .iter_mut()
+            .find(|resource_events| resource_events.resource == resource && resource_events.schema_url == schema_url)
+        {
+            Some(resource_events) => resource_events.instrumentation_library_events.push(library_events),
+            None => merged.push(ResourceEvents {
+                resource,
+                instrumentation_library_events: vec![library_events],
+                schema_url,
+            }),
+        }
+    }
+
+    Ok(merged)
+}
+
+// Concatenates the span, event and link batches. Columns are unioned by name, a column missing from a batch is filled
+// with nulls, and columns with different types are promoted (u64 -> i64 -> f64, any other mix -> string, u64 values
+// greater than i64::MAX mixed with i64 values -> f64). The field metadata is carried over and must be the same in all
+// the batches containing the column, except for numeric strings mixed with other columns, which are merged as the
+// original strings. The event and link `id` columns are rewritten to reference the span rows of the
+// merged batch (the merged span batch has no `id` column), and the dictionary encoding of the string columns is chosen
+// again from the merged values.
+pub fn merge_trace_batches(batches: &[TraceBatch], options: &EncodingOptions) -> Result<TraceBatch, ArrowError> {
+    let mut spans = vec![];
+    let mut events = vec![];
+    let mut links = vec![];
+    let mut span_offset = 0u32;
+
+    for batch in batches {
+        let span_rows = span_rows_by_id(&batch.spans);
+        spans.push(named_columns(&batch.spans).into_iter().filter(|(field, _)| field.name() != "id").collect());
+        if let Some(batch_events) = &batch.events {
+            events.push(rewrite_ids(batch_events, span_rows.as_ref(), span_offset)?);
+        }
+        if let Some(batch_links) = &batch.links {
+            links.push(rewrite_ids(batch_links, span_rows.as_ref(), span_offset)?);
+        }
+        span_offset += batch.spans.num_rows() as u32;
+    }
+
+    Ok(TraceBatch {
+        spans: concat_columns(spans, options)?.ok_or_else(|| ArrowError::InvalidArgumentError("no span to merge".into()))?,
+        events: concat_columns(events, options)?,
+        links: concat_columns(links, options)?,
+    })
+}
+
+type NamedColumns = Vec<(Field, ArrayRef)>;
+
+fn named_columns(batch: &RecordBatch) -> NamedColumns {
+    batch.schema().fields().iter().cloned().zip(batch.columns().iter().cloned()).collect()
+}
+
+// Row of every span id when the span batch has an `id` column (row-oriented encoder), otherwise ids are span rows.
+fn span_rows_by_id(spans: &RecordBatch) -> Option<HashMap<u32, u32>> {
+    let (index, _) = spans.schema().column_with_name("id")?;
+    let ids = spans.column(index).as_any().downcast_ref::<UInt32Array>()?;
+    Some(ids.values().iter().enumerate().map(|(row, id)| (*id, row as u32)).collect())
+}
+
+fn rewrite_ids(batch: &RecordBatch, span_rows: Option<&HashMap<u32, u32>>, span_offset: u32) -> Result<NamedColumns, ArrowError> {
+    let mut columns = named_columns(batch);
+    let (_, id_column) = columns
+        .iter_mut()
+        .find(|(field, _)| field.name() == "id")
+        .ok_or_else(|| ArrowError::SchemaError("missing id column".into()))?;
+    let ids = id_column
+        .as_any()
+        .downcast_ref::<UInt32Array>()
+        .ok_or_else(|| ArrowError::SchemaError("invalid id column".into()))?;
+
+    let new_ids = ids
+        .values()
+        .iter()
+        .map(|id| {
+            let row = match span_rows {
+                Some(span_rows) => span_rows
+                    .get(id)
+                    .copied()
+                    .ok_or_else(|| ArrowError::InvalidArgumentError(format!("unknown span id {}", id)))?,
+                None => *id,
+            };
+            Ok(span_offset + row)
+        })
+        .collect::<Result<Vec<u32>, ArrowError>>()?;
+    *id_column = Arc::new(UInt32Array::from(new_ids));
+
+    Ok(columns)
+}
+
+struct MergedColumn {
+    name: String,
+    data_type: DataType,
+    // Without the original type of the numeric strings.
+    metadata: Option<BTreeMap<String, String>>,
+    // A UInt64 column has values out of the Int64 range, a promotion to Int64 becomes a promotion to Float64.
+    exceeds_i64: bool,
+    // The column holds numeric strings (see `parsed_string_field`) in every batch.
+    parsed_strings: bool,
+    // The column holds numeric strings in some batches but not in all, or with types other than UInt64 and Int64. It's
+    // merged as the original strings.
+    restore_strings: bool,
+}
+
+fn concat_columns(batches: Vec<NamedColumns>, options: &EncodingOptions) -> Result<Option<RecordBatch>, ArrowError> {
+    let row_counts: Vec<usize> = batches
+        .iter()
+        .map(|columns| columns.first().map(|(_, column)| column.len()).unwrap_or(0))
+        .collect();
+    if row_counts.iter().sum::<usize>() == 0 {
+        return Ok(None);
+    }
+
+    // Union of the columns in order of appearance, with the promoted type and the metadata of each column.
+    let mut merged_columns: Vec<MergedColumn> = vec![];
+    for (field, column) in batches.iter().flatten() {
+        let data_type = value_type(column.data_type());
+        let exceeds_i64 = data_type == DataType::UInt64 && exceeds_i64(column);
+        let parsed_strings = parsed_strings(field);
+        match merged_columns.iter_mut().find(|merged_column| merged_column.name == *field.name()) {
+            Some(merged_column) => {
+                if merged_column.metadata != other_metadata(field) {
+                    return Err(ArrowError::SchemaError(format!("conflicting metadata for column '{}'", field.name())));
+                }
+                let integers = matches!(
+                    (&merged_column.data_type, &data_type),
+                    (DataType::UInt64 | DataType::Int64, DataType::UInt64 | DataType::Int64)
+                );
+                if merged_column.parsed_strings != parsed_strings || (parsed_strings && merged_column.data_type != data_type && !integers) {
+                    merged_column.restore_strings = true;
+                }
+                merged_column.parsed_strings &= parsed_strings;
+                merged_column.data_type = promote(&merged_column.data_type, &data_type);
+                merged_column.exceeds_i64 |= exceeds_i64;
+            }
+            None => merged_columns.push(MergedColumn {
+                name: field.name().clone(),
+                data_type,
+                metadata: other_metadata(field),
+                exceeds_i64,
+                parsed_strings,
+                restore_strings: false,
+            }),
+        }
+    }
+
+    let mut fields = vec![];
+    let mut columns = vec![];
+    for mut merged_column in merged_columns {
+        if merged_column.parsed_strings && merged_column.exceeds_i64 && merged_column.data_type == DataType::Int64 {
+            merged_column.restore_strings = true;
+        }
+        let data_type = match &merged_column.data_type {
+            _ if merged_column.restore_strings => DataType::Utf8,
+            DataType::Int64 if merged_column.exceeds_i64 => DataType::Float64,
+            data_type => data_type.clone(),
+        };
+        let arrays = batches
+            .iter()
+            .zip(row_counts.iter())
+            .map(|(batch, row_count)| match batch.iter().find(|(field, _)| *field.name() == merged_column.name) {
+                Some((field, column)) if merged_column.restore_strings && parsed_strings(field) => Ok(original_strings(column)),
+                Some((_, column)) => cast_column(column, &data_type),
+                None => Ok(new_null_array(&data_type, *row_count)),
+            })
+            .collect::<Result<Vec<ArrayRef>, ArrowError>>()?;
+        let array = concat(&arrays.iter().map(|array| array.as_ref()).collect::<Vec<&dyn Array>>())?;
+        let array = match data_type {
+            // only the spans have a `kind` column, its encoding doesn't depend on the cardinality (see `span_kind_field`)
+            DataType::Utf8 if merged_column.name == "kind" => cast(&array, &span_kind_type())?,
+            DataType::Utf8 => dictionary_encode(array, options)?,
+            _ => array,
+        };
+
+        let mut metadata = merged_column.metadata;
+        if merged_column.parsed_strings && !merged_column.restore_strings {
+            metadata
+                .get_or_insert_with(BTreeMap::new)
+                .insert(ORIGINAL_TYPE_KEY.to_string(), "string".to_string());
+        }
+        let mut field = new_field(&merged_column.name, array.data_type().clone(), array.null_count() > 0, &fields);
+        field.set_metadata(metadata);
+        fields.push(field);
+        columns.push(array);
+    }
+
+    Ok(Some(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?))
+}
+
+// Dictionary columns are merged on their values, the dictionary encoding is decided again after the concatenation.
+fn value_type(data_type: &DataType) -> DataType {
+    match data_type {
+        DataType::Dictionary(_, value_type) => value_type.as_ref().clone(),
+        data_type => data_type.clone(),
+    }
+}
+
+fn parsed_strings(field: &Field) -> bool {
+    field
+        .metadata()
+        .as_ref()
+        .and_then(|metadata| metadata.get(ORIGINAL_TYPE_KEY))
+        .map(String::as_str)
+        == Some("string")
+}
+
+fn other_metadata(field: &Field) -> Option<BTreeMap<String, String>> {
+    let mut metadata = field.metadata().clone()?;
+    metadata.remove(ORIGINAL_TYPE_KEY);
+    Some(metadata).filter(|metadata| !metadata.is_empty())
+}
+
+// Strings the values of a numeric string column were parsed from, formatted like the decoder does.
+fn original_strings(column: &ArrayRef) -> ArrayRef {
+    let strings: StringArray = match AttributeColumn::try_new(column) {
+        Some(attribute_column) => (0..column.len())
+            .map(|row| attribute_column.value(row).map(|value| value.to_string()))
+            .collect(),
+        None => (0..column.len()).map(|_| None::<String>).collect(),
+    };
+    Arc::new(strings)
+}
+
+fn promote(data_type: &DataType, other: &DataType) -> DataType {
+    match (data_type, other) {
+        (data_type, other) if data_type == other => data_type.clone(),
+        (DataType::UInt64, DataType::Int64) | (DataType::Int64, DataType::UInt64) => DataType::Int64,
+        (DataType::UInt64 | DataType::Int64 | DataType::Float64, DataType::UInt64 | DataType::Int64 | DataType::Float64) => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+fn exceeds_i64(column: &ArrayRef) -> bool {
+    column
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .is_some_and(|column| column.iter().flatten().any(|value| value > i64::MAX as u64))
+}
+
+fn cast_column(column: &ArrayRef, data_type: &DataType) -> Result<ArrayRef, ArrowError> {
+    let column = match column.data_type() {
+        DataType::Dictionary(_, _) => cast(column, &value_type(column.data_type()))?,
+        _ => column.clone(),
+    };
+
+    if column.data_type() == data_type {
+        return Ok(column);
+    }
+
+    match (column.data_type(), data_type) {
+        // Same representation as the JSON values ("true"/"false") instead of the "1"/"0" of the cast kernel.
+        (DataType::Boolean, DataType::Utf8) => {
+            let column = column.as_any().downcast_ref::<BooleanArray>().expect("boolean column");
+            Ok(Arc::new(
+                column.iter().map(|value| value.map(|value| value.to_string())).collect::<StringArray>(),
+            ))
+        }
+        (_, to) => cast(&column, to),
+    }
+}
+
+// Same heuristic as the encoders (see `string_column_type`).
+fn dictionary_encode(array: ArrayRef, options: &EncodingOptions) -> Result<ArrayRef, ArrowError> {
+    let strings = array.as_any().downcast_ref::<StringArray>().expect("string column");
+    let cardinality = string_cardinality(strings.iter().flatten(), strings.len(), options);
+    let non_null_count = strings.len() - strings.null_count();
+
+    match string_column_type(cardinality, non_null_count, options) {
+        DataType::Utf8 => Ok(array),
+        data_type => cast(&array, &data_type),
+    }
+}
+
+fn encode(batch: Option<&RecordBatch>) -> Result<Vec<u8>, ArrowError> {
+    match batch {
+        Some(batch) => serialize(&mut ColumnsStatistics::new(false), batch.schema().fields().clone(), batch.columns().to_vec()),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use common::{Attributes, Event, Link, Span, SpanKind};
+
+    use crate::arrow::statistics::StatisticsReporter;
+    use crate::arrow::{serialize_column_oriented_data_source, ArrowTraceEncoder, RowOrientedEncoder};
+    use crate::BenchmarkResult;
+
+    use super::*;
+
+    fn span<'a>(name: &'a str, attributes: Vec<(&str, Value)>) -> Span<'a> {
+        Span {
+            trace_id: "trace".into(),
+            span_id: name.into(),
+            trace_state: None,
+            parent_span_id: None,
+            name: name.into(),
+            kind: Some(SpanKind::Internal),
+            start_time_unix_nano: 1,
+            end_time_unix_nano: Some(2),
+            attributes: Some(attributes.into_iter().map(|(key, value)| (key.to_string(), value)).collect()),
+            dropped_attributes_count: None,
+            events: None,
+            dropped_events_count: None,
+            links: None,
+            dropped_links_count: None,
+            status: None,
+        }
+    }
+
+    fn event(name: &str) -> Event<'_> {
+        Event {
+            time_unix_nano: 1,
+            name: name.into(),
+            attributes: Attributes::default(),
+            dropped_attributes_count: None,
+        }
+    }
+
+    fn link(span_id: &str) -> Link<'_> {
+        Link {
+            trace_id: "trace".into(),
+            span_id: span_id.into(),
+            trace_state: None,
+            attributes: Attributes::default(),
+            dropped_attributes_count: None,
+        }
+    }
+
+    fn column_oriented_payload(spans: &[Span]) -> bytes::Bytes {
+        column_oriented_payload_with_options(spans, &EncodingOptions::default())
+    }
+
+    fn column_oriented_payload_with_options(spans: &[Span], options: &EncodingOptions) -> bytes::Bytes {
+        let mut stats_reporter = StatisticsReporter::noop();
+        serialize_column_oriented_data_source(stats_reporter.next_batch(), spans, options, &mut BenchmarkResult::new())
+            .unwrap()
+            .into()
+    }
+
+    fn row_oriented_payload(spans: &[Span]) -> bytes::Bytes {
+        let mut stats_reporter = StatisticsReporter::noop();
+        RowOrientedEncoder
+            .encode(stats_reporter.next_batch(), spans, &EncodingOptions::default(), &mut BenchmarkResult::new())
+            .unwrap()
+            .into()
+    }
+
+    fn merge(payloads: &[bytes::Bytes]) -> TraceBatch {
+        let merged = merge_payloads(payloads, &EncodingOptions::default()).unwrap();
+        assert_eq!(merged.len(), 1);
+        let mut buf = vec![];
+        merged[0].encode(&mut buf).unwrap();
+        let mut batches = TraceBatch::decode(buf.into()).unwrap();
+        assert_eq!(batches.len(), 1);
+        batches.remove(0)
+    }
+
+    fn field_type(batch: &RecordBatch, name: &str) -> DataType {
+        batch.schema().field_with_name(name).unwrap().data_type().clone()
+    }
+
+    fn attribute(span: &Span, key: &str) -> Option<Value> {
+        span.attributes.as_ref().and_then(|attributes| attributes.get(key)).cloned()
+    }
+
+    #[test]
+    fn merge_promotes_mismatched_attribute_types() {
+        let payload1 = column_oriented_payload(&[span(
+            "span1",
+            vec![
+                ("status", json!(200)),
+                ("retries", json!(1)),
+                ("ratio", json!(1)),
+                ("flag", json!(true)),
+                ("only1", json!("a")),
+            ],
+        )]);
+        let payload2 = column_oriented_payload(&[span(
+            "span2",
+            vec![("status", json!("OK")), ("retries", json!(-2)), ("ratio", json!(0.5)), ("flag", json!("yes"))],
+        )]);
+
+        let batch = merge(&[payload1, payload2]);
+
+        assert_eq!(field_type(&batch.spans, "attributes_status"), DataType::Utf8);
+        assert_eq!(field_type(&batch.spans, "attributes_retries"), DataType::Int64);
+        assert_eq!(field_type(&batch.spans, "attributes_ratio"), DataType::Float64);
+        assert_eq!(field_type(&batch.spans, "attributes_flag"), DataType::Utf8);
+
+        let spans = batch.view().unwrap().materialize();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(attribute(&spans[0], "status"), Some(json!("200")));
+        assert_eq!(attribute(&spans[1], "status"), Some(json!("OK")));
+        assert_eq!(attribute(&spans[0], "retries"), Some(json!(1)));
+        assert_eq!(attribute(&spans[1], "retries"), Some(json!(-2)));
+        assert_eq!(attribute(&spans[0], "ratio"), Some(json!(1.0)));
+        assert_eq!(attribute(&spans[1], "ratio"), Some(json!(0.5)));
+        assert_eq!(attribute(&spans[0], "flag"), Some(json!("true")));
+        assert_eq!(attribute(&spans[1], "flag"), Some(json!("yes")));
+        assert_eq!(attribute(&spans[0], "only1"), Some(json!("a")));
+        assert_eq!(attribute(&spans[1], "only1"), None);
+    }
+
+    #[test]
+    fn merge_promotes_u64_and_i64_to_i64() {
+        let payload1 = column_oriented_payload(&[span("span1", vec![("count", json!(u32::MAX))])]);
+        let payload2 = column_oriented_payload(&[span("span2", vec![("count", json!(i32::MIN))])]);
+
+        let batch = merge(&[payload1, payload2]);
+
+        assert_eq!(field_type(&batch.spans, "attributes_count"), DataType::Int64);
+        let spans = batch.view().unwrap().materialize();
+        assert_eq!(attribute(&spans[0], "count"), Some(json!(u32::MAX)));
+        assert_eq!(attribute(&spans[1], "count"), Some(json!(i32::MIN)));
+    }
+
+    #[test]
+    fn merge_promotes_u64_greater_than_i64_max_and_i64_to_f64() {
+        let payload1 = column_oriented_payload(&[span("span1", vec![("count", json!(u64::MAX))])]);
+        let payload2 = column_oriented_payload(&[span("span2", vec![("count", json!(-1))])]);
+
+        let batch = merge(&[payload1, payload2]);
+
+        assert_eq!(field_type(&batch.spans, "attributes_count"), DataType::Float64);
+        let spans = batch.view().unwrap().materialize();
+        assert_eq!(attribute(&spans[0], "count"), Some(json!(u64::MAX as f64)));
+        assert_eq!(attribute(&spans[1], "count"), Some(json!(-1.0)));
+    }
+
+    #[test]
+    fn merge_keeps_the_metadata_of_numeric_strings() {
+        let options = EncodingOptions {
+            numeric_strings: true,
+            ..Default::default()
+        };
+        let payload1 = column_oriented_payload_with_options(&[span("span1", vec![("status", json!("200"))])], &options);
+        let payload2 = column_oriented_payload_with_options(&[span("span2", vec![("status", json!("-1"))])], &options);
+
+        let batch = merge(&[payload1, payload2]);
+
+        let schema = batch.spans.schema();
+        let field = schema.field_with_name("attributes_status").unwrap();
+        assert_eq!(field.data_type(), &DataType::Int64);
+        assert_eq!(field.metadata().as_ref().unwrap().get(ORIGINAL_TYPE_KEY).map(String::as_str), Some("string"));
+        let spans = batch.view().unwrap().materialize();
+        assert_eq!(attribute(&spans[0], "status"), Some(json!("200")));
+        assert_eq!(attribute(&spans[1], "status"), Some(json!("-1")));
+    }
+
+    #[test]
+    fn merge_restores_the_numeric_strings_mixed_with_other_columns() {
+        let options = EncodingOptions {
+            numeric_strings: true,
+            ..Default::default()
+        };
+        // the same column holds strings, numeric strings of two types and numbers
+        let payload1 = column_oriented_payload(&[span("span1", vec![("status", json!("OK"))])]);
+        let payload2 = column_oriented_payload_with_options(&[span("span2", vec![("status", json!("200"))])], &options);
+        let payload3 = column_oriented_payload_with_options(&[span("span3", vec![("status", json!("0.5"))])], &options);
+        let payload4 = column_oriented_payload(&[span("span4", vec![("status", json!(404))])]);
+
+        let batch = merge(&[payload1, payload2, payload3, payload4]);
+
+        let schema = batch.spans.schema();
+        let field = schema.field_with_name("attributes_status").unwrap();
+        assert_eq!(value_type(field.data_type()), DataType::Utf8);
+        assert!(field.metadata().is_none());
+        let statuses: Vec<Option<Value>> = batch.view().unwrap().materialize().iter().map(|span| attribute(span, "status")).collect();
+        assert_eq!(statuses, vec![Some(json!("OK")), Some(json!("200")), Some(json!("0.5")), Some(json!("404"))]);
+
+        // numeric strings of two types are restored too
+        let payload2 = column_oriented_payload_with_options(&[span("span2", vec![("status", json!("200"))])], &options);
+        let payload3 = column_oriented_payload_with_options(&[span("span3", vec![("status", json!("0.5"))])], &options);
+        let batch = merge(&[payload2, payload3]);
+        let statuses: Vec<Option<Value>> = batch.view().unwrap().materialize().iter().map(|span| attribute(span, "status")).collect();
+        assert_eq!(statuses, vec![Some(json!("200")), Some(json!("0.5"))]);
+    }
+
+    #[test]
+    fn merge_rewrites_event_and_link_ids() {
+        let mut span2 = span("span2", vec![]);
+        span2.events = Some(vec![event("event2")]);
+        let mut span3 = span("span3", vec![]);
+        span3.events = Some(vec![event("event3a"), event("event3b")]);
+        span3.links = Some(vec![link("span1")]);
+
+        // The event and link ids of both payloads reference the span rows of their own batch.
+        let payload1 = row_oriented_payload(&[span("span1", vec![]), span2]);
+        let payload2 = column_oriented_payload(&[span3, span("span4", vec![])]);
+
+        let batch = merge(&[payload1, payload2]);
+
+        assert!(batch.spans.schema().column_with_name("id").is_none());
+        let view = batch.view().unwrap();
+        let events: Vec<Vec<&str>> = view.spans().map(|span| span.events().map(|event| event.name()).collect()).collect();
+        assert_eq!(events, vec![vec![], vec!["event2"], vec!["event3a", "event3b"], vec![]]);
+        let links: Vec<Vec<&str>> = view.spans().map(|span| span.links().map(|link| link.span_id()).collect()).collect();
+        assert_eq!(links, vec![vec![], vec![], vec!["span1"], vec![]]);
+    }
+
+    #[test]
+    fn merge_chooses_dictionary_encoding_from_merged_values() {
+        let spans = |count: usize| (0..count).map(|_| span("GET /users", vec![])).collect::<Vec<Span>>();
+        let payload1 = column_oriented_payload(&spans(3));
+        let payload2 = column_oriented_payload(&spans(3));
+        assert_eq!(field_type(&TraceBatch::decode(payload1.clone()).unwrap()[0].spans, "name"), DataType::Utf8);
+
+        let batch = merge(&[payload1, payload2]);
+
+        assert_eq!(
+            field_type(&batch.spans, "name"),
+            DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8))
+        );
+        assert_eq!(batch.view().unwrap().spans().filter(|span| span.name() == "GET /users").count(), 6);
+    }
+
+    #[test]
+    fn merge_keeps_the_span_kinds_dictionary_encoded() {
+        let mut server_span = span("span1", vec![]);
+        server_span.kind = Some(SpanKind::Server);
+        let payload1 = column_oriented_payload(&[server_span]);
+        let payload2 = column_oriented_payload(&[span("span2", vec![])]);
+
+        let batch = merge(&[payload1, payload2]);
+
+        assert_eq!(field_type(&batch.spans, "kind"), span_kind_type());
+        let kinds: Vec<Option<SpanKind>> = batch.view().unwrap().spans().map(|span| span.kind()).collect();
+        assert_eq!(kinds, vec![Some(SpanKind::Server), Some(SpanKind::Internal)]);
+    }
+}
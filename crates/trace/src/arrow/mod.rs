@@ -1,68 +1,123 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::Instant;
 
-use arrow::datatypes::{DataType, Field, Schema, UInt8Type, UInt16Type};
+use arrow::datatypes::{ArrowDictionaryKeyType, DataType, Field, Schema, UInt8Type, UInt16Type};
 use arrow::ipc::reader::StreamReader;
+use bytes::{BufMut, Bytes, BytesMut};
 use prost::Message;
 use twox_hash::RandomXxHashBuilder64;
 
-use common::{Attributes, Span};
+use common::{Attributes, Span, SpanKind};
 use event::serialize_events_from_row_oriented_data_source;
 use link::serialize_links_from_row_oriented_data_source;
 use oltp::opentelemetry::proto::events::v1::{InstrumentationLibraryEvents, ResourceEvents};
 use schema::{FieldInfo, FieldType};
 use span::serialize_spans_from_row_oriented_data_source;
 
+use crate::allocator::AllocationProbe;
 use crate::arrow::attribute::{infer_event_attribute_schema, infer_link_attribute_schema, infer_span_attribute_schema};
-use crate::arrow::event::{infer_event_schema, serialize_events_from_column_oriented_data_source};
-use crate::arrow::link::{infer_link_schema, serialize_links_from_column_oriented_data_source};
-use crate::arrow::span::{infer_span_schema, serialize_spans_from_column_oriented_data_source};
+use crate::arrow::cardinality::{string_cardinality, CardinalityEstimator};
+use crate::arrow::event::serialize_events_from_column_oriented_data_source;
+use crate::arrow::link::serialize_links_from_column_oriented_data_source;
+use crate::arrow::pool::BufferPool;
+use crate::arrow::span::serialize_spans_from_column_oriented_data_source;
 use crate::arrow::statistics::{BatchStatistics, ColumnsStatistics};
 use crate::BenchmarkResult;
-use arrow::array::{Array, ArrayRef, BinaryArray, BinaryBuilder, StringArray, StringBuilder, UInt32Array, UInt32Builder, UInt64Array, UInt64Builder, UInt8Builder, Int64Builder, Float64Builder, BooleanBuilder, StringDictionaryBuilder, PrimitiveBuilder};
+use arrow::array::{Array, ArrayRef, BinaryArray, BinaryBuilder, StringBuilder, UInt32Array, UInt32Builder, UInt64Array, UInt64Builder, UInt8Builder, Int64Builder, Float64Builder, BooleanBuilder, StringDictionaryBuilder, PrimitiveBuilder, Int64Array, Float64Array, BooleanArray};
 use arrow::error::ArrowError;
 use arrow::ipc::writer::StreamWriter;
 use arrow::record_batch::RecordBatch;
-use itertools::Itertools;
 
 mod attribute;
+pub mod cardinality;
 mod event;
 mod link;
 pub(crate) mod schema;
 mod span;
+pub mod builder;
+pub mod merge;
+pub mod pool;
 pub mod statistics;
+pub mod view;
 
-#[derive(Debug)]
-pub struct EntitySchema {
-    pub schema: Arc<Schema>,
-    pub attribute_fields: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+// Field metadata of the columns of string attribute values encoded as numbers or booleans (see `parsed_string_field`).
+pub const ORIGINAL_TYPE_KEY: &str = "original_type";
+
+#[derive(Debug, Clone, Copy)]
+pub struct EncodingOptions {
+    // A string column is dictionary encoded when its cardinality/non-null count ratio is below this threshold.
+    pub dictionary_threshold: f64,
+    // Cardinality computation used by this heuristic.
+    pub cardinality_estimator: CardinalityEstimator,
+    // String attribute columns whose values all are numbers or booleans are encoded as numeric or boolean columns (see
+    // `parsed_string_field`).
+    pub numeric_strings: bool,
 }
 
-#[derive(Debug)]
-pub struct DataColumns {
-    spans: SpanDataColumns,
-    events: EventDataColumns,
-    links: LinkDataColumns,
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        Self {
+            dictionary_threshold: 0.2,
+            cardinality_estimator: CardinalityEstimator::Exact,
+            numeric_strings: false,
+        }
+    }
 }
 
+// The string values are borrowed from the spans (see `to_data_columns`), or owned when they are built or modified in
+// place (see `ArrowSpanBatchBuilder` and `AttributeProcessor`).
 #[derive(Debug)]
-pub struct SpanDataColumns {
-    trace_id_column: Vec<String>,
-    span_id_column: Vec<String>,
-    trace_state_column: Vec<Option<String>>,
-    parent_span_id_column: Vec<Option<String>>,
-    name_column: Vec<String>,
-    kind_column: Vec<Option<u8>>,
+pub struct DataColumns<'a> {
+    spans: SpanDataColumns<'a>,
+    events: EventDataColumns<'a>,
+    links: LinkDataColumns<'a>,
+}
+
+impl<'a> DataColumns<'a> {
+    pub fn new(
+        span_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+        event_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+        link_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+    ) -> Self {
+        Self {
+            spans: SpanDataColumns::new(span_attributes),
+            events: EventDataColumns::new(event_attributes),
+            links: LinkDataColumns::new(link_attributes),
+        }
+    }
+
+    // Attribute columns of the spans, events and links, each with the row count of its entity.
+    pub fn attribute_columns_mut(&mut self) -> [(usize, &mut HashMap<String, DataColumn<'a>>); 3] {
+        [
+            (self.spans.trace_id_column.len(), &mut self.spans.attributes_column),
+            (self.events.id_column.len(), &mut self.events.attributes_column),
+            (self.links.id_column.len(), &mut self.links.attributes_column),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SpanDataColumns<'a> {
+    trace_id_column: Vec<Cow<'a, str>>,
+    span_id_column: Vec<Cow<'a, str>>,
+    trace_state_column: Vec<Option<Cow<'a, str>>>,
+    parent_span_id_column: Vec<Option<Cow<'a, str>>>,
+    name_column: Vec<Cow<'a, str>>,
+    kind_column: Vec<Option<SpanKind>>,
     start_time_unix_nano_column: Vec<u64>,
     end_time_unix_nano_column: Vec<Option<u64>>,
-    attributes_column: HashMap<String, DataColumn>,
+    attributes_column: HashMap<String, DataColumn<'a>>,
     dropped_attrs_count_column: Vec<Option<u32>>,
     dropped_events_count_column: Vec<Option<u32>>,
     dropped_links_count_column: Vec<Option<u32>>,
+    status_code_column: Vec<Option<u8>>,
+    status_message_column: Vec<Option<Cow<'a, str>>>,
 }
 
-impl SpanDataColumns {
+impl SpanDataColumns<'_> {
     pub fn new(inferred_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>) -> Self {
         Self {
             attributes_column: build_attribute_columns(inferred_attributes),
@@ -71,35 +126,16 @@ impl SpanDataColumns {
     }
 }
 
-impl Default for SpanDataColumns {
-    fn default() -> Self {
-        Self {
-            trace_id_column: vec![],
-            span_id_column: vec![],
-            trace_state_column: vec![],
-            parent_span_id_column: vec![],
-            name_column: vec![],
-            kind_column: vec![],
-            start_time_unix_nano_column: vec![],
-            end_time_unix_nano_column: vec![],
-            attributes_column: Default::default(),
-            dropped_attrs_count_column: vec![],
-            dropped_events_count_column: vec![],
-            dropped_links_count_column: vec![],
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct EventDataColumns {
+#[derive(Debug, Default)]
+pub struct EventDataColumns<'a> {
     id_column: Vec<u32>,
     time_unix_nano_column: Vec<u64>,
-    name_column: Vec<String>,
-    attributes_column: HashMap<String, DataColumn>,
+    name_column: Vec<Cow<'a, str>>,
+    attributes_column: HashMap<String, DataColumn<'a>>,
     dropped_attributes_count_column: Vec<Option<u32>>,
 }
 
-impl EventDataColumns {
+impl EventDataColumns<'_> {
     pub fn new(inferred_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>) -> Self {
         Self {
             attributes_column: build_attribute_columns(inferred_attributes),
@@ -108,42 +144,17 @@ impl EventDataColumns {
     }
 }
 
-impl Default for EventDataColumns {
-    fn default() -> Self {
-        Self {
-            id_column: vec![],
-            time_unix_nano_column: vec![],
-            name_column: vec![],
-            attributes_column: Default::default(),
-            dropped_attributes_count_column: vec![],
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct LinkDataColumns {
+#[derive(Debug, Default)]
+pub struct LinkDataColumns<'a> {
     id_column: Vec<u32>,
-    trace_id_column: Vec<String>,
-    span_id_column: Vec<String>,
-    trace_state_column: Vec<Option<String>>,
-    attributes_column: HashMap<String, DataColumn>,
+    trace_id_column: Vec<Cow<'a, str>>,
+    span_id_column: Vec<Cow<'a, str>>,
+    trace_state_column: Vec<Option<Cow<'a, str>>>,
+    attributes_column: HashMap<String, DataColumn<'a>>,
     dropped_attributes_count_column: Vec<Option<u32>>,
 }
 
-impl Default for LinkDataColumns {
-    fn default() -> Self {
-        Self {
-            id_column: vec![],
-            trace_id_column: vec![],
-            span_id_column: vec![],
-            trace_state_column: vec![],
-            attributes_column: HashMap::default(),
-            dropped_attributes_count_column: vec![],
-        }
-    }
-}
-
-impl LinkDataColumns {
+impl LinkDataColumns<'_> {
     pub fn new(inferred_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>) -> Self {
         Self {
             attributes_column: build_attribute_columns(inferred_attributes),
@@ -153,31 +164,155 @@ impl LinkDataColumns {
 }
 
 #[derive(Debug)]
-pub enum DataColumn {
+#[allow(clippy::enum_variant_names)]
+pub enum DataColumn<'a> {
     U64Column { missing: usize, values: Vec<Option<u64>> },
     I64Column { missing: usize, values: Vec<Option<i64>> },
     F64Column { missing: usize, values: Vec<Option<f64>> },
-    StringColumn { missing: usize, values: Vec<Option<String>> },
+    StringColumn { missing: usize, values: Vec<Option<Cow<'a, str>>> },
     BoolColumn { missing: usize, values: Vec<Option<bool>> },
 }
 
-pub fn serialize_row_oriented_data_source(
+// Encodes a batch of spans into an OTLP-Arrow `ResourceEvents` payload. For the same spans, all the implementations
+// produce the same span, event and link schemas: fixed fields first (in the order of the OTLP messages, all-null
+// fields dropped, nullable only if they contain nulls), then the attribute fields sorted by name, with the string
+// dictionary encoding chosen by `string_column_type`.
+pub trait ArrowTraceEncoder {
+    fn name(&self) -> &'static str;
+
+    fn encode(
+        &self,
+        batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.encode_with_pool(&mut BufferPool::one_shot(), batch_stats, spans, options, bench_result)
+    }
+
+    fn encode_with_pool(
+        &self,
+        pool: &mut BufferPool,
+        batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    // Gives back an encoded payload once it has been consumed, so that a long-lived encoder can reuse its buffer.
+    fn recycle(&self, _buf: Vec<u8>) {}
+}
+
+// Builds the Arrow columns directly from the spans, after a schema inference pass over their attributes.
+pub struct RowOrientedEncoder;
+
+// Converts the spans into intermediate data columns (see `to_data_columns`), then builds the Arrow columns from them.
+pub struct ColumnOrientedEncoder;
+
+// Keeps the buffers of an encoder between the batches (see `BufferPool`), like a long-lived exporter.
+pub struct ReusingEncoder<E> {
+    encoder: E,
+    pool: RefCell<BufferPool>,
+}
+
+impl<E: ArrowTraceEncoder> ReusingEncoder<E> {
+    pub fn new(encoder: E) -> Self {
+        Self {
+            encoder,
+            pool: RefCell::new(BufferPool::long_lived()),
+        }
+    }
+}
+
+impl ArrowTraceEncoder for RowOrientedEncoder {
+    fn name(&self) -> &'static str {
+        "arrow_row_oriented"
+    }
+
+    fn encode_with_pool(
+        &self,
+        pool: &mut BufferPool,
+        batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        serialize_row_oriented_data_source_with_pool(pool, batch_stats, spans, options, bench_result)
+    }
+}
+
+impl ArrowTraceEncoder for ColumnOrientedEncoder {
+    fn name(&self) -> &'static str {
+        "arrow_column_oriented"
+    }
+
+    fn encode_with_pool(
+        &self,
+        pool: &mut BufferPool,
+        batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        serialize_column_oriented_data_source_with_pool(pool, batch_stats, spans, options, bench_result)
+    }
+}
+
+impl<E: ArrowTraceEncoder> ArrowTraceEncoder for ReusingEncoder<E> {
+    fn name(&self) -> &'static str {
+        self.encoder.name()
+    }
+
+    fn encode(
+        &self,
+        batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.encoder
+            .encode_with_pool(&mut self.pool.borrow_mut(), batch_stats, spans, options, bench_result)
+    }
+
+    fn encode_with_pool(
+        &self,
+        pool: &mut BufferPool,
+        batch_stats: &mut BatchStatistics,
+        spans: &[Span],
+        options: &EncodingOptions,
+        bench_result: &mut BenchmarkResult,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.encoder.encode_with_pool(pool, batch_stats, spans, options, bench_result)
+    }
+
+    fn recycle(&self, buf: Vec<u8>) {
+        self.pool.borrow_mut().recycle_output_buf(buf);
+    }
+}
+
+fn serialize_row_oriented_data_source_with_pool(
+    pool: &mut BufferPool,
     batch_stats: &mut BatchStatistics,
     spans: &[Span],
+    options: &EncodingOptions,
     bench_result: &mut BenchmarkResult,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let start = Instant::now();
-    let (event_schema, event_count) = infer_event_schema(spans);
-    let (link_schema, link_count) = infer_link_schema(spans);
-    let gen_id_column = (event_count + link_count) > 0;
-    let span_schema = infer_span_schema(spans, gen_id_column);
+    let probe = AllocationProbe::start();
+    let (_, event_attributes) = infer_event_attribute_schema(spans, options);
+    let (_, link_attributes) = infer_link_attribute_schema(spans, options);
+    let span_attributes = infer_span_attribute_schema(spans, options);
     let elapse_time = Instant::now() - start;
     bench_result.total_infer_schema_ns += elapse_time.as_nanos();
+    bench_result.infer_schema_allocs.add(&probe.stop());
 
     let start = Instant::now();
-    let events_buf = serialize_events_from_row_oriented_data_source(batch_stats.event_stats(), event_schema, spans)?;
-    let links_buf = serialize_links_from_row_oriented_data_source(batch_stats.link_stats(), link_schema, spans)?;
-    let spans_buf = serialize_spans_from_row_oriented_data_source(batch_stats.span_stats(), span_schema, spans, gen_id_column)?;
+    let probe = AllocationProbe::start();
+    let ipc_buf = pool.ipc_buf();
+    let events_buf = serialize_events_from_row_oriented_data_source(batch_stats.event_stats(), &event_attributes, spans, options, ipc_buf)?;
+    let links_buf = serialize_links_from_row_oriented_data_source(batch_stats.link_stats(), &link_attributes, spans, options, ipc_buf)?;
+    let spans_buf = serialize_spans_from_row_oriented_data_source(batch_stats.span_stats(), &span_attributes, spans, options, ipc_buf)?;
+    let ipc_len = events_buf.len() + links_buf.len() + spans_buf.len();
 
     let resource_events = ResourceEvents {
         resource: None,
@@ -192,12 +327,16 @@ pub fn serialize_row_oriented_data_source(
 
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_creation_ns += elapse_time.as_nanos();
+    bench_result.buffer_creation_allocs.add(&probe.stop());
 
     let start = Instant::now();
-    let mut buf: Vec<u8> = Vec::new();
+    let probe = AllocationProbe::start();
+    let mut buf = pool.output_buf();
     resource_events.encode(&mut buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_serialization_ns += elapse_time.as_nanos();
+    bench_result.buffer_serialization_allocs.add(&probe.stop());
+    pool.record_sizes(ipc_len, buf.len());
 
     Ok(buf)
 }
@@ -205,14 +344,66 @@ pub fn serialize_row_oriented_data_source(
 pub fn serialize_column_oriented_data_source(
     batch_stats: &mut BatchStatistics,
     spans: &[Span],
+    options: &EncodingOptions,
     bench_result: &mut BenchmarkResult,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let data_columns = to_data_columns(spans);
+    serialize_column_oriented_data_source_with_pool(&mut BufferPool::one_shot(), batch_stats, spans, options, bench_result)
+}
+
+fn serialize_column_oriented_data_source_with_pool(
+    pool: &mut BufferPool,
+    batch_stats: &mut BatchStatistics,
+    spans: &[Span],
+    options: &EncodingOptions,
+    bench_result: &mut BenchmarkResult,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let probe = AllocationProbe::start();
+    let (_, event_attributes) = infer_event_attribute_schema(spans, options);
+    let (_, link_attributes) = infer_link_attribute_schema(spans, options);
+    let span_attributes = infer_span_attribute_schema(spans, options);
+    let elapse_time = Instant::now() - start;
+    bench_result.total_infer_schema_ns += elapse_time.as_nanos();
+    bench_result.infer_schema_allocs.add(&probe.stop());
 
     let start = Instant::now();
-    let events_buf = serialize_events_from_column_oriented_data_source(batch_stats.event_stats(), &data_columns)?;
-    let links_buf = serialize_links_from_column_oriented_data_source(batch_stats.link_stats(), &data_columns)?;
-    let spans_buf = serialize_spans_from_column_oriented_data_source(batch_stats.span_stats(), &data_columns)?;
+    let probe = AllocationProbe::start();
+    let mut data_columns = pool.data_columns(span_attributes, event_attributes, link_attributes);
+    append_to_data_columns(spans, &mut data_columns);
+    let elapse_time = Instant::now() - start;
+    bench_result.total_data_source_conversion_ns += elapse_time.as_nanos();
+    bench_result.data_source_conversion_allocs.add(&probe.stop());
+
+    let buf = serialize_data_columns_with_pool(pool, batch_stats, &data_columns, options, bench_result)?;
+
+    pool.record_row_counts(&data_columns);
+
+    Ok(buf)
+}
+
+pub fn serialize_data_columns(
+    batch_stats: &mut BatchStatistics,
+    data_columns: &DataColumns,
+    options: &EncodingOptions,
+    bench_result: &mut BenchmarkResult,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    serialize_data_columns_with_pool(&mut BufferPool::one_shot(), batch_stats, data_columns, options, bench_result)
+}
+
+fn serialize_data_columns_with_pool(
+    pool: &mut BufferPool,
+    batch_stats: &mut BatchStatistics,
+    data_columns: &DataColumns,
+    options: &EncodingOptions,
+    bench_result: &mut BenchmarkResult,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let probe = AllocationProbe::start();
+    let ipc_buf = pool.ipc_buf();
+    let events_buf = serialize_events_from_column_oriented_data_source(batch_stats.event_stats(), data_columns, options, ipc_buf)?;
+    let links_buf = serialize_links_from_column_oriented_data_source(batch_stats.link_stats(), data_columns, options, ipc_buf)?;
+    let spans_buf = serialize_spans_from_column_oriented_data_source(batch_stats.span_stats(), data_columns, options, ipc_buf)?;
+    let ipc_len = events_buf.len() + links_buf.len() + spans_buf.len();
 
     let resource_events = ResourceEvents {
         resource: None,
@@ -227,18 +418,23 @@ pub fn serialize_column_oriented_data_source(
 
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_creation_ns += elapse_time.as_nanos();
+    bench_result.buffer_creation_allocs.add(&probe.stop());
 
     let start = Instant::now();
-    let mut buf: Vec<u8> = Vec::new();
+    let probe = AllocationProbe::start();
+    let mut buf = pool.output_buf();
     resource_events.encode(&mut buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_serialization_ns += elapse_time.as_nanos();
+    bench_result.buffer_serialization_allocs.add(&probe.stop());
+    pool.record_sizes(ipc_len, buf.len());
 
     Ok(buf)
 }
 
 pub fn deserialize(buf: Vec<u8>, bench_result: &mut BenchmarkResult) {
     let start = Instant::now();
+    let probe = AllocationProbe::start();
     let resource_events = ResourceEvents::decode(bytes::Bytes::from(buf)).unwrap();
     let mut reader = StreamReader::try_new(&resource_events.instrumentation_library_events[0].spans as &[u8]).expect("stream reader error");
     let batch = reader.next().unwrap().unwrap();
@@ -255,38 +451,47 @@ pub fn deserialize(buf: Vec<u8>, bench_result: &mut BenchmarkResult) {
     }
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_deserialization_ns += elapse_time.as_nanos();
+    bench_result.buffer_deserialization_allocs.add(&probe.stop());
 }
 
-fn to_data_columns(spans: &[Span]) -> DataColumns {
-    let mut data_columns = DataColumns {
-        spans: SpanDataColumns::new(infer_span_attribute_schema(spans)),
-        events: EventDataColumns::new(infer_event_attribute_schema(spans).1),
-        links: LinkDataColumns::new(infer_link_attribute_schema(spans).1),
-    };
+pub fn to_data_columns<'a>(spans: &'a [Span], options: &EncodingOptions) -> DataColumns<'a> {
+    let mut data_columns = DataColumns::new(
+        infer_span_attribute_schema(spans, options),
+        infer_event_attribute_schema(spans, options).1,
+        infer_link_attribute_schema(spans, options).1,
+    );
+    append_to_data_columns(spans, &mut data_columns);
+    data_columns
+}
 
+// The string values of the data columns are borrowed from the spans, nothing is copied. The data columns must be empty
+// and have an attribute column for every attribute of the spans, events and links.
+fn append_to_data_columns<'a>(spans: &'a [Span], data_columns: &mut DataColumns<'a>) {
     spans.iter().enumerate().for_each(|(id, span)| {
         // process span fields
-        data_columns.spans.trace_id_column.push(span.trace_id.clone());
-        data_columns.spans.span_id_column.push(span.span_id.clone());
-        data_columns.spans.trace_state_column.push(span.trace_state.clone());
-        data_columns.spans.parent_span_id_column.push(span.parent_span_id.clone());
-        data_columns.spans.name_column.push(span.name.clone());
-        data_columns.spans.kind_column.push(span.kind.map(|v| v as u8));
+        data_columns.spans.trace_id_column.push(Cow::Borrowed(&*span.trace_id));
+        data_columns.spans.span_id_column.push(Cow::Borrowed(&*span.span_id));
+        data_columns.spans.trace_state_column.push(span.trace_state.as_deref().map(Cow::Borrowed));
+        data_columns.spans.parent_span_id_column.push(span.parent_span_id.as_deref().map(Cow::Borrowed));
+        data_columns.spans.name_column.push(Cow::Borrowed(&*span.name));
+        data_columns.spans.kind_column.push(span.kind);
         data_columns.spans.start_time_unix_nano_column.push(span.start_time_unix_nano);
-        data_columns.spans.end_time_unix_nano_column.push(span.end_time_unix_nano.clone());
+        data_columns.spans.end_time_unix_nano_column.push(span.end_time_unix_nano);
         attributes_to_data_columns(span.attributes.as_ref(), &mut data_columns.spans.attributes_column);
-        data_columns.spans.dropped_attrs_count_column.push(span.dropped_attributes_count.clone());
-        data_columns.spans.dropped_events_count_column.push(span.dropped_events_count.clone());
-        data_columns.spans.dropped_links_count_column.push(span.dropped_links_count.clone());
+        data_columns.spans.dropped_attrs_count_column.push(span.dropped_attributes_count);
+        data_columns.spans.dropped_events_count_column.push(span.dropped_events_count);
+        data_columns.spans.dropped_links_count_column.push(span.dropped_links_count);
+        data_columns.spans.status_code_column.push(span.status.as_ref().and_then(|status| status.code).map(|v| v as u8));
+        data_columns.spans.status_message_column.push(span.status.as_ref().and_then(|status| status.message.as_deref()).map(Cow::Borrowed));
 
         // process event fields
         if let Some(events) = &span.events {
             events.iter().for_each(|event| {
                 data_columns.events.id_column.push(id as u32);
                 data_columns.events.time_unix_nano_column.push(event.time_unix_nano);
-                data_columns.events.name_column.push(event.name.clone());
+                data_columns.events.name_column.push(Cow::Borrowed(&*event.name));
                 attributes_to_data_columns(Some(&event.attributes), &mut data_columns.events.attributes_column);
-                data_columns.events.dropped_attributes_count_column.push(event.dropped_attributes_count.clone());
+                data_columns.events.dropped_attributes_count_column.push(event.dropped_attributes_count);
             });
         }
 
@@ -294,19 +499,17 @@ fn to_data_columns(spans: &[Span]) -> DataColumns {
         if let Some(links) = &span.links {
             links.iter().for_each(|link| {
                 data_columns.links.id_column.push(id as u32);
-                data_columns.links.trace_id_column.push(link.trace_id.clone());
-                data_columns.links.span_id_column.push(link.span_id.clone());
-                data_columns.links.trace_state_column.push(link.trace_state.clone());
+                data_columns.links.trace_id_column.push(Cow::Borrowed(&*link.trace_id));
+                data_columns.links.span_id_column.push(Cow::Borrowed(&*link.span_id));
+                data_columns.links.trace_state_column.push(link.trace_state.as_deref().map(Cow::Borrowed));
                 attributes_to_data_columns(Some(&link.attributes), &mut data_columns.links.attributes_column);
-                data_columns.links.dropped_attributes_count_column.push(link.dropped_attributes_count.clone());
+                data_columns.links.dropped_attributes_count_column.push(link.dropped_attributes_count);
             });
         }
     });
-
-    data_columns
 }
 
-fn attributes_to_data_columns(attributes: Option<&Attributes>, attributes_column: &mut HashMap<String, DataColumn>) {
+fn attributes_to_data_columns<'a>(attributes: Option<&'a Attributes>, attributes_column: &mut HashMap<String, DataColumn<'a>>) {
     match attributes {
         None => {
             attributes_column.iter_mut().for_each(|(_, data_column)| match data_column {
@@ -359,7 +562,7 @@ fn attributes_to_data_columns(attributes: Option<&Attributes>, attributes_column
                         }
                         DataColumn::StringColumn { values, .. } => {
                             let value = value.as_str().expect("should be a string value based on the inference schema");
-                            values.push(Some(value.into()));
+                            values.push(Some(Cow::Borrowed(value)));
                             max_row_count = usize::max(max_row_count, values.len());
                         }
                         DataColumn::BoolColumn { values, .. } => {
@@ -402,7 +605,7 @@ fn attributes_to_data_columns(attributes: Option<&Attributes>, attributes_column
     }
 }
 
-fn build_attribute_columns(inferred_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>) -> HashMap<String, DataColumn> {
+fn build_attribute_columns<'a>(inferred_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>) -> HashMap<String, DataColumn<'a>> {
     inferred_attributes
         .iter()
         .map(|(field_name, field)| {
@@ -425,7 +628,7 @@ pub fn u64_non_nullable_field(field_name: &str, data: &[u64], fields: &mut Vec<F
         return;
     }
     fields.push(Field::new(field_name, DataType::UInt64, false));
-    columns.push(Arc::new(UInt64Array::from_iter_values(data.iter().map(|v| *v))));
+    columns.push(Arc::new(UInt64Array::from_iter_values(data.iter().copied())));
 }
 
 pub fn u64_nullable_field(field_name: &str, data: &[Option<u64>], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
@@ -508,12 +711,25 @@ pub fn u8_nullable_field(field_name: &str, data: &[Option<u8>], fields: &mut Vec
     }
 }
 
+// The span kinds are encoded as a dictionary of their names (at most 6 values, UInt8 keys).
+pub fn span_kind_type() -> DataType {
+    DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8))
+}
+
+pub fn span_kind_field<I>(field_name: &str, kinds: I, row_count: usize, fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>)
+where
+    I: Iterator<Item = Option<SpanKind>> + Clone,
+{
+    let array = string_array(kinds.map(|kind| kind.map(SpanKind::name)), row_count, &span_kind_type());
+    array_field(field_name, array, fields, columns);
+}
+
 pub fn u32_non_nullable_field(field_name: &str, data: &[u32], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
     if data.is_empty() {
         return;
     }
     fields.push(Field::new(field_name, DataType::UInt32, false));
-    columns.push(Arc::new(UInt32Array::from_iter_values(data.iter().map(|v| *v))));
+    columns.push(Arc::new(UInt32Array::from_iter_values(data.iter().copied())));
 }
 
 pub fn u32_nullable_field(field_name: &str, data: &[Option<u32>], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
@@ -532,146 +748,232 @@ pub fn u32_nullable_field(field_name: &str, data: &[Option<u32>], fields: &mut V
     }
 }
 
-pub fn binary_non_nullable_field(field_name: &str, data: &[String], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
+pub fn binary_non_nullable_field<S: AsRef<str>>(field_name: &str, data: &[S], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
     if data.is_empty() {
         return;
     }
     fields.push(Field::new(field_name, DataType::Binary, false));
-    columns.push(Arc::new(BinaryArray::from(data.iter().map(|v| v.as_bytes()).collect::<Vec<&[u8]>>())));
+    columns.push(Arc::new(BinaryArray::from(data.iter().map(|v| v.as_ref().as_bytes()).collect::<Vec<&[u8]>>())));
 }
 
-pub fn string_non_nullable_field(field_name: &str, data: &[String], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
-    let row_count = data.len();
-    let cardinality = data.iter().unique().count();
-
+pub fn string_non_nullable_field<S: AsRef<str>>(field_name: &str, data: &[S], options: &EncodingOptions, fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
+    let cardinality = string_cardinality(data.iter().map(|v| v.as_ref()), data.len(), options);
     if cardinality == 0 {
-        return
+        return;
     }
-    let min_num_bits = min_num_bits_to_represent(cardinality);
-    let is_dictionary = min_num_bits <= 16 && (cardinality as f64 / row_count as f64) < 0.2;
 
-    if is_dictionary {
-        if min_num_bits <= 8 {
-            let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt8Type>::new(row_count), StringBuilder::new(row_count));
-            data.iter().for_each(|v| {
-                builder.append(v.clone()).unwrap();
-            });
-            let array = builder.finish();
-            if array.null_count() < array.len() {
-                fields.push(Field::new(
-                    field_name,
-                    DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8)),
-                    array.null_count() > 0,
-                ));
-                columns.push(Arc::new(array));
-            }
-        } else if min_num_bits <= 16 {
-            let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt16Type>::new(row_count), StringBuilder::new(row_count));
-            data.iter().for_each(|v| {
-                builder.append(v.clone()).unwrap();
-            });
-            let array = builder.finish();
-            if array.null_count() < array.len() {
-                fields.push(Field::new(
-                    field_name,
-                    DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8)),
-                    array.null_count() > 0,
-                ));
-                columns.push(Arc::new(array));
-            }
-        }
-    } else {
-        fields.push(Field::new(field_name, DataType::Utf8, false));
-        columns.push(Arc::new(StringArray::from_iter_values(data.iter().map(|v| v.clone()))));
+    let data_type = string_column_type(cardinality, data.len(), options);
+    let array = string_array(data.iter().map(|v| Some(v.as_ref())), data.len(), &data_type);
+    fields.push(new_field(field_name, array.data_type().clone(), false, fields));
+    columns.push(array);
+}
+
+pub fn string_nullable_field<S: AsRef<str>>(
+    field_name: &str,
+    data: &[Option<S>],
+    options: &EncodingOptions,
+    fields: &mut Vec<Field>,
+    columns: &mut Vec<ArrayRef>,
+) {
+    let non_null_count = data.iter().flatten().count();
+    if non_null_count == 0 {
+        return;
     }
+
+    let cardinality = string_cardinality(data.iter().flatten().map(|v| v.as_ref()), data.len(), options);
+    let data_type = string_column_type(cardinality, non_null_count, options);
+    let array = string_array(data.iter().map(|v| v.as_ref().map(|v| v.as_ref())), data.len(), &data_type);
+    fields.push(new_field(field_name, array.data_type().clone(), array.null_count() > 0, fields));
+    columns.push(array);
 }
 
-pub fn string_nullable_field(field_name: &str, data: &[Option<String>], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
-    let mut dictionary_values = HashSet::new();
-    let mut non_null_count = 0;
-    let row_count = data.len();
-    data.iter().for_each(|v| {
-        if let Some(v) = v {
-            dictionary_values.insert(v);
-            non_null_count += 1;
-        }
-    });
+// Adds a string attribute column as a UInt64, Int64, Float64 or Boolean column when all its values are the canonical
+// representation of a number or a boolean (see `parsed_string_type`). The field metadata records that the values were
+// strings, formatting the decoded values gives back the original strings. Returns false when the column must be encoded
+// as a string column.
+pub fn parsed_string_field<'a, I>(field_name: &str, values: I, fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) -> bool
+where
+    I: Iterator<Item = Option<&'a str>> + Clone,
+{
+    let array: ArrayRef = match parsed_string_type(values.clone().flatten()) {
+        Some(FieldType::U64) => Arc::new(values.map(|value| value.and_then(|value| value.parse::<u64>().ok())).collect::<UInt64Array>()),
+        Some(FieldType::I64) => Arc::new(values.map(|value| value.and_then(|value| value.parse::<i64>().ok())).collect::<Int64Array>()),
+        Some(FieldType::F64) => Arc::new(values.map(|value| value.and_then(|value| value.parse::<f64>().ok())).collect::<Float64Array>()),
+        Some(FieldType::Bool) => Arc::new(values.map(|value| value.map(|value| value == "true")).collect::<BooleanArray>()),
+        _ => return false,
+    };
 
-    if dictionary_values.len() == 0 {
-        return
+    let field_count = fields.len();
+    array_field(field_name, array, fields, columns);
+    if let Some(field) = fields.get_mut(field_count) {
+        let mut metadata = BTreeMap::new();
+        metadata.insert(ORIGINAL_TYPE_KEY.to_string(), "string".to_string());
+        field.set_metadata(Some(metadata));
     }
+    true
+}
 
-    let min_num_bits = min_num_bits_to_represent(dictionary_values.len());
-    let is_dictionary = min_num_bits <= 16 && (dictionary_values.len() as f64 / non_null_count as f64) < 0.2;
+// Type of string values that all are the canonical representation of an integer (UInt64, or Int64 if one of them is
+// negative), a float or a boolean, i.e. formatting the parsed value gives back the string ("007", "1.50" or "1e3" are
+// not). Integers mixed with floats are Float64 if formatting them as floats gives back the strings too. None otherwise,
+// or without values.
+pub fn parsed_string_type<'a, I>(values: I) -> Option<FieldType>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut parsed_type = None;
+    let mut exact_f64_integers = true;
 
-    if is_dictionary {
-        if min_num_bits <= 8 {
-            let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt8Type>::new(row_count), StringBuilder::new(row_count));
-            data.iter().for_each(|v| match v {
-                None => builder.append_null().unwrap(),
-                Some(v) => {
-                    builder.append(v.clone()).unwrap();
-                }
-            });
-            let array = builder.finish();
-            if array.null_count() < array.len() {
-                fields.push(Field::new(
-                    field_name,
-                    DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8)),
-                    array.null_count() > 0,
-                ));
-                columns.push(Arc::new(array));
+    for value in values {
+        let value_type = if value == "true" || value == "false" {
+            FieldType::Bool
+        } else if let Ok(number) = value.parse::<i64>() {
+            if number.to_string() != value {
+                return None;
             }
-        } else if min_num_bits <= 16 {
-            let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<UInt16Type>::new(row_count), StringBuilder::new(row_count));
-            data.iter().for_each(|v| match v {
-                None => builder.append_null().unwrap(),
-                Some(v) => {
-                    builder.append(v.clone()).unwrap();
-                }
-            });
-            let array = builder.finish();
-            if array.null_count() < array.len() {
-                fields.push(Field::new(
-                    field_name,
-                    DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8)),
-                    array.null_count() > 0,
-                ));
-                columns.push(Arc::new(array));
+            exact_f64_integers &= (number as f64).to_string() == value;
+            if number < 0 {
+                FieldType::I64
+            } else {
+                FieldType::U64
             }
-        }
-    } else {
-        let mut builder = StringBuilder::new(data.len());
-        data.iter().for_each(|value| {
-            match value {
-                None => builder.append_null(),
-                Some(value) => builder.append_value(value.clone()),
+        } else if let Ok(number) = value.parse::<f64>() {
+            if !number.is_finite() || number.to_string() != value {
+                return None;
             }
-                .expect("append data into builder failed")
-        });
+            FieldType::F64
+        } else {
+            return None;
+        };
 
-        let array = builder.finish();
-        if array.null_count() < array.len() {
-            fields.push(Field::new(field_name, DataType::Utf8, array.null_count() > 0));
-            columns.push(Arc::new(array));
-        }
+        parsed_type = match (parsed_type, value_type) {
+            (None, value_type) => Some(value_type),
+            (Some(FieldType::U64), FieldType::I64) | (Some(FieldType::I64), FieldType::U64) => Some(FieldType::I64),
+            (Some(FieldType::U64 | FieldType::I64), FieldType::F64) | (Some(FieldType::F64), FieldType::U64 | FieldType::I64) => Some(FieldType::F64),
+            (Some(parsed_type), value_type) if parsed_type == value_type => Some(parsed_type),
+            _ => return None,
+        };
+    }
+
+    match parsed_type {
+        Some(FieldType::F64) if !exact_f64_integers => None,
+        parsed_type => parsed_type,
     }
 }
 
-pub fn binary_nullable_field(field_name: &str, data: &[Option<String>], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
+pub fn binary_nullable_field<S: AsRef<str>>(field_name: &str, data: &[Option<S>], fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
     let mut builder = BinaryBuilder::new(data.len());
     data.iter().for_each(|value| {
         match value {
             None => builder.append_null(),
-            Some(value) => builder.append_value(value.as_bytes()),
+            Some(value) => builder.append_value(value.as_ref().as_bytes()),
         }
         .expect("append data into builder failed")
     });
-    let array = builder.finish();
+    array_field(field_name, Arc::new(builder.finish()), fields, columns);
+}
+
+// Adds an already built column, unless all its values are null. The field is nullable only if the column has nulls.
+pub fn array_field(field_name: &str, array: ArrayRef, fields: &mut Vec<Field>, columns: &mut Vec<ArrayRef>) {
     if array.null_count() < array.len() {
-        fields.push(Field::new(field_name, DataType::Binary, array.null_count() > 0));
-        columns.push(Arc::new(array));
+        fields.push(new_field(field_name, array.data_type().clone(), array.null_count() > 0, fields));
+        columns.push(array);
+    }
+}
+
+// Field of a column added after `fields`. The IPC writer and reader look the dictionaries up by their id, so every
+// dictionary column of a batch gets its own (the number of dictionary fields before it).
+pub fn new_field(field_name: &str, data_type: DataType, nullable: bool, fields: &[Field]) -> Field {
+    match data_type {
+        DataType::Dictionary(..) => {
+            let dict_id = fields.iter().filter(|field| matches!(field.data_type(), DataType::Dictionary(..))).count();
+            Field::new_dict(field_name, data_type, nullable, dict_id as i64, false)
+        }
+        _ => Field::new(field_name, data_type, nullable),
+    }
+}
+
+// Type of a string column: a dictionary with the smallest key type when the cardinality/non-null count ratio is below
+// the dictionary threshold (and the cardinality fits in 16 bits), plain Utf8 otherwise.
+pub fn string_column_type(cardinality: usize, non_null_count: usize, options: &EncodingOptions) -> DataType {
+    if cardinality == 0 {
+        return DataType::Utf8;
+    }
+    let min_num_bits = min_num_bits_to_represent(cardinality);
+    if min_num_bits > 16 || (cardinality as f64 / non_null_count as f64) >= options.dictionary_threshold {
+        return DataType::Utf8;
     }
+    let key_type = if min_num_bits <= 8 { DataType::UInt8 } else { DataType::UInt16 };
+    DataType::Dictionary(Box::new(key_type), Box::new(DataType::Utf8))
+}
+
+// Builds a string column of the type returned by `string_column_type`. The cardinality the type was chosen from may be
+// underestimated (see `CardinalityEstimator::HyperLogLog`): when the dictionary keys overflow, the column is built with
+// the next key type, then as Utf8.
+pub fn string_array<'a, I>(values: I, row_count: usize, data_type: &DataType) -> ArrayRef
+where
+    I: Iterator<Item = Option<&'a str>> + Clone,
+{
+    match data_type {
+        DataType::Dictionary(key_type, _) if key_type.as_ref() == &DataType::UInt8 => {
+            match string_dictionary_array::<UInt8Type, _>(values.clone(), row_count) {
+                Some(array) => array,
+                None => string_array(values, row_count, &DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8))),
+            }
+        }
+        DataType::Dictionary(..) => match string_dictionary_array::<UInt16Type, _>(values.clone(), row_count) {
+            Some(array) => array,
+            None => string_array(values, row_count, &DataType::Utf8),
+        },
+        _ => {
+            let mut builder = StringBuilder::new(row_count);
+            values.for_each(|value| {
+                match value {
+                    None => builder.append_null(),
+                    Some(value) => builder.append_value(value),
+                }
+                .expect("append data into builder failed")
+            });
+            Arc::new(builder.finish())
+        }
+    }
+}
+
+// Dictionary column with `K` keys, None if there are more distinct values than keys.
+fn string_dictionary_array<'a, K, I>(values: I, row_count: usize) -> Option<ArrayRef>
+where
+    K: ArrowDictionaryKeyType,
+    I: Iterator<Item = Option<&'a str>>,
+{
+    let mut builder = StringDictionaryBuilder::new(PrimitiveBuilder::<K>::new(row_count), StringBuilder::new(row_count));
+    for value in values {
+        match value {
+            None => builder.append_null().unwrap(),
+            Some(value) => {
+                if builder.append(value).is_err() {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(Arc::new(builder.finish()))
+}
+
+// Writes the Arrow IPC stream into `buf` and splits it off, the remaining capacity of `buf` is used by the next stream.
+pub fn serialize_into(stats: &mut ColumnsStatistics, fields: Vec<Field>, columns: Vec<ArrayRef>, buf: &mut BytesMut) -> Result<Bytes, ArrowError> {
+    if fields.is_empty() {
+        return Ok(Bytes::new());
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    stats.report(schema.clone(), &columns);
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let mut writer = StreamWriter::try_new((&mut *buf).writer(), schema.as_ref())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    writer.into_inner()?;
+    Ok(buf.split().freeze())
 }
 
 pub fn serialize(stats: &mut ColumnsStatistics, fields: Vec<Field>, columns: Vec<ArrayRef>) -> Result<Vec<u8>, ArrowError> {
@@ -686,7 +988,7 @@ pub fn serialize(stats: &mut ColumnsStatistics, fields: Vec<Field>, columns: Vec
     let mut writer = StreamWriter::try_new(Vec::new(), schema.as_ref())?;
     writer.write(&batch)?;
     writer.finish()?;
-    Ok(writer.into_inner()?)
+    writer.into_inner()
 }
 
 const fn num_bits<T>() -> usize {
@@ -696,4 +998,267 @@ const fn num_bits<T>() -> usize {
 fn min_num_bits_to_represent(x: usize) -> u32 {
     assert!(x > 0);
     num_bits::<usize>() as u32 - x.leading_zeros()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use arrow::array::{DictionaryArray, StringArray};
+    use itertools::Itertools;
+    use serde_json::json;
+
+    use common::{Event, Link, Status, StatusCode};
+
+    use crate::arrow::statistics::StatisticsReporter;
+    use crate::arrow::view::TraceBatch;
+
+    use super::*;
+
+    pub(crate) fn spans(count: usize) -> Vec<Span<'static>> {
+        (0..count)
+            .map(|i| {
+                let mut attributes = Attributes::new();
+                attributes.insert("http.method".into(), json!(if i % 2 == 0 { "GET" } else { "POST" }));
+                attributes.insert("user".into(), json!(format!("user-{}", i)));
+                attributes.insert("http.status_code".into(), json!(200 + i % 3));
+                attributes.insert("retry".into(), json!(-(i as i64)));
+                attributes.insert("ratio".into(), json!(i as f64 / 2.0));
+                if i % 3 == 0 {
+                    attributes.insert("cached".into(), json!(i % 2 == 0));
+                }
+
+                Span {
+                    trace_id: format!("trace-{}", i / 4).into(),
+                    span_id: format!("span-{}", i).into(),
+                    trace_state: None,
+                    parent_span_id: if i % 4 == 0 { None } else { Some(format!("span-{}", i - 1).into()) },
+                    name: format!("operation-{}", i % 3).into(),
+                    kind: if i % 5 == 0 { None } else { Some(SpanKind::Server) },
+                    start_time_unix_nano: i as u64,
+                    end_time_unix_nano: Some(i as u64 + 10),
+                    attributes: Some(attributes),
+                    dropped_attributes_count: None,
+                    events: Some(vec![Event {
+                        time_unix_nano: i as u64 + 1,
+                        name: "exception".into(),
+                        attributes: vec![("exception.type".to_string(), json!("IOError"))].into_iter().collect(),
+                        dropped_attributes_count: None,
+                    }]),
+                    dropped_events_count: None,
+                    links: if i % 2 == 0 {
+                        Some(vec![Link {
+                            trace_id: "trace-0".into(),
+                            span_id: format!("span-{}", i + 1).into(),
+                            trace_state: Some("state".into()),
+                            attributes: Attributes::new(),
+                            dropped_attributes_count: Some(0),
+                        }])
+                    } else {
+                        None
+                    },
+                    dropped_links_count: None,
+                    status: if i % 7 == 0 {
+                        Some(Status {
+                            code: Some(StatusCode::Error),
+                            message: None,
+                        })
+                    } else {
+                        None
+                    },
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn encode(encoder: &dyn ArrowTraceEncoder, spans: &[Span]) -> TraceBatch {
+        encode_with_options(encoder, spans, &EncodingOptions::default())
+    }
+
+    fn encode_with_options(encoder: &dyn ArrowTraceEncoder, spans: &[Span], options: &EncodingOptions) -> TraceBatch {
+        let mut stats_reporter = StatisticsReporter::noop();
+        let buf = encoder
+            .encode(stats_reporter.next_batch(), spans, options, &mut BenchmarkResult::new())
+            .unwrap();
+        let mut batches = TraceBatch::decode(buf.into()).unwrap();
+        assert_eq!(batches.len(), 1);
+        batches.remove(0)
+    }
+
+    pub(crate) fn assert_same_batches(entity: &str, row_oriented: Option<&RecordBatch>, column_oriented: Option<&RecordBatch>) {
+        let (row_oriented, column_oriented) = match (row_oriented, column_oriented) {
+            (None, None) => return,
+            (Some(row_oriented), Some(column_oriented)) => (row_oriented, column_oriented),
+            _ => panic!("{} batch produced by only one encoder", entity),
+        };
+
+        let row_oriented_schema = row_oriented.schema();
+        let column_oriented_schema = column_oriented.schema();
+        let row_oriented_names: Vec<&String> = row_oriented_schema.fields().iter().map(|field| field.name()).collect();
+        let column_oriented_names: Vec<&String> = column_oriented_schema.fields().iter().map(|field| field.name()).collect();
+        assert_eq!(row_oriented_names, column_oriented_names, "{} field names", entity);
+
+        for (i, (row_oriented_field, column_oriented_field)) in row_oriented_schema.fields().iter().zip(column_oriented_schema.fields()).enumerate() {
+            assert_eq!(row_oriented_field, column_oriented_field, "{} field '{}'", entity, row_oriented_field.name());
+            assert_eq!(
+                row_oriented.column(i).data(),
+                column_oriented.column(i).data(),
+                "{} column '{}'",
+                entity,
+                row_oriented_field.name()
+            );
+        }
+    }
+
+    #[test]
+    fn row_and_column_oriented_encoders_produce_identical_batches() {
+        for count in [1, 10, 40].iter() {
+            let spans = spans(*count);
+            let row_oriented = encode(&RowOrientedEncoder, &spans);
+            let column_oriented = encode(&ColumnOrientedEncoder, &spans);
+
+            assert_same_batches("span", Some(&row_oriented.spans), Some(&column_oriented.spans));
+            assert_same_batches("event", row_oriented.events.as_ref(), column_oriented.events.as_ref());
+            assert_same_batches("link", row_oriented.links.as_ref(), column_oriented.links.as_ref());
+        }
+    }
+
+    #[test]
+    fn link_trace_id_encoding_is_shared() {
+        let spans = spans(40);
+        for encoder in [&RowOrientedEncoder as &dyn ArrowTraceEncoder, &ColumnOrientedEncoder].iter() {
+            let links = encode(*encoder, &spans).links.unwrap();
+            assert_eq!(
+                links.schema().field_with_name("trace_id").unwrap().data_type(),
+                &DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8)),
+                "{}",
+                encoder.name()
+            );
+        }
+    }
+
+    #[test]
+    fn span_kinds_are_dictionary_encoded() {
+        let spans = spans(10);
+        for encoder in [&RowOrientedEncoder as &dyn ArrowTraceEncoder, &ColumnOrientedEncoder].iter() {
+            let batch = encode(*encoder, &spans);
+            assert_eq!(
+                batch.spans.schema().field_with_name("kind").unwrap().data_type(),
+                &DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8)),
+                "{}",
+                encoder.name()
+            );
+            let kinds: Vec<Option<SpanKind>> = batch.view().unwrap().spans().map(|span| span.kind()).collect();
+            assert_eq!(kinds, spans.iter().map(|span| span.kind).collect::<Vec<_>>(), "{}", encoder.name());
+        }
+    }
+
+    #[test]
+    fn span_kinds_are_decoded_next_to_other_dictionary_columns() {
+        let mut spans = spans(40);
+        for (i, span) in spans.iter_mut().enumerate() {
+            span.kind = Some(SpanKind::ALL[i % SpanKind::ALL.len()]);
+        }
+        for encoder in [&RowOrientedEncoder as &dyn ArrowTraceEncoder, &ColumnOrientedEncoder].iter() {
+            let batch = encode(*encoder, &spans);
+            let schema = batch.spans.schema();
+            let dictionary_fields: Vec<&Field> = schema.fields().iter().filter(|field| matches!(field.data_type(), DataType::Dictionary(..))).collect();
+            assert!(dictionary_fields.len() > 1, "{}", encoder.name());
+            assert!(dictionary_fields.iter().map(|field| field.dict_id().unwrap()).all_unique(), "{}", encoder.name());
+
+            let view = batch.view().unwrap();
+            let kinds: Vec<Option<SpanKind>> = view.spans().map(|span| span.kind()).collect();
+            assert_eq!(kinds, spans.iter().map(|span| span.kind).collect::<Vec<_>>(), "{}", encoder.name());
+            let names: Vec<&str> = view.spans().map(|span| span.name()).collect();
+            assert_eq!(names, spans.iter().map(|span| &*span.name).collect::<Vec<_>>(), "{}", encoder.name());
+        }
+    }
+
+    #[test]
+    fn reusing_encoders_produce_the_same_batches() {
+        let encoders: Vec<(&dyn ArrowTraceEncoder, Box<dyn ArrowTraceEncoder>)> = vec![
+            (&RowOrientedEncoder, Box::new(ReusingEncoder::new(RowOrientedEncoder))),
+            (&ColumnOrientedEncoder, Box::new(ReusingEncoder::new(ColumnOrientedEncoder))),
+        ];
+        // smaller and larger batches than the previous one, the type of an attribute changes in the second one
+        let mut batches = [spans(40), spans(10), spans(25)];
+        for span in batches[1].iter_mut() {
+            span.attributes.as_mut().unwrap().insert("retry".into(), json!("none"));
+        }
+
+        for (encoder, reusing_encoder) in encoders.iter() {
+            for spans in batches.iter() {
+                let expected = encode(*encoder, spans);
+                let batch = encode(reusing_encoder.as_ref(), spans);
+
+                assert_same_batches("span", Some(&expected.spans), Some(&batch.spans));
+                assert_same_batches("event", expected.events.as_ref(), batch.events.as_ref());
+                assert_same_batches("link", expected.links.as_ref(), batch.links.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn numeric_strings_are_restored_by_the_decoder() {
+        let options = EncodingOptions {
+            numeric_strings: true,
+            ..Default::default()
+        };
+        let mut spans = spans(10);
+        for (i, span) in spans.iter_mut().enumerate() {
+            let attributes = span.attributes.as_mut().unwrap();
+            attributes.insert("status".into(), json!((200 + i).to_string()));
+            attributes.insert("offset".into(), json!((i as i64 - 5).to_string()));
+            attributes.insert("latency".into(), json!((i as f64 / 4.0).to_string()));
+            attributes.insert("sampled".into(), json!((i % 2 == 0).to_string()));
+            // not the canonical representation of the number
+            attributes.insert("zip".into(), json!(format!("0{}", i)));
+        }
+
+        let row_oriented = encode_with_options(&RowOrientedEncoder, &spans, &options);
+        let column_oriented = encode_with_options(&ColumnOrientedEncoder, &spans, &options);
+        assert_same_batches("span", Some(&row_oriented.spans), Some(&column_oriented.spans));
+
+        let schema = row_oriented.spans.schema();
+        for (name, data_type) in [
+            ("status", DataType::UInt64),
+            ("offset", DataType::Int64),
+            ("latency", DataType::Float64),
+            ("sampled", DataType::Boolean),
+        ] {
+            let field = schema.field_with_name(&format!("attributes_{}", name)).unwrap();
+            assert_eq!(field.data_type(), &data_type, "{}", name);
+            assert_eq!(field.metadata().as_ref().unwrap().get(ORIGINAL_TYPE_KEY).map(String::as_str), Some("string"));
+        }
+        assert!(schema.field_with_name("attributes_zip").unwrap().metadata().is_none());
+
+        let decoded = row_oriented.view().unwrap().materialize();
+        for (span, decoded_span) in spans.iter().zip(decoded.iter()) {
+            assert_eq!(span.attributes, decoded_span.attributes);
+        }
+    }
+
+    #[test]
+    fn integer_and_float_strings_are_parsed_as_f64_when_exact() {
+        assert_eq!(parsed_string_type(["1", "-2", "0.5"].iter().copied()), Some(FieldType::F64));
+        assert_eq!(parsed_string_type(["9007199254740992", "0.5"].iter().copied()), Some(FieldType::F64));
+        // 2^53 + 1 is formatted as 9007199254740992 once converted to f64
+        assert_eq!(parsed_string_type(["9007199254740993", "0.5"].iter().copied()), None);
+        assert_eq!(parsed_string_type(["9007199254740993", "1"].iter().copied()), Some(FieldType::U64));
+    }
+
+    #[test]
+    fn string_array_falls_back_when_the_dictionary_keys_overflow() {
+        let values: Vec<String> = (0..70_000).map(|i| i.to_string()).collect();
+        let u8_dictionary = DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8));
+        let u16_dictionary = DataType::Dictionary(Box::new(DataType::UInt16), Box::new(DataType::Utf8));
+
+        let array = string_array(values[..300].iter().map(|v| Some(v.as_str())), 300, &u8_dictionary);
+        assert_eq!(array.data_type(), &u16_dictionary);
+        let dictionary = array.as_any().downcast_ref::<DictionaryArray<UInt16Type>>().unwrap();
+        assert_eq!(dictionary.values().len(), 300);
+
+        let array = string_array(values.iter().map(|v| Some(v.as_str())), values.len(), &u8_dictionary);
+        assert_eq!(array.data_type(), &DataType::Utf8);
+        let strings = array.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(strings.value(69_999), "69999");
+    }
+}
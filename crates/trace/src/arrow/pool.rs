@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use bytes::BytesMut;
+use twox_hash::RandomXxHashBuilder64;
+
+use crate::arrow::schema::FieldInfo;
+use crate::arrow::{DataColumn, DataColumns, EventDataColumns, LinkDataColumns, SpanDataColumns};
+
+// Buffers of an encoder kept between the batches. A one-shot pool (used by `ArrowTraceEncoder::encode`) starts empty for
+// every batch. A long-lived pool (see `ReusingEncoder`) sizes the buffers of a batch from the previous one:
+// - the vectors of the data columns of the column-oriented encoder are allocated with the span, event and link counts
+//   of the previous batch instead of growing from empty (they borrow the strings of the spans of their batch, so they
+//   can't be kept),
+// - the buffer receiving the Arrow IPC streams is kept and reserved with the size of the previous batch. Its memory is
+//   reclaimed once the payloads of the previous batch are dropped,
+// - the output buffer is kept once its payload has been consumed (see `ArrowTraceEncoder::recycle`), cleared and
+//   reserved with the size of the previous one.
+// The Arrow builders and the fields of the schemas are not kept: `finish` moves the buffers of a builder into the built
+// array, and the fields are moved into the schema of the record batch, so they are created for every batch (the
+// builders with the row count of the batch as capacity).
+pub struct BufferPool {
+    long_lived: bool,
+    row_counts: RowCounts,
+    ipc_buf: BytesMut,
+    ipc_len: usize,
+    output_buf: Vec<u8>,
+    output_len: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RowCounts {
+    spans: usize,
+    events: usize,
+    links: usize,
+}
+
+impl BufferPool {
+    pub fn one_shot() -> Self {
+        Self {
+            long_lived: false,
+            row_counts: RowCounts::default(),
+            ipc_buf: BytesMut::new(),
+            ipc_len: 0,
+            output_buf: Vec::new(),
+            output_len: 0,
+        }
+    }
+
+    pub fn long_lived() -> Self {
+        Self {
+            long_lived: true,
+            ..Self::one_shot()
+        }
+    }
+
+    // Data columns for the next batch, with the capacity of the row counts of the previous batch.
+    pub fn data_columns<'a>(
+        &self,
+        span_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+        event_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+        link_attributes: HashMap<String, FieldInfo, RandomXxHashBuilder64>,
+    ) -> DataColumns<'a> {
+        let mut data_columns = DataColumns::new(span_attributes, event_attributes, link_attributes);
+        data_columns.spans.reserve(self.row_counts.spans);
+        data_columns.events.reserve(self.row_counts.events);
+        data_columns.links.reserve(self.row_counts.links);
+        data_columns
+    }
+
+    // Row counts of the data columns of a batch, used to size the data columns of the next one.
+    pub fn record_row_counts(&mut self, data_columns: &DataColumns<'_>) {
+        if self.long_lived {
+            self.row_counts = RowCounts {
+                spans: data_columns.spans.trace_id_column.len(),
+                events: data_columns.events.id_column.len(),
+                links: data_columns.links.id_column.len(),
+            };
+        }
+    }
+
+    // Buffer receiving the Arrow IPC streams of a batch (see `serialize_into`).
+    pub fn ipc_buf(&mut self) -> &mut BytesMut {
+        self.ipc_buf.reserve(self.ipc_len);
+        &mut self.ipc_buf
+    }
+
+    // Buffer receiving the output payload of a batch, the recycled output buffer of the previous batch if any.
+    pub fn output_buf(&mut self) -> Vec<u8> {
+        let mut buf = std::mem::take(&mut self.output_buf);
+        buf.clear();
+        buf.reserve(self.output_len);
+        buf
+    }
+
+    // Takes back an output buffer once its payload has been consumed.
+    pub fn recycle_output_buf(&mut self, buf: Vec<u8>) {
+        if self.long_lived {
+            self.output_buf = buf;
+        }
+    }
+
+    // Sizes of the buffers of a batch, used to size the buffers of the next one.
+    pub fn record_sizes(&mut self, ipc_len: usize, output_len: usize) {
+        if self.long_lived {
+            self.ipc_len = ipc_len;
+            self.output_len = output_len;
+        }
+    }
+}
+
+impl SpanDataColumns<'_> {
+    fn reserve(&mut self, additional: usize) {
+        self.trace_id_column.reserve(additional);
+        self.span_id_column.reserve(additional);
+        self.trace_state_column.reserve(additional);
+        self.parent_span_id_column.reserve(additional);
+        self.name_column.reserve(additional);
+        self.kind_column.reserve(additional);
+        self.start_time_unix_nano_column.reserve(additional);
+        self.end_time_unix_nano_column.reserve(additional);
+        reserve_attribute_columns(&mut self.attributes_column, additional);
+        self.dropped_attrs_count_column.reserve(additional);
+        self.dropped_events_count_column.reserve(additional);
+        self.dropped_links_count_column.reserve(additional);
+        self.status_code_column.reserve(additional);
+        self.status_message_column.reserve(additional);
+    }
+}
+
+impl EventDataColumns<'_> {
+    fn reserve(&mut self, additional: usize) {
+        self.id_column.reserve(additional);
+        self.time_unix_nano_column.reserve(additional);
+        self.name_column.reserve(additional);
+        reserve_attribute_columns(&mut self.attributes_column, additional);
+        self.dropped_attributes_count_column.reserve(additional);
+    }
+}
+
+impl LinkDataColumns<'_> {
+    fn reserve(&mut self, additional: usize) {
+        self.id_column.reserve(additional);
+        self.trace_id_column.reserve(additional);
+        self.span_id_column.reserve(additional);
+        self.trace_state_column.reserve(additional);
+        reserve_attribute_columns(&mut self.attributes_column, additional);
+        self.dropped_attributes_count_column.reserve(additional);
+    }
+}
+
+fn reserve_attribute_columns(attribute_columns: &mut HashMap<String, DataColumn<'_>>, additional: usize) {
+    for column in attribute_columns.values_mut() {
+        match column {
+            DataColumn::U64Column { values, .. } => values.reserve(additional),
+            DataColumn::I64Column { values, .. } => values.reserve(additional),
+            DataColumn::F64Column { values, .. } => values.reserve(additional),
+            DataColumn::StringColumn { values, .. } => values.reserve(additional),
+            DataColumn::BoolColumn { values, .. } => values.reserve(additional),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arrow::statistics::BatchStatistics;
+    use crate::arrow::tests::spans;
+    use crate::arrow::{ArrowTraceEncoder, ColumnOrientedEncoder, EncodingOptions};
+    use crate::BenchmarkResult;
+
+    use super::*;
+
+    // Benchmark result of the second of two batches encoded with the pool, the output buffers being recycled.
+    fn second_batch_result(pool: &mut BufferPool) -> BenchmarkResult {
+        let spans = spans(200);
+        let options = EncodingOptions::default();
+        let mut bench_results = vec![BenchmarkResult::new(), BenchmarkResult::new()];
+        for bench_result in bench_results.iter_mut() {
+            let buf = ColumnOrientedEncoder
+                .encode_with_pool(pool, &mut BatchStatistics::noop(), &spans, &options, bench_result)
+                .unwrap();
+            pool.recycle_output_buf(buf);
+        }
+        bench_results.pop().unwrap()
+    }
+
+    #[test]
+    fn long_lived_pool_sizes_the_data_columns_from_the_previous_batch() {
+        let mut pool = BufferPool::long_lived();
+        let long_lived_result = second_batch_result(&mut pool);
+        let one_shot_result = second_batch_result(&mut BufferPool::one_shot());
+        assert!(long_lived_result.data_source_conversion_allocs.allocations < one_shot_result.data_source_conversion_allocs.allocations);
+
+        let data_columns = pool.data_columns(HashMap::default(), HashMap::default(), HashMap::default());
+        assert!(data_columns.spans.trace_id_column.capacity() >= 200);
+        assert!(data_columns.events.id_column.capacity() >= 200);
+        assert!(data_columns.links.id_column.capacity() >= 100);
+        assert_eq!(data_columns.spans.trace_id_column.len(), 0);
+    }
+
+    #[test]
+    fn long_lived_pool_reuses_the_output_buffer() {
+        let long_lived_result = second_batch_result(&mut BufferPool::long_lived());
+        let one_shot_result = second_batch_result(&mut BufferPool::one_shot());
+        assert_eq!(long_lived_result.buffer_serialization_allocs.allocations, 0);
+        assert!(one_shot_result.buffer_serialization_allocs.allocations > 0);
+    }
+}
@@ -1,11 +1,12 @@
 use serde::Serialize;
-use std::collections::HashSet;
+
+use crate::arrow::cardinality::Cardinality;
 
 #[derive(PartialEq, Debug)]
 pub struct FieldInfo {
     pub non_null_count: usize,
     pub field_type: FieldType,
-    pub dictionary_values: HashSet<String>,
+    pub cardinality: Cardinality,
 }
 
 #[derive(PartialEq, Debug, Serialize)]
@@ -16,9 +17,3 @@ pub enum FieldType {
     String,
     Bool,
 }
-
-impl FieldInfo {
-    pub fn is_dictionary(&self) -> bool {
-        (self.dictionary_values.len() as f64 / self.non_null_count as f64) < 0.2
-    }
-}
@@ -9,10 +9,6 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 use bitvec::vec::BitVec;
 use bitvec::order::Msb0;
-use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
-use std::fs;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StatisticsReporter {
@@ -92,6 +88,15 @@ pub struct ColumnsStatistics {
 }
 
 impl BatchStatistics {
+    pub fn noop() -> Self {
+        Self {
+            stats_enabled: false,
+            span_columns: ColumnsStatistics::new(false),
+            event_columns: ColumnsStatistics::new(false),
+            link_columns: ColumnsStatistics::new(false),
+        }
+    }
+
     pub fn span_stats(&mut self) -> &mut ColumnsStatistics {
         &mut self.span_columns
     }
@@ -282,7 +287,7 @@ impl ColumnsStatistics {
                         ColumnStatistics {
                             column_type: ColumnType::Binary,
                             total_values: column.len(),
-                            cardinality: column.iter().filter_map(|value| value).unique().count(),
+                            cardinality: column.iter().flatten().unique().count(),
                             missing_values: column.null_count(),
                             dictionary: false,
                             validity_map
@@ -295,7 +300,7 @@ impl ColumnsStatistics {
                         ColumnStatistics {
                             column_type: ColumnType::String,
                             total_values: column.len(),
-                            cardinality: column.iter().filter_map(|value| value).unique().count(),
+                            cardinality: column.iter().flatten().unique().count(),
                             missing_values: column.null_count(),
                             dictionary: false,
                             validity_map
@@ -309,7 +314,7 @@ impl ColumnsStatistics {
                             ColumnStatistics {
                                 column_type: ColumnType::String,
                                 total_values: column.keys().len(),
-                                cardinality: column.keys().iter().filter_map(|v| v).unique().count(),
+                                cardinality: column.keys().iter().flatten().unique().count(),
                                 missing_values: column.null_count(),
                                 dictionary: true,
                                 validity_map
@@ -322,7 +327,7 @@ impl ColumnsStatistics {
                             ColumnStatistics {
                                 column_type: ColumnType::String,
                                 total_values: column.keys().len(),
-                                cardinality: column.keys().iter().filter_map(|v| v).unique().count(),
+                                cardinality: column.keys().iter().flatten().unique().count(),
                                 missing_values: column.null_count(),
                                 dictionary: true,
                                 validity_map
@@ -335,7 +340,7 @@ impl ColumnsStatistics {
                             ColumnStatistics {
                                 column_type: ColumnType::String,
                                 total_values: column.keys().len(),
-                                cardinality: column.keys().iter().filter_map(|v| v).unique().count(),
+                                cardinality: column.keys().iter().flatten().unique().count(),
                                 missing_values: column.null_count(),
                                 dictionary: true,
                                 validity_map
@@ -0,0 +1,726 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, DictionaryArray, Float64Array, Int64Array, PrimitiveArray, StringArray, UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow::datatypes::{ArrowNativeType, ArrowPrimitiveType, DataType, UInt16Type, UInt32Type, UInt8Type};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use prost::Message;
+use serde_json::Value;
+
+use common::{Attributes, Event, Link, Span, SpanKind, Status, StatusCode};
+use oltp::opentelemetry::proto::events::v1::{InstrumentationLibraryEvents, ResourceEvents};
+
+use crate::arrow::ORIGINAL_TYPE_KEY;
+
+const ATTRIBUTE_PREFIX: &str = "attributes_";
+
+// Span, event and link record batches of a decoded OTLP-Arrow payload.
+#[derive(Debug, Clone)]
+pub struct TraceBatch {
+    pub spans: RecordBatch,
+    pub events: Option<RecordBatch>,
+    pub links: Option<RecordBatch>,
+}
+
+// Read-only view over a `TraceBatch`. Accessors return values borrowed from the Arrow arrays, nothing is materialized.
+pub struct TraceBatchView<'a> {
+    batch: &'a TraceBatch,
+    trace_id: &'a BinaryArray,
+    span_id: &'a BinaryArray,
+    parent_span_id: Option<&'a BinaryArray>,
+    name: StringColumn<'a>,
+    kind: Option<StringColumn<'a>>,
+    status_code: Option<&'a UInt8Array>,
+    start_time_unix_nano: &'a UInt64Array,
+    end_time_unix_nano: Option<&'a UInt64Array>,
+    // Span attribute columns by key, resolved once per batch.
+    span_attributes: HashMap<String, AttributeColumn<'a>>,
+    events: Option<EventColumns<'a>>,
+    links: Option<LinkColumns<'a>>,
+    // Rows of the events and links grouped by span, and the range of these rows belonging to each span row.
+    event_rows: Vec<usize>,
+    link_rows: Vec<usize>,
+    event_ranges: Vec<Range<usize>>,
+    link_ranges: Vec<Range<usize>>,
+}
+
+struct EventColumns<'a> {
+    batch: &'a RecordBatch,
+    time_unix_nano: &'a UInt64Array,
+    name: StringColumn<'a>,
+}
+
+struct LinkColumns<'a> {
+    batch: &'a RecordBatch,
+    trace_id: StringColumn<'a>,
+    span_id: StringColumn<'a>,
+}
+
+#[derive(Clone, Copy)]
+pub struct SpanView<'a> {
+    view: &'a TraceBatchView<'a>,
+    row: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct EventView<'a> {
+    columns: &'a EventColumns<'a>,
+    row: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct LinkView<'a> {
+    columns: &'a LinkColumns<'a>,
+    row: usize,
+}
+
+// Utf8 column, dictionary encoded or not.
+#[derive(Clone, Copy)]
+pub enum StringColumn<'a> {
+    Utf8(&'a StringArray),
+    UInt8Dictionary(&'a PrimitiveArray<UInt8Type>, &'a StringArray),
+    UInt16Dictionary(&'a PrimitiveArray<UInt16Type>, &'a StringArray),
+    UInt32Dictionary(&'a PrimitiveArray<UInt32Type>, &'a StringArray),
+}
+
+#[derive(Clone, Copy)]
+pub enum AttributeColumn<'a> {
+    U64(&'a UInt64Array),
+    I64(&'a Int64Array),
+    F64(&'a Float64Array),
+    Bool(&'a BooleanArray),
+    String(StringColumn<'a>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeValue<'a> {
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    String(&'a str),
+}
+
+impl TraceBatch {
+    // The payloads of `ResourceEvents` are slices of `buf`, but the Arrow IPC stream reader copies them into the arrays.
+    pub fn decode(buf: bytes::Bytes) -> Result<Vec<TraceBatch>, Box<dyn std::error::Error>> {
+        let resource_events = ResourceEvents::decode(buf)?;
+        let mut batches = vec![];
+
+        for library_events in resource_events.instrumentation_library_events.iter() {
+            let spans = match read_batch(&library_events.spans)? {
+                Some(spans) => spans,
+                None => continue,
+            };
+            batches.push(TraceBatch {
+                spans,
+                events: read_batch(&library_events.events)?,
+                links: read_batch(&library_events.links)?,
+            });
+        }
+
+        Ok(batches)
+    }
+
+    // Inverse of `decode`, the batches are written in a `ResourceEvents` with a single `InstrumentationLibraryEvents`.
+    pub fn encode(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let resource_events = ResourceEvents {
+            resource: None,
+            instrumentation_library_events: vec![InstrumentationLibraryEvents {
+                instrumentation_library: None,
+                spans: write_batch(Some(&self.spans))?.into(),
+                events: write_batch(self.events.as_ref())?.into(),
+                links: write_batch(self.links.as_ref())?.into(),
+            }],
+            schema_url: "".to_string(),
+        };
+
+        let mut buf = vec![];
+        resource_events.encode(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn view(&self) -> Result<TraceBatchView<'_>, ArrowError> {
+        TraceBatchView::try_new(self)
+    }
+}
+
+pub(crate) fn read_batch(payload: &[u8]) -> Result<Option<RecordBatch>, ArrowError> {
+    if payload.is_empty() {
+        return Ok(None);
+    }
+    let mut reader = StreamReader::try_new(payload)?;
+    reader.next().transpose()
+}
+
+fn write_batch(batch: Option<&RecordBatch>) -> Result<Vec<u8>, ArrowError> {
+    let batch = match batch {
+        Some(batch) => batch,
+        None => return Ok(vec![]),
+    };
+    let mut writer = StreamWriter::try_new(Vec::new(), batch.schema().as_ref())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    writer.into_inner()
+}
+
+impl<'a> TraceBatchView<'a> {
+    pub fn try_new(batch: &'a TraceBatch) -> Result<Self, ArrowError> {
+        let spans = &batch.spans;
+        let span_count = spans.num_rows();
+
+        let events = match &batch.events {
+            Some(events) => Some(EventColumns {
+                batch: events,
+                time_unix_nano: required_column(events, "time_unix_nano")?,
+                name: required_string_column(events, "name")?,
+            }),
+            None => None,
+        };
+        let links = match &batch.links {
+            Some(links) => Some(LinkColumns {
+                batch: links,
+                trace_id: required_string_column(links, "trace_id")?,
+                span_id: required_string_column(links, "span_id")?,
+            }),
+            None => None,
+        };
+
+        // Events and links reference their span through the `id` column, i.e. the span `id` column when present or the span row.
+        let span_ids: Option<&UInt32Array> = typed_column(spans, "id");
+        let span_id_of = |row: usize| span_ids.map(|ids| ids.value(row)).unwrap_or(row as u32);
+        let (event_rows, event_ranges) = batch.events.as_ref().map(rows_by_id).transpose()?.unwrap_or_default();
+        let (link_rows, link_ranges) = batch.links.as_ref().map(rows_by_id).transpose()?.unwrap_or_default();
+
+        Ok(Self {
+            batch,
+            trace_id: required_column(spans, "trace_id")?,
+            span_id: required_column(spans, "span_id")?,
+            parent_span_id: typed_column(spans, "parent_span_id"),
+            name: required_string_column(spans, "name")?,
+            kind: string_column(spans, "kind"),
+            status_code: typed_column(spans, "status_code"),
+            start_time_unix_nano: required_column(spans, "start_time_unix_nano")?,
+            end_time_unix_nano: typed_column(spans, "end_time_unix_nano"),
+            span_attributes: attribute_columns(spans).into_iter().map(|(key, column, _)| (key, column)).collect(),
+            events,
+            links,
+            event_rows,
+            link_rows,
+            event_ranges: (0..span_count).map(|row| event_ranges.get(&span_id_of(row)).cloned().unwrap_or(0..0)).collect(),
+            link_ranges: (0..span_count).map(|row| link_ranges.get(&span_id_of(row)).cloned().unwrap_or(0..0)).collect(),
+        })
+    }
+
+    pub fn span_count(&self) -> usize {
+        self.batch.spans.num_rows()
+    }
+
+    pub fn trace_ids(&self) -> &'a BinaryArray {
+        self.trace_id
+    }
+
+    pub fn names(&self) -> StringColumn<'a> {
+        self.name
+    }
+
+    pub fn start_times_unix_nano(&self) -> &'a UInt64Array {
+        self.start_time_unix_nano
+    }
+
+    pub fn end_times_unix_nano(&self) -> Option<&'a UInt64Array> {
+        self.end_time_unix_nano
+    }
+
+    // Names of the span kinds (see `SpanKind::name`).
+    pub fn kinds(&self) -> Option<StringColumn<'a>> {
+        self.kind
+    }
+
+    // None when no span of the batch has a status.
+    pub fn status_codes(&self) -> Option<&'a UInt8Array> {
+        self.status_code
+    }
+
+    pub fn duration_ns(&self, row: usize) -> Option<u64> {
+        let end_time = self.end_time_unix_nano.filter(|end_time| end_time.is_valid(row))?.value(row);
+        Some(end_time.saturating_sub(self.start_time_unix_nano.value(row)))
+    }
+
+    pub fn span_attribute(&self, key: &str) -> Option<AttributeColumn<'a>> {
+        self.span_attributes.get(key).copied()
+    }
+
+    pub fn span(&self, row: usize) -> SpanView<'_> {
+        SpanView { view: self, row }
+    }
+
+    pub fn spans(&self) -> impl Iterator<Item = SpanView<'_>> {
+        (0..self.span_count()).map(move |row| self.span(row))
+    }
+
+    // Copies the batch into `common::Span`s (used as the reference to compare the view with).
+    pub fn materialize(&self) -> Vec<Span<'static>> {
+        let span_attributes = attribute_columns(&self.batch.spans);
+        let event_attributes = self.events.as_ref().map(|events| attribute_columns(events.batch)).unwrap_or_default();
+        let link_attributes = self.links.as_ref().map(|links| attribute_columns(links.batch)).unwrap_or_default();
+        let dropped_attributes_count: Option<&UInt32Array> = typed_column(&self.batch.spans, "dropped_attributes_count");
+        let dropped_events_count: Option<&UInt32Array> = typed_column(&self.batch.spans, "dropped_events_count");
+        let dropped_links_count: Option<&UInt32Array> = typed_column(&self.batch.spans, "dropped_links_count");
+        let trace_state = string_column(&self.batch.spans, "trace_state");
+        let status_message = string_column(&self.batch.spans, "status_message");
+
+        self.spans()
+            .map(|span| {
+                let row = span.row;
+                let attributes = materialize_attributes(&span_attributes, row);
+                let events: Vec<Event> = span
+                    .events()
+                    .map(|event| Event {
+                        time_unix_nano: event.time_unix_nano(),
+                        name: event.name().to_string().into(),
+                        attributes: materialize_attributes(&event_attributes, event.row),
+                        dropped_attributes_count: optional_value(typed_column::<UInt32Array>(event.columns.batch, "dropped_attributes_count"), event.row),
+                    })
+                    .collect();
+                let links: Vec<Link> = span
+                    .links()
+                    .map(|link| Link {
+                        trace_id: link.trace_id().to_string().into(),
+                        span_id: link.span_id().to_string().into(),
+                        trace_state: string_column(link.columns.batch, "trace_state")
+                            .and_then(|column| column.value(link.row))
+                            .map(|trace_state| trace_state.to_string().into()),
+                        attributes: materialize_attributes(&link_attributes, link.row),
+                        dropped_attributes_count: optional_value(typed_column::<UInt32Array>(link.columns.batch, "dropped_attributes_count"), link.row),
+                    })
+                    .collect();
+
+                Span {
+                    trace_id: String::from_utf8_lossy(span.trace_id()).to_string().into(),
+                    span_id: String::from_utf8_lossy(span.span_id()).to_string().into(),
+                    trace_state: trace_state
+                        .and_then(|column| column.value(row))
+                        .map(|trace_state| trace_state.to_string().into()),
+                    parent_span_id: span
+                        .parent_span_id()
+                        .map(|parent_span_id| String::from_utf8_lossy(parent_span_id).to_string().into()),
+                    name: span.name().to_string().into(),
+                    kind: span.kind(),
+                    start_time_unix_nano: span.start_time_unix_nano(),
+                    end_time_unix_nano: span.end_time_unix_nano(),
+                    attributes: if attributes.is_empty() { None } else { Some(attributes) },
+                    dropped_attributes_count: optional_value(dropped_attributes_count, row),
+                    events: if events.is_empty() { None } else { Some(events) },
+                    dropped_events_count: optional_value(dropped_events_count, row),
+                    links: if links.is_empty() { None } else { Some(links) },
+                    dropped_links_count: optional_value(dropped_links_count, row),
+                    status: match (span.status_code(), status_message.and_then(|column| column.value(row))) {
+                        (None, None) => None,
+                        (code, message) => Some(Status {
+                            code: code.and_then(|code| StatusCode::from_i64(code as i64)),
+                            message: message.map(|message| message.to_string().into()),
+                        }),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a> SpanView<'a> {
+    pub fn trace_id(&self) -> &'a [u8] {
+        self.view.trace_id.value(self.row)
+    }
+
+    pub fn span_id(&self) -> &'a [u8] {
+        self.view.span_id.value(self.row)
+    }
+
+    pub fn parent_span_id(&self) -> Option<&'a [u8]> {
+        self.view
+            .parent_span_id
+            .filter(|parent_span_id| parent_span_id.is_valid(self.row))
+            .map(|parent_span_id| parent_span_id.value(self.row))
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.view.name.value(self.row).unwrap_or("")
+    }
+
+    pub fn kind(&self) -> Option<SpanKind> {
+        self.view.kind.and_then(|kind| kind.value(self.row)).and_then(SpanKind::from_name)
+    }
+
+    pub fn status_code(&self) -> Option<u8> {
+        optional_value(self.view.status_code, self.row)
+    }
+
+    pub fn start_time_unix_nano(&self) -> u64 {
+        self.view.start_time_unix_nano.value(self.row)
+    }
+
+    pub fn end_time_unix_nano(&self) -> Option<u64> {
+        optional_value(self.view.end_time_unix_nano, self.row)
+    }
+
+    pub fn duration_ns(&self) -> Option<u64> {
+        self.view.duration_ns(self.row)
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<AttributeValue<'a>> {
+        self.view.span_attribute(key)?.value(self.row)
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = EventView<'a>> {
+        let columns = self.view.events.as_ref();
+        self.view.event_rows[self.view.event_ranges[self.row].clone()]
+            .iter()
+            .flat_map(move |row| columns.map(|columns| EventView { columns, row: *row }))
+    }
+
+    pub fn links(&self) -> impl Iterator<Item = LinkView<'a>> {
+        let columns = self.view.links.as_ref();
+        self.view.link_rows[self.view.link_ranges[self.row].clone()]
+            .iter()
+            .flat_map(move |row| columns.map(|columns| LinkView { columns, row: *row }))
+    }
+}
+
+impl<'a> EventView<'a> {
+    pub fn time_unix_nano(&self) -> u64 {
+        self.columns.time_unix_nano.value(self.row)
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.columns.name.value(self.row).unwrap_or("")
+    }
+}
+
+impl<'a> LinkView<'a> {
+    pub fn trace_id(&self) -> &'a str {
+        self.columns.trace_id.value(self.row).unwrap_or("")
+    }
+
+    pub fn span_id(&self) -> &'a str {
+        self.columns.span_id.value(self.row).unwrap_or("")
+    }
+}
+
+impl<'a> StringColumn<'a> {
+    pub fn try_new(array: &'a ArrayRef) -> Option<Self> {
+        match array.data_type() {
+            DataType::Utf8 => array.as_any().downcast_ref::<StringArray>().map(StringColumn::Utf8),
+            DataType::Dictionary(key_type, value_type) if value_type.as_ref() == &DataType::Utf8 => match key_type.as_ref() {
+                DataType::UInt8 => {
+                    let array = array.as_any().downcast_ref::<DictionaryArray<UInt8Type>>()?;
+                    Some(StringColumn::UInt8Dictionary(
+                        array.keys(),
+                        array.values().as_any().downcast_ref::<StringArray>()?,
+                    ))
+                }
+                DataType::UInt16 => {
+                    let array = array.as_any().downcast_ref::<DictionaryArray<UInt16Type>>()?;
+                    Some(StringColumn::UInt16Dictionary(
+                        array.keys(),
+                        array.values().as_any().downcast_ref::<StringArray>()?,
+                    ))
+                }
+                DataType::UInt32 => {
+                    let array = array.as_any().downcast_ref::<DictionaryArray<UInt32Type>>()?;
+                    Some(StringColumn::UInt32Dictionary(
+                        array.keys(),
+                        array.values().as_any().downcast_ref::<StringArray>()?,
+                    ))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn value(&self, row: usize) -> Option<&'a str> {
+        match self {
+            StringColumn::Utf8(array) => {
+                if array.is_valid(row) {
+                    Some(array.value(row))
+                } else {
+                    None
+                }
+            }
+            StringColumn::UInt8Dictionary(keys, values) => dictionary_value(keys, values, row),
+            StringColumn::UInt16Dictionary(keys, values) => dictionary_value(keys, values, row),
+            StringColumn::UInt32Dictionary(keys, values) => dictionary_value(keys, values, row),
+        }
+    }
+}
+
+// A key out of the range of the dictionary values (corrupted payload) reads as a null instead of panicking.
+fn dictionary_value<'a, K: ArrowPrimitiveType>(keys: &PrimitiveArray<K>, values: &'a StringArray, row: usize) -> Option<&'a str> {
+    if !keys.is_valid(row) {
+        return None;
+    }
+    keys.value(row)
+        .to_usize()
+        .filter(|key| *key < values.len() && values.is_valid(*key))
+        .map(|key| values.value(key))
+}
+
+impl<'a> AttributeColumn<'a> {
+    pub fn try_new(array: &'a ArrayRef) -> Option<Self> {
+        match array.data_type() {
+            DataType::UInt64 => array.as_any().downcast_ref::<UInt64Array>().map(AttributeColumn::U64),
+            DataType::Int64 => array.as_any().downcast_ref::<Int64Array>().map(AttributeColumn::I64),
+            DataType::Float64 => array.as_any().downcast_ref::<Float64Array>().map(AttributeColumn::F64),
+            DataType::Boolean => array.as_any().downcast_ref::<BooleanArray>().map(AttributeColumn::Bool),
+            _ => StringColumn::try_new(array).map(AttributeColumn::String),
+        }
+    }
+
+    pub fn value(&self, row: usize) -> Option<AttributeValue<'a>> {
+        match self {
+            AttributeColumn::U64(array) => optional_value(Some(*array), row).map(AttributeValue::U64),
+            AttributeColumn::I64(array) => optional_value(Some(*array), row).map(AttributeValue::I64),
+            AttributeColumn::F64(array) => optional_value(Some(*array), row).map(AttributeValue::F64),
+            AttributeColumn::Bool(array) => {
+                if array.is_valid(row) {
+                    Some(AttributeValue::Bool(array.value(row)))
+                } else {
+                    None
+                }
+            }
+            AttributeColumn::String(column) => column.value(row).map(AttributeValue::String),
+        }
+    }
+}
+
+// Same representation as the string values encoded as numbers or booleans (see `parsed_string_type`).
+impl std::fmt::Display for AttributeValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeValue::U64(value) => write!(f, "{}", value),
+            AttributeValue::I64(value) => write!(f, "{}", value),
+            AttributeValue::F64(value) => write!(f, "{}", value),
+            AttributeValue::Bool(value) => write!(f, "{}", value),
+            AttributeValue::String(value) => f.write_str(value),
+        }
+    }
+}
+
+impl<'a> From<AttributeValue<'a>> for Value {
+    fn from(value: AttributeValue<'a>) -> Self {
+        match value {
+            AttributeValue::U64(value) => Value::from(value),
+            AttributeValue::I64(value) => Value::from(value),
+            AttributeValue::F64(value) => Value::from(value),
+            AttributeValue::Bool(value) => Value::Bool(value),
+            AttributeValue::String(value) => Value::String(value.to_string()),
+        }
+    }
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Option<&'a ArrayRef> {
+    batch.schema().column_with_name(name).map(|(index, _)| batch.column(index))
+}
+
+fn typed_column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Option<&'a T> {
+    column(batch, name)?.as_any().downcast_ref::<T>()
+}
+
+fn required_column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T, ArrowError> {
+    typed_column(batch, name).ok_or_else(|| ArrowError::SchemaError(format!("missing or invalid column '{}'", name)))
+}
+
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> Option<StringColumn<'a>> {
+    StringColumn::try_new(column(batch, name)?)
+}
+
+fn required_string_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<StringColumn<'a>, ArrowError> {
+    string_column(batch, name).ok_or_else(|| ArrowError::SchemaError(format!("missing or invalid string column '{}'", name)))
+}
+
+// The flag is set for the columns of string values encoded as numbers or booleans (see `parsed_string_field`).
+fn attribute_columns(batch: &RecordBatch) -> Vec<(String, AttributeColumn<'_>, bool)> {
+    let schema = batch.schema();
+    schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, field)| {
+            let key = field.name().strip_prefix(ATTRIBUTE_PREFIX)?;
+            let parsed_strings = field
+                .metadata()
+                .as_ref()
+                .and_then(|metadata| metadata.get(ORIGINAL_TYPE_KEY))
+                .is_some_and(|original_type| original_type == "string");
+            Some((key.to_string(), AttributeColumn::try_new(batch.column(index))?, parsed_strings))
+        })
+        .collect()
+}
+
+fn materialize_attributes(columns: &[(String, AttributeColumn, bool)], row: usize) -> Attributes {
+    columns
+        .iter()
+        .filter_map(|(key, column, parsed_strings)| {
+            column.value(row).map(|value| {
+                let value = if *parsed_strings {
+                    Value::String(value.to_string())
+                } else {
+                    Value::from(value)
+                };
+                (key.clone(), value)
+            })
+        })
+        .collect()
+}
+
+fn optional_value<T: ArrowPrimitiveType>(array: Option<&PrimitiveArray<T>>, row: usize) -> Option<T::Native> {
+    array.filter(|array| array.is_valid(row)).map(|array| array.value(row))
+}
+
+type RowsById = (Vec<usize>, HashMap<u32, Range<usize>>);
+
+// The encoders write the events and links span after span, but the rows sharing the same `id` don't have to be
+// contiguous (e.g. merged or filtered batches). The rows are sorted by id (stable, the order of the rows of a span is
+// kept) and each id is mapped to its range in the sorted rows.
+fn rows_by_id(batch: &RecordBatch) -> Result<RowsById, ArrowError> {
+    let ids: &UInt32Array = required_column(batch, "id")?;
+    let mut rows: Vec<usize> = (0..ids.len()).collect();
+    rows.sort_by_key(|row| ids.value(*row));
+    let mut ranges: HashMap<u32, Range<usize>> = HashMap::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        ranges
+            .entry(ids.value(*row))
+            .and_modify(|range| range.end = index + 1)
+            .or_insert(index..index + 1);
+    }
+
+    Ok((rows, ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::datatypes::{Field, Schema};
+
+    use crate::arrow::tests::{encode, spans};
+    use crate::arrow::ColumnOrientedEncoder;
+
+    use super::*;
+
+    #[test]
+    fn accessors_read_the_encoded_spans() {
+        let spans = spans(10);
+        let batch = encode(&ColumnOrientedEncoder, &spans);
+        let view = batch.view().unwrap();
+
+        assert_eq!(view.span_count(), spans.len());
+        for (span, span_view) in spans.iter().zip(view.spans()) {
+            assert_eq!(span_view.trace_id(), span.trace_id.as_bytes());
+            assert_eq!(span_view.span_id(), span.span_id.as_bytes());
+            assert_eq!(span_view.parent_span_id(), span.parent_span_id.as_ref().map(|id| id.as_bytes()));
+            assert_eq!(span_view.name(), span.name);
+            assert_eq!(span_view.kind(), span.kind);
+            assert_eq!(
+                span_view.status_code(),
+                span.status.as_ref().and_then(|status| status.code).map(|code| code as u8)
+            );
+            assert_eq!(span_view.duration_ns(), Some(10));
+
+            let attributes = span.attributes.as_ref().unwrap();
+            assert_eq!(
+                span_view.attribute("http.method"),
+                attributes["http.method"].as_str().map(AttributeValue::String)
+            );
+            assert_eq!(span_view.attribute("ratio"), attributes["ratio"].as_f64().map(AttributeValue::F64));
+            assert_eq!(
+                span_view.attribute("cached"),
+                attributes.get("cached").and_then(Value::as_bool).map(AttributeValue::Bool)
+            );
+            assert_eq!(span_view.attribute("unknown"), None);
+
+            let events: Vec<(u64, &str)> = span_view.events().map(|event| (event.time_unix_nano(), event.name())).collect();
+            assert_eq!(events, vec![(span.start_time_unix_nano + 1, "exception")]);
+            let links: Vec<(&str, &str)> = span_view.links().map(|link| (link.trace_id(), link.span_id())).collect();
+            let expected_links: Vec<(&str, &str)> = span
+                .links
+                .iter()
+                .flatten()
+                .map(|link| (link.trace_id.as_ref(), link.span_id.as_ref()))
+                .collect();
+            assert_eq!(links, expected_links);
+        }
+    }
+
+    #[test]
+    fn materialize_restores_the_spans() {
+        let spans = spans(20);
+        let batch = encode(&ColumnOrientedEncoder, &spans);
+
+        let decoded = batch.view().unwrap().materialize();
+        assert_eq!(decoded.len(), spans.len());
+        for (span, decoded_span) in spans.iter().zip(decoded.iter()) {
+            assert_eq!(serde_json::to_value(decoded_span).unwrap(), serde_json::to_value(span).unwrap());
+        }
+    }
+
+    #[test]
+    fn events_of_a_span_do_not_have_to_be_contiguous() {
+        let spans = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("trace_id", DataType::Binary, false),
+                Field::new("span_id", DataType::Binary, false),
+                Field::new("name", DataType::Utf8, false),
+                Field::new("start_time_unix_nano", DataType::UInt64, false),
+            ])),
+            vec![
+                Arc::new(BinaryArray::from(vec![b"t".as_ref(), b"t".as_ref(), b"t".as_ref()])) as ArrayRef,
+                Arc::new(BinaryArray::from(vec![b"a".as_ref(), b"b".as_ref(), b"c".as_ref()])),
+                Arc::new(StringArray::from(vec!["a", "b", "c"])),
+                Arc::new(UInt64Array::from(vec![0, 0, 0])),
+            ],
+        )
+        .unwrap();
+        let events = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("id", DataType::UInt32, false),
+                Field::new("time_unix_nano", DataType::UInt64, false),
+                Field::new("name", DataType::Utf8, false),
+            ])),
+            vec![
+                Arc::new(UInt32Array::from(vec![1, 0, 1, 0, 1])) as ArrayRef,
+                Arc::new(UInt64Array::from(vec![1, 2, 3, 4, 5])),
+                Arc::new(StringArray::from(vec!["b1", "a1", "b2", "a2", "b3"])),
+            ],
+        )
+        .unwrap();
+        let batch = TraceBatch {
+            spans,
+            events: Some(events),
+            links: None,
+        };
+
+        let view = batch.view().unwrap();
+        let event_names: Vec<Vec<&str>> = view.spans().map(|span| span.events().map(|event| event.name()).collect()).collect();
+        assert_eq!(event_names, vec![vec!["a1", "a2"], vec!["b1", "b2", "b3"], vec![]]);
+        assert_eq!(view.span(0).links().count(), 0);
+    }
+
+    #[test]
+    fn dictionary_keys_out_of_range_read_as_nulls() {
+        let keys = UInt8Array::from(vec![Some(1), Some(2), None]);
+        let values = StringArray::from(vec!["a", "b"]);
+        let column = StringColumn::UInt8Dictionary(&keys, &values);
+
+        assert_eq!(column.value(0), Some("b"));
+        assert_eq!(column.value(1), None);
+        assert_eq!(column.value(2), None);
+    }
+}
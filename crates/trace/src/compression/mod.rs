@@ -0,0 +1,74 @@
+use std::io::{self, Read, Write};
+
+use clap::ArgEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+use serde::Serialize;
+
+#[derive(ArgEnum, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Lz4,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn compress(&self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(buf.to_vec()),
+            Compression::Lz4 => Ok(compress_prepend_size(buf)),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(buf)?;
+                encoder.finish()
+            }
+            Compression::Zstd => zstd::encode_all(buf, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    pub fn decompress(&self, buf: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(buf.to_vec()),
+            // lz4_flex is built without `std`, its error type doesn't implement `std::error::Error`.
+            Compression::Lz4 => decompress_size_prepended(buf).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("lz4: {:?}", error))),
+            Compression::Gzip => {
+                let mut decompressed_buf = Vec::new();
+                GzDecoder::new(buf).read_to_end(&mut decompressed_buf)?;
+                Ok(decompressed_buf)
+            }
+            Compression::Zstd => zstd::decode_all(buf),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Compression::None => "none",
+            Compression::Lz4 => "lz4",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_and_corrupted_input() {
+        let buf = b"trace trace trace trace trace trace".to_vec();
+        for compression in [Compression::None, Compression::Lz4, Compression::Gzip, Compression::Zstd].iter() {
+            let compressed_buf = compression.compress(&buf).unwrap();
+            assert_eq!(compression.decompress(&compressed_buf).unwrap(), buf);
+        }
+        for compression in [Compression::Lz4, Compression::Gzip, Compression::Zstd].iter() {
+            assert!(compression.decompress(&[0xff; 3]).is_err(), "{} should reject corrupted input", compression);
+        }
+    }
+}
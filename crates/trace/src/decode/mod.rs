@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::{Clap, ValueHint};
+use comfy_table::Table;
+use itertools::Itertools;
+
+use common::Span;
+
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::TraceBatch;
+use crate::arrow::{serialize_column_oriented_data_source, EncodingOptions};
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+#[derive(Clap, Debug)]
+pub struct DecodeOpt {
+    /// Maximum batch size
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Span attribute read for every span (in addition to trace id, name, duration, events and links)
+    #[clap(short, long)]
+    pub attribute: Option<String>,
+
+    /// String columns with a cardinality/row count ratio below this threshold are dictionary encoded
+    #[clap(long, default_value = "0.2")]
+    pub dictionary_threshold: f64,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+// What a query-side consumer reads from every span, summed so both access paths can be checked against each other.
+#[derive(Debug, Default, PartialEq)]
+struct ScanSummary {
+    spans: usize,
+    trace_id_bytes: usize,
+    name_bytes: usize,
+    total_duration_ns: u64,
+    attribute_hits: usize,
+    events: usize,
+    links: usize,
+}
+
+#[derive(Debug, Default)]
+struct DecodeResult {
+    span_count: usize,
+    decode_and_view_ns: u128,
+    decode_and_materialization_ns: u128,
+}
+
+// Compares reading decoded OTLP-Arrow batches through `TraceBatchView` with materializing them into `common::Span`s.
+pub fn decode(opt: &DecodeOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let options = EncodingOptions {
+        dictionary_threshold: opt.dictionary_threshold,
+        ..Default::default()
+    };
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File",
+        "Spans",
+        "Decode + view (ms)",
+        "Decode + materialization (ms)",
+        "Decode + view ns/span",
+        "Decode + materialization ns/span",
+    ]);
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Processing file '{}'...", filename);
+        let mut result = DecodeResult::default();
+        let mut stats_reporter = StatisticsReporter::noop();
+
+        for chunk in importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .chunks(opt.batch_size)
+            .into_iter()
+        {
+            let spans: Vec<Span> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+            let buf = serialize_column_oriented_data_source(stats_reporter.next_batch(), &spans, &options, &mut BenchmarkResult::new())?;
+            let buf = bytes::Bytes::from(buf);
+
+            let start = Instant::now();
+            let view_summary = scan_view(buf.clone(), opt.attribute.as_deref())?;
+            result.decode_and_view_ns += (Instant::now() - start).as_nanos();
+
+            let start = Instant::now();
+            let materialized_summary = scan_materialized(buf, opt.attribute.as_deref())?;
+            result.decode_and_materialization_ns += (Instant::now() - start).as_nanos();
+
+            if view_summary != materialized_summary {
+                return Err(format!(
+                    "view and materialized spans differ in '{}': {:?} vs {:?}",
+                    filename, view_summary, materialized_summary
+                )
+                .into());
+            }
+            result.span_count += view_summary.spans;
+        }
+
+        let span_count = result.span_count.max(1) as f64;
+        table.add_row(vec![
+            filename,
+            result.span_count.to_string(),
+            format!("{:.3}", result.decode_and_view_ns as f64 / 1000000.0),
+            format!("{:.3}", result.decode_and_materialization_ns as f64 / 1000000.0),
+            format!("{:.1}", result.decode_and_view_ns as f64 / span_count),
+            format!("{:.1}", result.decode_and_materialization_ns as f64 / span_count),
+        ]);
+        eprintln!("DONE.");
+    }
+
+    println!("{}", table);
+    Ok(())
+}
+
+fn scan_view(buf: bytes::Bytes, attribute: Option<&str>) -> Result<ScanSummary, Box<dyn std::error::Error>> {
+    let mut summary = ScanSummary::default();
+
+    for batch in TraceBatch::decode(buf)? {
+        let view = batch.view()?;
+
+        for span in view.spans() {
+            summary.spans += 1;
+            summary.trace_id_bytes += span.trace_id().len();
+            summary.name_bytes += span.name().len();
+            summary.total_duration_ns += span.duration_ns().unwrap_or(0);
+            if attribute.and_then(|key| span.attribute(key)).is_some() {
+                summary.attribute_hits += 1;
+            }
+            summary.events += span.events().count();
+            summary.links += span.links().count();
+        }
+    }
+
+    Ok(summary)
+}
+
+fn scan_materialized(buf: bytes::Bytes, attribute: Option<&str>) -> Result<ScanSummary, Box<dyn std::error::Error>> {
+    let mut summary = ScanSummary::default();
+
+    for batch in TraceBatch::decode(buf)? {
+        for span in batch.view()?.materialize() {
+            summary.spans += 1;
+            summary.trace_id_bytes += span.trace_id.len();
+            summary.name_bytes += span.name.len();
+            summary.total_duration_ns += span
+                .end_time_unix_nano
+                .map(|end_time| end_time.saturating_sub(span.start_time_unix_nano))
+                .unwrap_or(0);
+            if attribute.and_then(|key| span.attributes.as_ref()?.get(key)).is_some() {
+                summary.attribute_hits += 1;
+            }
+            summary.events += span.events.as_ref().map(|events| events.len()).unwrap_or(0);
+            summary.links += span.links.as_ref().map(|links| links.len()).unwrap_or(0);
+        }
+    }
+
+    Ok(summary)
+}
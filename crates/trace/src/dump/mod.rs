@@ -0,0 +1,284 @@
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+use arrow::error::ArrowError;
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use clap::{ArgEnum, Clap, ValueHint};
+use comfy_table::Table;
+use itertools::Itertools;
+
+use common::Span;
+
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::TraceBatch;
+use crate::arrow::{deserialize, ArrowTraceEncoder, ColumnOrientedEncoder, EncodingOptions, RowOrientedEncoder};
+use crate::BenchmarkResult;
+
+const ENCODERS: [&dyn ArrowTraceEncoder; 2] = [&RowOrientedEncoder, &ColumnOrientedEncoder];
+
+#[derive(Clap, Debug)]
+pub struct ReplayOpt {
+    /// Number of untimed warm-up iterations per batch
+    #[clap(long, default_value = "0")]
+    pub warmup: usize,
+
+    /// Number of timed repetitions per batch (times are averaged over the repetitions)
+    #[clap(long, default_value = "1")]
+    pub repetitions: usize,
+
+    /// Directory created with the `--dump-dir` option of the benchmark
+    #[clap(name = "DIR", parse(from_os_str), value_hint = ValueHint::DirPath)]
+    pub dump_dir: PathBuf,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum DumpEncoder {
+    ArrowRowOriented,
+    ArrowColumnOriented,
+}
+
+impl DumpEncoder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DumpEncoder::ArrowRowOriented => RowOrientedEncoder.name(),
+            DumpEncoder::ArrowColumnOriented => ColumnOrientedEncoder.name(),
+        }
+    }
+}
+
+// Writes the span, event and link record batches produced by both Arrow encoders for a batch of spans as Arrow IPC files
+// (Feather v2): `<dump dir>/<input file>/<encoder>/batch-<n>/{spans,events,links}.arrow`, where `<input file>` is the
+// absolute path of the input file (without root), so input files with the same name don't share a directory. The spans
+// are encoded again, outside of the timed phases of the benchmark.
+pub fn dump_batch(dump_dir: &Path, file: &Path, batch_index: usize, spans: &[Span], options: &EncodingOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let file_dir = input_file_dir(dump_dir, file)?;
+    let mut stats_reporter = StatisticsReporter::noop();
+
+    for encoder in ENCODERS.iter() {
+        let buf = encoder.encode(stats_reporter.next_batch(), spans, options, &mut BenchmarkResult::new())?;
+        let batch_dir = file_dir.join(encoder.name()).join(format!("batch-{:05}", batch_index));
+        fs::create_dir_all(&batch_dir)?;
+
+        for batch in TraceBatch::decode(buf.into())? {
+            write_ipc_file(&batch_dir.join("spans.arrow"), &batch.spans)?;
+            if let Some(events) = &batch.events {
+                write_ipc_file(&batch_dir.join("events.arrow"), events)?;
+            }
+            if let Some(links) = &batch.links {
+                write_ipc_file(&batch_dir.join("links.arrow"), links)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn input_file_dir(dump_dir: &Path, file: &Path) -> std::io::Result<PathBuf> {
+    let file = file.canonicalize()?;
+    let relative_path: PathBuf = file.components().filter(|component| matches!(component, Component::Normal(_))).collect();
+    Ok(dump_dir.join(relative_path))
+}
+
+fn write_ipc_file(path: &Path, batch: &RecordBatch) -> Result<(), ArrowError> {
+    let mut writer = FileWriter::try_new(File::create(path)?, batch.schema().as_ref())?;
+    writer.write(batch)?;
+    writer.finish()
+}
+
+// Reads the span, event and link record batches of a `batch-<n>` directory, `None` if it doesn't contain a span file.
+pub fn read_batch_dir(batch_dir: &Path) -> Result<Option<TraceBatch>, ArrowError> {
+    let spans = match read_ipc_file(&batch_dir.join("spans.arrow"))? {
+        Some(spans) => spans,
+        None => return Ok(None),
+    };
+    Ok(Some(TraceBatch {
+        spans,
+        events: read_ipc_file(&batch_dir.join("events.arrow"))?,
+        links: read_ipc_file(&batch_dir.join("links.arrow"))?,
+    }))
+}
+
+// Returns all the `batch-<n>` directories below a dump directory (the dump directory itself, an input file directory or
+// an encoder directory), in a stable order.
+pub fn find_batch_dirs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if dir.join("spans.arrow").is_file() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
+    let mut batch_dirs = vec![];
+    for sub_dir in sorted_dirs(dir)? {
+        batch_dirs.extend(find_batch_dirs(&sub_dir)?);
+    }
+    Ok(batch_dirs)
+}
+
+// Same as `find_batch_dirs`, restricted to the batches of one encoder (a dump has the batches of every encoder, loading
+// them all would load every span several times).
+pub fn find_encoder_batch_dirs(dir: &Path, encoder: DumpEncoder) -> std::io::Result<Vec<PathBuf>> {
+    Ok(find_batch_dirs(dir)?
+        .into_iter()
+        .filter(|batch_dir| {
+            let encoder_dir = batch_dir.parent().and_then(Path::file_name);
+            encoder_dir.is_some_and(|encoder_dir| encoder_dir == encoder.name())
+        })
+        .collect())
+}
+
+fn read_ipc_file(path: &Path) -> Result<Option<RecordBatch>, ArrowError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    FileReader::try_new(File::open(path)?)?.next().transpose()
+}
+
+#[derive(Debug, Default)]
+struct ReplayResult {
+    batch_count: usize,
+    span_count: usize,
+    deserialization_ns: u128,
+    materialization_ns: u128,
+}
+
+// Re-runs the deserialization phase of the benchmark (and the materialization of the decoded batches into spans) on
+// the dumped batches. The OTLP-Arrow payloads are rebuilt from the IPC files before the timed phases.
+pub fn replay(opt: &ReplayOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let repetitions = opt.repetitions.max(1);
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File",
+        "Encoder",
+        "Batches",
+        "Spans",
+        "Deserialization (ms)",
+        "Materialization (ms)",
+        "Deserialization ns/span",
+    ]);
+
+    let batch_dirs = find_batch_dirs(&opt.dump_dir)?;
+    for (encoder_dir, batch_dirs) in batch_dirs.iter().group_by(|batch_dir| batch_dir.parent().unwrap_or(&opt.dump_dir)).into_iter() {
+        let mut result = ReplayResult::default();
+
+        for batch_dir in batch_dirs {
+            let batch = match read_batch_dir(batch_dir)? {
+                Some(batch) => batch,
+                None => continue,
+            };
+            let buf = batch.encode()?;
+            let payload = bytes::Bytes::from(buf.clone());
+
+            for _ in 0..opt.warmup {
+                deserialize(buf.clone(), &mut BenchmarkResult::new());
+                materialize(payload.clone())?;
+            }
+
+            for _ in 0..repetitions {
+                let mut bench_result = BenchmarkResult::new();
+                deserialize(buf.clone(), &mut bench_result);
+                result.deserialization_ns += bench_result.total_buffer_deserialization_ns / repetitions as u128;
+
+                let start = Instant::now();
+                materialize(payload.clone())?;
+                result.materialization_ns += (Instant::now() - start).as_nanos() / repetitions as u128;
+            }
+
+            result.batch_count += 1;
+            result.span_count += batch.spans.num_rows();
+        }
+
+        let file_dir = encoder_dir.parent().unwrap_or(&opt.dump_dir);
+        table.add_row(vec![
+            file_dir.strip_prefix(&opt.dump_dir).unwrap_or(file_dir).display().to_string(),
+            encoder_dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            result.batch_count.to_string(),
+            result.span_count.to_string(),
+            format!("{:.3}", result.deserialization_ns as f64 / 1000000.0),
+            format!("{:.3}", result.materialization_ns as f64 / 1000000.0),
+            format!("{:.1}", result.deserialization_ns as f64 / result.span_count.max(1) as f64),
+        ]);
+    }
+
+    println!("{}", table);
+    Ok(())
+}
+
+fn materialize(payload: bytes::Bytes) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut span_count = 0;
+    for batch in TraceBatch::decode(payload)? {
+        span_count += batch.view()?.materialize().len();
+    }
+    Ok(span_count)
+}
+
+fn sorted_dirs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::arrow::tests::{assert_same_batches, encode, spans};
+
+    use super::*;
+
+    fn to_values(spans: &[Span]) -> Vec<Value> {
+        spans.iter().map(|span| serde_json::to_value(span).unwrap()).collect()
+    }
+
+    #[test]
+    fn dumped_batches_are_read_back() {
+        let dir = std::env::temp_dir().join(format!("trace-dump-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dump_dir = dir.join("dump");
+        let spans = spans(8);
+
+        // two input files with the same name in different directories
+        let inputs = [(dir.join("a").join("spans.json"), &spans[..4]), (dir.join("b").join("spans.json"), &spans[4..])];
+        for (input, input_spans) in inputs.iter() {
+            fs::create_dir_all(input.parent().unwrap()).unwrap();
+            File::create(input).unwrap();
+            dump_batch(&dump_dir, input, 0, input_spans, &EncodingOptions::default()).unwrap();
+        }
+
+        let batch_dirs = find_batch_dirs(&dump_dir).unwrap();
+        assert_eq!(batch_dirs.len(), inputs.len() * ENCODERS.len());
+        for (input, input_spans) in inputs.iter() {
+            let file_dir = input_file_dir(&dump_dir, input).unwrap();
+            for encoder in ENCODERS.iter() {
+                let batch_dir = file_dir.join(encoder.name()).join("batch-00000");
+                assert!(batch_dirs.contains(&batch_dir));
+
+                let batch = read_batch_dir(&batch_dir).unwrap().unwrap();
+                let expected = encode(*encoder, input_spans);
+                assert_same_batches("span", Some(&expected.spans), Some(&batch.spans));
+                assert_same_batches("event", expected.events.as_ref(), batch.events.as_ref());
+                assert_same_batches("link", expected.links.as_ref(), batch.links.as_ref());
+
+                let mut decoded = TraceBatch::decode(batch.encode().unwrap().into()).unwrap();
+                assert_eq!(decoded.len(), 1);
+                assert_eq!(to_values(&decoded.remove(0).view().unwrap().materialize()), to_values(input_spans));
+            }
+        }
+
+        for encoder in [DumpEncoder::ArrowRowOriented, DumpEncoder::ArrowColumnOriented] {
+            let encoder_batch_dirs = find_encoder_batch_dirs(&dump_dir, encoder).unwrap();
+            assert_eq!(encoder_batch_dirs.len(), inputs.len());
+            assert!(encoder_batch_dirs.iter().all(|batch_dir| batch_dir.parent().unwrap().ends_with(encoder.name())));
+            // a batch directory of the other encoder is not selected
+            let other_batch_dir = batch_dirs.iter().find(|batch_dir| !encoder_batch_dirs.contains(batch_dir)).unwrap();
+            assert!(find_encoder_batch_dirs(other_batch_dir, encoder).unwrap().is_empty());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::{ArgEnum, Clap, ValueHint};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+
+use common::{Attributes, Event, Link, Span, SpanKind, Status, StatusCode};
+
+const BASE_TIME_UNIX_NANO: u64 = 1626371667388918000;
+const TRACE_INTERVAL_NANO: u64 = 1_000_000_000;
+const ROOT_MAX_DURATION_NANO: u64 = 500_000_000;
+
+#[derive(Clap, Debug)]
+pub struct GenerateOpt {
+    /// Number of traces to generate
+    #[clap(short, long, default_value = "100")]
+    pub traces: usize,
+
+    /// Number of spans per trace
+    #[clap(long, default_value = "10")]
+    pub spans_per_trace: usize,
+
+    /// Maximum depth of a trace tree (1 = only root spans)
+    #[clap(long, default_value = "4")]
+    pub max_depth: usize,
+
+    /// Number of distinct span names
+    #[clap(long, default_value = "20")]
+    pub span_names: usize,
+
+    /// Number of attribute keys per span
+    #[clap(long, default_value = "20")]
+    pub attribute_keys: usize,
+
+    /// Probability for an attribute key to be missing on a span, event or link [0..1]
+    #[clap(long, default_value = "0.5")]
+    pub sparsity: f64,
+
+    /// Number of distinct values per attribute key
+    #[clap(long, default_value = "100")]
+    pub cardinality: usize,
+
+    /// Attribute value types, assigned to the attribute keys in a round-robin fashion
+    #[clap(long, arg_enum, use_delimiter = true, default_values = &["string", "int", "float", "bool"])]
+    pub value_types: Vec<ValueType>,
+
+    /// Average number of events per span
+    #[clap(long, default_value = "0.5")]
+    pub event_rate: f64,
+
+    /// Number of attribute keys per event
+    #[clap(long, default_value = "3")]
+    pub event_attribute_keys: usize,
+
+    /// Average number of links per span
+    #[clap(long, default_value = "0.1")]
+    pub link_rate: f64,
+
+    /// Number of attribute keys per link
+    #[clap(long, default_value = "2")]
+    pub link_attribute_keys: usize,
+
+    /// Probability for a span to have an error status [0..1]
+    #[clap(long, default_value = "0")]
+    pub error_rate: f64,
+
+    /// Seed of the random generator (same seed and options => same output)
+    #[clap(long, default_value = "42")]
+    pub seed: u64,
+
+    /// Output file (stdout if not specified)
+    #[clap(short, long, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+    String,
+    Int,
+    Float,
+    Bool,
+}
+
+struct TreeNode {
+    span_id: String,
+    depth: usize,
+    start_time_unix_nano: u64,
+    end_time_unix_nano: u64,
+}
+
+pub fn generate(opt: &GenerateOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if opt.value_types.is_empty() {
+        return Err("at least one value type must be specified".into());
+    }
+    if !(0.0..=1.0).contains(&opt.sparsity) {
+        return Err("sparsity must be in [0..1]".into());
+    }
+
+    let writer: Box<dyn Write> = match &opt.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut writer = BufWriter::new(writer);
+    write_spans(opt, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_spans<W: Write>(opt: &GenerateOpt, writer: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = StdRng::seed_from_u64(opt.seed);
+    let mut previous_trace: Vec<(String, String)> = vec![];
+
+    for trace_index in 0..opt.traces {
+        let trace_id = format!("{:032x}", rng.gen::<u128>());
+        let trace_start_time = BASE_TIME_UNIX_NANO + trace_index as u64 * TRACE_INTERVAL_NANO;
+        let mut nodes: Vec<TreeNode> = Vec::with_capacity(opt.spans_per_trace);
+
+        for _ in 0..opt.spans_per_trace {
+            let span_id = format!("{:016x}", rng.gen::<u64>());
+            let candidates: Vec<usize> = (0..nodes.len()).filter(|i| nodes[*i].depth + 1 < opt.max_depth).collect();
+            let parent = if candidates.is_empty() {
+                None
+            } else {
+                Some(candidates[rng.gen_range(0..candidates.len())])
+            };
+
+            let (depth, start_time_unix_nano, end_time_unix_nano) = match parent {
+                None => {
+                    let start_time = trace_start_time + rng.gen_range(0..TRACE_INTERVAL_NANO - ROOT_MAX_DURATION_NANO);
+                    (0, start_time, start_time + rng.gen_range(1..=ROOT_MAX_DURATION_NANO))
+                }
+                Some(parent) => {
+                    let parent = &nodes[parent];
+                    let parent_duration = parent.end_time_unix_nano - parent.start_time_unix_nano;
+                    let start_time = parent.start_time_unix_nano + rng.gen_range(0..=parent_duration / 2);
+                    (
+                        parent.depth + 1,
+                        start_time,
+                        start_time + rng.gen_range(0..=parent.end_time_unix_nano - start_time),
+                    )
+                }
+            };
+
+            let events = generate_events(opt, &mut rng, start_time_unix_nano, end_time_unix_nano);
+            // the random draw is skipped when errors are disabled, so the spans generated for a given seed are unchanged
+            let status = if opt.error_rate > 0.0 && rng.gen::<f64>() < opt.error_rate {
+                Some(Status {
+                    code: Some(StatusCode::Error),
+                    message: Some("generated error".into()),
+                })
+            } else {
+                None
+            };
+            let links = generate_links(opt, &mut rng, &previous_trace);
+
+            let span = Span {
+                trace_id: trace_id.clone().into(),
+                span_id: span_id.clone().into(),
+                trace_state: None,
+                parent_span_id: parent.map(|parent| nodes[parent].span_id.clone().into()),
+                name: format!("operation_{}", rng.gen_range(0..opt.span_names.max(1))).into(),
+                kind: SpanKind::from_i64(rng.gen_range(1..=5)),
+                start_time_unix_nano,
+                end_time_unix_nano: Some(end_time_unix_nano),
+                attributes: Some(generate_attributes(opt, &mut rng, "attribute", opt.attribute_keys)),
+                dropped_attributes_count: None,
+                events: if events.is_empty() { None } else { Some(events) },
+                dropped_events_count: None,
+                links: if links.is_empty() { None } else { Some(links) },
+                dropped_links_count: None,
+                status,
+            };
+
+            // attributes are hash maps, sorting the keys keeps the output byte-for-byte reproducible
+            let mut value = serde_json::to_value(&span)?;
+            value.sort_all_objects();
+            serde_json::to_writer(&mut *writer, &value)?;
+            writer.write_all(b"\n")?;
+
+            nodes.push(TreeNode {
+                span_id,
+                depth,
+                start_time_unix_nano,
+                end_time_unix_nano,
+            });
+        }
+
+        // links always target spans of the previous trace
+        previous_trace = nodes.into_iter().map(|node| (trace_id.clone(), node.span_id)).collect();
+    }
+
+    Ok(())
+}
+
+fn generate_attributes(opt: &GenerateOpt, rng: &mut StdRng, key_prefix: &str, key_count: usize) -> Attributes {
+    let mut attributes = Attributes::new();
+
+    for key_index in 0..key_count {
+        if rng.gen::<f64>() < opt.sparsity {
+            continue;
+        }
+        let value_type = opt.value_types[key_index % opt.value_types.len()];
+        let value_index = rng.gen_range(0..opt.cardinality.max(1));
+        let value = match value_type {
+            ValueType::String => Value::String(format!("value_{}_{}", key_index, value_index)),
+            ValueType::Int => Value::from(value_index as i64),
+            ValueType::Float => Value::from(value_index as f64 * 1.5),
+            ValueType::Bool => Value::Bool(value_index % 2 == 0),
+        };
+        attributes.insert(format!("{}_{}", key_prefix, key_index), value);
+    }
+
+    attributes
+}
+
+fn generate_events(opt: &GenerateOpt, rng: &mut StdRng, start_time_unix_nano: u64, end_time_unix_nano: u64) -> Vec<Event<'static>> {
+    (0..sample_count(rng, opt.event_rate))
+        .map(|_| Event {
+            time_unix_nano: rng.gen_range(start_time_unix_nano..=end_time_unix_nano),
+            name: format!("event_{}", rng.gen_range(0..opt.span_names.max(1))).into(),
+            attributes: generate_attributes(opt, rng, "event_attribute", opt.event_attribute_keys),
+            dropped_attributes_count: None,
+        })
+        .collect()
+}
+
+fn generate_links(opt: &GenerateOpt, rng: &mut StdRng, targets: &[(String, String)]) -> Vec<Link<'static>> {
+    let link_count = sample_count(rng, opt.link_rate);
+    if targets.is_empty() {
+        return vec![];
+    }
+
+    (0..link_count)
+        .map(|_| {
+            let (trace_id, span_id) = &targets[rng.gen_range(0..targets.len())];
+            Link {
+                trace_id: trace_id.clone().into(),
+                span_id: span_id.clone().into(),
+                trace_state: None,
+                attributes: generate_attributes(opt, rng, "link_attribute", opt.link_attribute_keys),
+                dropped_attributes_count: None,
+            }
+        })
+        .collect()
+}
+
+// Returns floor(rate) plus one more with a probability equal to the fractional part of the rate.
+fn sample_count(rng: &mut StdRng, rate: f64) -> usize {
+    let rate = rate.max(0.0);
+    let mut count = rate.floor() as usize;
+    if rng.gen::<f64>() < rate.fract() {
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Clap;
+
+    use crate::generator::{write_spans, GenerateOpt};
+
+    fn generate(seed: &str) -> Vec<u8> {
+        let opt = GenerateOpt::parse_from(["generate", "--traces", "5", "--error-rate", "0.2", "--link-rate", "0.5", "--seed", seed]);
+        let mut output = vec![];
+        write_spans(&opt, &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_output() {
+        let output = generate("7");
+        assert_eq!(String::from_utf8_lossy(&output).lines().count(), 50);
+        assert_eq!(output, generate("7"));
+        assert_ne!(output, generate("8"));
+    }
+}
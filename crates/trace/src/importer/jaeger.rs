@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use common::{Attributes, Event, Link, Span};
+
+use crate::importer::{span_kind, status_from_tags};
+
+#[derive(Deserialize, Debug)]
+struct JaegerTrace {
+    spans: Vec<JaegerSpan>,
+    #[serde(default)]
+    processes: HashMap<String, JaegerProcess>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JaegerSpan {
+    #[serde(rename = "traceID")]
+    trace_id: String,
+    #[serde(rename = "spanID")]
+    span_id: String,
+    #[serde(rename = "parentSpanID")]
+    parent_span_id: Option<String>,
+    operation_name: String,
+    #[serde(default)]
+    references: Vec<JaegerReference>,
+    start_time: u64,
+    duration: Option<u64>,
+    #[serde(default)]
+    tags: Vec<JaegerKeyValue>,
+    #[serde(default)]
+    logs: Vec<JaegerLog>,
+    #[serde(rename = "processID")]
+    process_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JaegerReference {
+    ref_type: String,
+    #[serde(rename = "traceID")]
+    trace_id: String,
+    #[serde(rename = "spanID")]
+    span_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct JaegerKeyValue {
+    key: String,
+    #[serde(rename = "type", default)]
+    value_type: Option<String>,
+    value: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct JaegerLog {
+    timestamp: u64,
+    #[serde(default)]
+    fields: Vec<JaegerKeyValue>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JaegerProcess {
+    service_name: String,
+    #[serde(default)]
+    tags: Vec<JaegerKeyValue>,
+}
+
+// Payloads are UI exports (`{"data": [trace, ...]}`), arrays of traces or traces. The reader stops at the first invalid
+// payload.
+pub fn read_spans<'a, R: Read>(reader: R) -> impl Iterator<Item = Result<Span<'a>, serde_json::Error>> {
+    serde_json::Deserializer::from_reader(reader)
+        .into_iter::<Value>()
+        .map(|payload| payload.and_then(payload_traces))
+        .flat_map(|traces| match traces {
+            Ok(traces) => traces.into_iter().flat_map(trace_spans).map(Ok).collect::<Vec<_>>(),
+            Err(error) => vec![Err(error)],
+        })
+}
+
+// The shape of the payload is checked first so an invalid span is reported with its own error (e.g. a missing field)
+// instead of the error of an untagged enum matching none of the shapes.
+fn payload_traces(payload: Value) -> Result<Vec<JaegerTrace>, serde_json::Error> {
+    match payload {
+        Value::Object(mut export) if export.contains_key("data") => serde_json::from_value(export.remove("data").unwrap_or_default()),
+        Value::Array(_) => serde_json::from_value(payload),
+        _ => Ok(vec![serde_json::from_value(payload)?]),
+    }
+}
+
+fn trace_spans<'a>(trace: JaegerTrace) -> Vec<Span<'a>> {
+    let processes = trace.processes;
+    trace
+        .spans
+        .into_iter()
+        .map(|span| {
+            let process = span.process_id.as_ref().and_then(|process_id| processes.get(process_id));
+            to_span(span, process)
+        })
+        .collect()
+}
+
+fn to_span<'a>(span: JaegerSpan, process: Option<&JaegerProcess>) -> Span<'a> {
+    let mut attributes = Attributes::new();
+    let mut kind = None;
+
+    if let Some(process) = process {
+        attributes.insert("service.name".into(), Value::String(process.service_name.clone()));
+        for tag in &process.tags {
+            attributes.insert(tag.key.clone(), tag_value(tag));
+        }
+    }
+    for tag in &span.tags {
+        if tag.key == "span.kind" {
+            kind = tag.value.as_str().and_then(span_kind);
+        } else {
+            attributes.insert(tag.key.clone(), tag_value(tag));
+        }
+    }
+
+    let status = status_from_tags(&mut attributes);
+
+    // The first CHILD_OF reference within the same trace is the parent, every other reference becomes a link.
+    let mut parent_span_id = span.parent_span_id.filter(|parent_span_id| !parent_span_id.is_empty());
+    let mut links = vec![];
+    for reference in span.references {
+        if parent_span_id.is_none() && reference.ref_type == "CHILD_OF" && reference.trace_id == span.trace_id {
+            parent_span_id = Some(reference.span_id);
+            continue;
+        }
+        if parent_span_id.as_ref() == Some(&reference.span_id) && reference.trace_id == span.trace_id {
+            continue;
+        }
+        let mut link_attributes = Attributes::new();
+        link_attributes.insert("opentracing.ref_type".into(), Value::String(reference.ref_type.to_ascii_lowercase()));
+        links.push(Link {
+            trace_id: reference.trace_id.into(),
+            span_id: reference.span_id.into(),
+            trace_state: None,
+            attributes: link_attributes,
+            dropped_attributes_count: None,
+        });
+    }
+
+    let events: Vec<Event> = span
+        .logs
+        .into_iter()
+        .map(|log| {
+            let mut name = "log".to_string();
+            let mut attributes = Attributes::new();
+            for field in &log.fields {
+                match (field.key.as_str(), field.value.as_str()) {
+                    ("event", Some(event)) => name = event.to_string(),
+                    _ => {
+                        attributes.insert(field.key.clone(), tag_value(field));
+                    }
+                }
+            }
+            Event {
+                time_unix_nano: log.timestamp * 1000,
+                name: name.into(),
+                attributes,
+                dropped_attributes_count: None,
+            }
+        })
+        .collect();
+
+    let start_time_unix_nano = span.start_time * 1000;
+
+    Span {
+        trace_id: span.trace_id.into(),
+        span_id: span.span_id.into(),
+        trace_state: None,
+        parent_span_id: parent_span_id.map(Into::into),
+        name: span.operation_name.into(),
+        kind,
+        start_time_unix_nano,
+        end_time_unix_nano: span.duration.map(|duration| start_time_unix_nano + duration * 1000),
+        attributes: if attributes.is_empty() { None } else { Some(attributes) },
+        dropped_attributes_count: None,
+        events: if events.is_empty() { None } else { Some(events) },
+        dropped_events_count: None,
+        links: if links.is_empty() { None } else { Some(links) },
+        dropped_links_count: None,
+        status,
+    }
+}
+
+// Jaeger exports usually carry native JSON values, but some exporters stringify numbers.
+fn tag_value(kv: &JaegerKeyValue) -> Value {
+    match (kv.value_type.as_deref(), &kv.value) {
+        (Some("int64"), Value::String(value)) => value.parse::<i64>().map(Value::from).unwrap_or_else(|_| kv.value.clone()),
+        (Some("float64"), Value::String(value)) => value.parse::<f64>().map(Value::from).unwrap_or_else(|_| kv.value.clone()),
+        (Some("bool"), Value::String(value)) => value.parse::<bool>().map(Value::from).unwrap_or_else(|_| kv.value.clone()),
+        _ => kv.value.clone(),
+    }
+}
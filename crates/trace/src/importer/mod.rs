@@ -0,0 +1,190 @@
+use std::io::Read;
+use std::path::Path;
+
+use clap::ArgEnum;
+use serde_json::Value;
+
+use common::{Attributes, Span, SpanKind, Status, StatusCode};
+
+mod jaeger;
+mod zipkin;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    /// Line delimited JSON spans (see the sample printed when no file is provided)
+    Json,
+    /// Zipkin v2 JSON (array of spans or array of traces)
+    Zipkin,
+    /// Jaeger UI JSON export
+    Jaeger,
+}
+
+// The strings of the spans are copied from the reader. The iterator stops after the first span (or Zipkin/Jaeger payload)
+// that fails to deserialize.
+pub fn read_spans<'a, R: Read + 'a>(format: InputFormat, reader: R) -> Box<dyn Iterator<Item = Result<Span<'static>, serde_json::Error>> + 'a> {
+    match format {
+        InputFormat::Json => Box::new(serde_json::Deserializer::from_reader(reader).into_iter::<Span>()),
+        InputFormat::Zipkin => Box::new(zipkin::read_spans(reader)),
+        InputFormat::Jaeger => Box::new(jaeger::read_spans(reader)),
+    }
+}
+
+// The strings of the line delimited JSON spans are borrowed from the input when they don't contain escape sequences,
+// the Zipkin and Jaeger spans are converted and own their strings.
+pub fn parse_spans(format: InputFormat, input: &str) -> Box<dyn Iterator<Item = Result<Span<'_>, serde_json::Error>> + '_> {
+    match format {
+        InputFormat::Json => Box::new(serde_json::Deserializer::from_str(input).into_iter::<Span>()),
+        InputFormat::Zipkin => Box::new(zipkin::read_spans(input.as_bytes())),
+        InputFormat::Jaeger => Box::new(jaeger::read_spans(input.as_bytes())),
+    }
+}
+
+// The error message gives the line and column of the invalid span for the line delimited JSON spans and of any JSON
+// syntax error (Zipkin and Jaeger spans are deserialized from their parsed payload).
+pub fn span_error(file: &Path, error: serde_json::Error) -> Box<dyn std::error::Error> {
+    format!("invalid span in '{}': {}", file.display(), error).into()
+}
+
+fn span_kind(kind: &str) -> Option<SpanKind> {
+    match kind.to_ascii_lowercase().as_str() {
+        "internal" => Some(SpanKind::Internal),
+        "server" => Some(SpanKind::Server),
+        "client" => Some(SpanKind::Client),
+        "producer" => Some(SpanKind::Producer),
+        "consumer" => Some(SpanKind::Consumer),
+        _ => None,
+    }
+}
+
+// The OpenTelemetry exporters set the `otel.status_code`/`otel.status_description` tags, older instrumentations only set an
+// `error` tag. These tags are mapped to the span status and removed from the attributes.
+fn status_from_tags<'a>(attributes: &mut Attributes) -> Option<Status<'a>> {
+    let status_code = attributes.remove("otel.status_code");
+    let description = attributes.remove("otel.status_description");
+    let error = attributes.remove("error");
+
+    let code = match status_code.as_ref().and_then(Value::as_str) {
+        Some("OK") => Some(StatusCode::Ok),
+        Some("ERROR") => Some(StatusCode::Error),
+        _ => match &error {
+            None | Some(Value::Bool(false)) => None,
+            Some(Value::String(value)) if value == "false" => None,
+            Some(_) => Some(StatusCode::Error),
+        },
+    };
+    // Zipkin instrumentations usually put the error message in the `error` tag.
+    let message = description
+        .as_ref()
+        .and_then(Value::as_str)
+        .or_else(|| error.as_ref().and_then(Value::as_str).filter(|error| *error != "true"));
+
+    code.map(|code| Status {
+        code: Some(code),
+        message: message.map(|message| message.to_string().into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use common::{Attributes, Span, SpanKind, StatusCode};
+
+    use crate::importer::{parse_spans, InputFormat};
+
+    fn spans(format: InputFormat, input: &str) -> Vec<Span<'_>> {
+        parse_spans(format, input).collect::<Result<_, _>>().unwrap()
+    }
+
+    fn attributes(value: serde_json::Value) -> Option<Attributes> {
+        Some(serde_json::from_value(value).unwrap())
+    }
+
+    fn status<'a>(span: &'a Span) -> Option<(Option<StatusCode>, Option<&'a str>)> {
+        span.status.as_ref().map(|status| (status.code, status.message.as_deref()))
+    }
+
+    #[test]
+    fn zipkin_spans_are_mapped() {
+        let spans = spans(InputFormat::Zipkin, include_str!("../../../../data/zipkin_trace.json"));
+        assert_eq!(spans.len(), 3);
+
+        let server = &spans[0];
+        assert_eq!(server.kind, Some(SpanKind::Server));
+        assert_eq!(server.start_time_unix_nano, 1626371667388918000);
+        assert_eq!(server.end_time_unix_nano, Some(1626371667389038000));
+        assert_eq!(
+            server.attributes,
+            attributes(json!({
+                "http.method": "GET", "http.path": "/api", "service.name": "frontend", "net.host.ip": "192.168.99.1", "net.host.port": 8080,
+                "net.peer.ip": "172.19.0.2", "net.peer.port": 58648
+            }))
+        );
+        // the `error` tag is the status message
+        assert_eq!(status(server), Some((Some(StatusCode::Error), Some("backend unavailable"))));
+        let events = server.events.as_ref().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].time_unix_nano, &*events[0].name), (1626371667388950000, "ws"));
+
+        let client = &spans[1];
+        assert_eq!(client.kind, Some(SpanKind::Client));
+        assert_eq!(client.parent_span_id.as_deref(), Some("bd7a977555f6b982"));
+        assert_eq!(
+            client.attributes,
+            attributes(json!({"service.name": "frontend", "peer.service": "backend", "net.peer.ip": "::1", "net.peer.port": 9000}))
+        );
+        assert_eq!(status(client), Some((Some(StatusCode::Ok), None)));
+
+        // local span without tags, annotations nor duration
+        let local = &spans[2];
+        assert_eq!(local.kind, Some(SpanKind::Internal));
+        assert_eq!(local.end_time_unix_nano, None);
+        assert!(local.attributes.is_none() && local.events.is_none() && local.status.is_none());
+    }
+
+    #[test]
+    fn jaeger_spans_are_mapped() {
+        let spans = spans(InputFormat::Jaeger, include_str!("../../../../data/jaeger_trace.json"));
+        assert_eq!(spans.len(), 2);
+
+        let server = &spans[0];
+        assert_eq!(server.kind, Some(SpanKind::Server));
+        assert_eq!(server.end_time_unix_nano, Some(1626371667389038000));
+        // process tags are span attributes, stringified numbers are parsed
+        assert_eq!(
+            server.attributes,
+            attributes(json!({"service.name": "frontend", "hostname": "host-1", "http.status_code": 500}))
+        );
+        assert_eq!(status(server), Some((Some(StatusCode::Error), None)));
+        let events = server.events.as_ref().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].time_unix_nano, &*events[0].name), (1626371667388950000, "exception"));
+        assert_eq!(Some(events[0].attributes.clone()), attributes(json!({"message": "timeout"})));
+
+        // the CHILD_OF reference is the parent, the other references are links
+        let client = &spans[1];
+        assert_eq!(client.kind, Some(SpanKind::Client));
+        assert_eq!(client.parent_span_id.as_deref(), Some("bd7a977555f6b982"));
+        assert_eq!(status(client), Some((Some(StatusCode::Ok), None)));
+        let links = client.links.as_ref().unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!((&*links[0].trace_id, &*links[0].span_id), ("1f2e3d4c5b6a7988", "0a1b2c3d4e5f6071"));
+        assert_eq!(Some(links[0].attributes.clone()), attributes(json!({"opentracing.ref_type": "follows_from"})));
+    }
+
+    #[test]
+    fn invalid_spans_are_errors() {
+        for (format, input) in [
+            (
+                InputFormat::Json,
+                "{\"trace_id\":\"t1\",\"span_id\":\"s1\",\"name\":\"n\",\"start_time_unix_nano\":1}\n{\"span_id\":\"s2\"}",
+            ),
+            (InputFormat::Zipkin, r#"[{"traceId":"t1","id":"s1"}] [{"id":"s2"}]"#),
+            (InputFormat::Jaeger, r#"{"data":[{"spans":[{"traceID":"t1","spanID":"s1","startTime":1}]}]}"#),
+        ] {
+            let result = parse_spans(format, input).collect::<Result<Vec<_>, _>>();
+            let error = result.err().unwrap_or_else(|| panic!("{:?}", format)).to_string();
+            assert!(error.contains("missing field"), "{:?}: {}", format, error);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use common::{Attributes, Event, Span, SpanKind};
+
+use crate::importer::{span_kind, status_from_tags};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ZipkinSpan {
+    trace_id: String,
+    id: String,
+    parent_id: Option<String>,
+    name: Option<String>,
+    kind: Option<String>,
+    timestamp: Option<u64>,
+    duration: Option<u64>,
+    local_endpoint: Option<Endpoint>,
+    remote_endpoint: Option<Endpoint>,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Endpoint {
+    service_name: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Annotation {
+    timestamp: u64,
+    value: String,
+}
+
+// Payloads are arrays of spans or arrays of traces (arrays of spans). The reader stops at the first invalid payload.
+pub fn read_spans<'a, R: Read>(reader: R) -> impl Iterator<Item = Result<Span<'a>, serde_json::Error>> {
+    serde_json::Deserializer::from_reader(reader)
+        .into_iter::<Value>()
+        .map(|payload| payload.and_then(payload_spans))
+        .flat_map(|spans| match spans {
+            Ok(spans) => spans.into_iter().map(|span| Ok(to_span(span))).collect::<Vec<_>>(),
+            Err(error) => vec![Err(error)],
+        })
+}
+
+// The shape of the payload is checked first so an invalid span is reported with its own error (e.g. a missing field)
+// instead of the error of an untagged enum matching none of the shapes.
+fn payload_spans(payload: Value) -> Result<Vec<ZipkinSpan>, serde_json::Error> {
+    match &payload {
+        Value::Array(items) if matches!(items.first(), Some(Value::Array(_))) => {
+            let traces: Vec<Vec<ZipkinSpan>> = serde_json::from_value(payload)?;
+            Ok(traces.into_iter().flatten().collect())
+        }
+        _ => serde_json::from_value(payload),
+    }
+}
+
+fn to_span<'a>(span: ZipkinSpan) -> Span<'a> {
+    let mut attributes: Attributes = span.tags.into_iter().map(|(key, value)| (key, Value::String(value))).collect();
+    let status = status_from_tags(&mut attributes);
+
+    if let Some(endpoint) = span.local_endpoint {
+        add_endpoint_attributes(endpoint, "service.name", "net.host", &mut attributes);
+    }
+    if let Some(endpoint) = span.remote_endpoint {
+        add_endpoint_attributes(endpoint, "peer.service", "net.peer", &mut attributes);
+    }
+
+    let events: Vec<Event> = span
+        .annotations
+        .into_iter()
+        .map(|annotation| Event {
+            time_unix_nano: annotation.timestamp * 1000,
+            name: annotation.value.into(),
+            attributes: Default::default(),
+            dropped_attributes_count: None,
+        })
+        .collect();
+
+    let start_time_unix_nano = span.timestamp.unwrap_or(0) * 1000;
+
+    Span {
+        trace_id: span.trace_id.into(),
+        span_id: span.id.into(),
+        trace_state: None,
+        parent_span_id: span.parent_id.map(Into::into),
+        name: span.name.unwrap_or_default().into(),
+        // A Zipkin span without kind is a local span.
+        kind: span.kind.as_deref().map_or(Some(SpanKind::Internal), span_kind),
+        start_time_unix_nano,
+        end_time_unix_nano: span.duration.map(|duration| start_time_unix_nano + duration * 1000),
+        attributes: if attributes.is_empty() { None } else { Some(attributes) },
+        dropped_attributes_count: None,
+        events: if events.is_empty() { None } else { Some(events) },
+        dropped_events_count: None,
+        links: None,
+        dropped_links_count: None,
+        status,
+    }
+}
+
+fn add_endpoint_attributes(endpoint: Endpoint, service_key: &str, net_prefix: &str, attributes: &mut Attributes) {
+    if let Some(service_name) = endpoint.service_name {
+        attributes.insert(service_key.into(), Value::String(service_name));
+    }
+    if let Some(ip) = endpoint.ipv4.or(endpoint.ipv6) {
+        attributes.insert(format!("{}.ip", net_prefix), Value::String(ip));
+    }
+    if let Some(port) = endpoint.port {
+        attributes.insert(format!("{}.port", net_prefix), Value::from(port));
+    }
+}
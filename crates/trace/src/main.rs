@@ -1,25 +1,67 @@
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
-use std::io::BufReader;
+use std::fs::{self, File};
 use std::path::PathBuf;
 use std::time::Instant;
 
 use clap::{Clap, ValueHint};
 use comfy_table::Table;
 use itertools::Itertools;
-use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+use serde::Serialize;
 use serde_json::Value;
 
+use crate::allocator::{AllocationProbe, AllocationStats, CountingAllocator};
+use crate::arrow::builder::BuilderEncoder;
+use crate::arrow::cardinality::CardinalityEstimator;
 use crate::arrow::statistics::{BatchStatistics, StatisticsReporter};
-use common::{Event, Link, Span};
-
+use crate::arrow::{ArrowTraceEncoder, ColumnOrientedEncoder, EncodingOptions, ReusingEncoder, RowOrientedEncoder};
+use crate::compression::Compression;
+use crate::decode::DecodeOpt;
+use crate::dump::ReplayOpt;
+use crate::generator::GenerateOpt;
+use crate::importer::InputFormat;
+use crate::merger::MergeOpt;
+use crate::metrics::MetricsOpt;
+use crate::parquet::ParquetOpt;
+use crate::pipeline::PipelineOpt;
+use crate::processor::ProcessOpt;
+use crate::query::QueryOpt;
+use crate::report::{OutputFormat, PhaseStatistics};
+use crate::sampling::SampleOpt;
+use crate::semconv::AttributeNormalizer;
+use crate::sweep::SweepOpt;
+use crate::validation::ValidateOpt;
+use common::{Event, Link, Span, SpanKind, Status, StatusCode};
+
+mod allocator;
 mod arrow;
+mod compression;
+mod decode;
+mod dump;
+mod generator;
+mod importer;
+mod merger;
+mod metrics;
+mod parquet;
+mod pipeline;
+mod processor;
 mod protobuf;
+mod query;
+mod report;
+mod sampling;
+mod semconv;
+mod sweep;
+mod validation;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
 
 #[derive(Clap, Debug)]
 #[clap(name = "trace")]
 pub struct Opt {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Maximum batch size
     #[clap(short, long, default_value = "1000")]
     pub batch_size: usize,
@@ -31,13 +73,95 @@ pub struct Opt {
     /// Generate statistics
     #[clap(short, long)]
     pub statistics: bool,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// Compression codec applied to the serialized buffers
+    #[clap(short, long, arg_enum, default_value = "lz4")]
+    pub compression: Compression,
+
+    /// String columns with a cardinality/row count ratio below this threshold are dictionary encoded
+    #[clap(long, default_value = "0.2")]
+    pub dictionary_threshold: f64,
+
+    /// How the cardinality of the string columns is computed for the dictionary threshold (the early exit and HyperLogLog estimators don't keep a copy of the values)
+    #[clap(long, arg_enum, default_value = "exact")]
+    pub cardinality_estimator: CardinalityEstimator,
+
+    /// Encode the string attributes whose values are all integers, floats or booleans as numeric or boolean columns (the decoder restores the strings)
+    #[clap(long)]
+    pub numeric_strings: bool,
+
+    /// Format of the benchmark results
+    #[clap(long, arg_enum, default_value = "table")]
+    pub format: OutputFormat,
+
+    /// Number of untimed warm-up iterations per batch
+    #[clap(long, default_value = "0")]
+    pub warmup: usize,
+
+    /// Number of timed repetitions per batch (totals are averaged over the repetitions)
+    #[clap(long, default_value = "1")]
+    pub repetitions: usize,
+
+    /// Directory where the span, event and link record batches of every encoded batch are written as Arrow IPC files (see the replay subcommand)
+    #[clap(long, parse(from_os_str), value_hint = ValueHint::DirPath)]
+    pub dump_dir: Option<PathBuf>,
+
+    /// Keep the buffers of the Arrow encoders between the batches of a file instead of allocating them for every batch
+    #[clap(long)]
+    pub reuse_buffers: bool,
+
+    /// JSON semantic convention table (e.g. data/semconv.json) used to rename the attribute keys and cast their values before encoding
+    #[clap(long, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub semconv_table: Option<PathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Clap, Debug)]
+pub enum Command {
+    /// Generate line delimited JSON spans with configurable shapes and attribute distributions
+    Generate(GenerateOpt),
+    /// Run the benchmark for every combination of batch size, compression codec, dictionary threshold and cardinality estimator
+    Sweep(SweepOpt),
+    /// Encode batches concurrently with a pool of workers fed by the JSON reader and report the throughput
+    Pipeline(PipelineOpt),
+    /// Compare reading decoded Arrow batches through a view borrowing from the arrays with materializing them into spans
+    Decode(DecodeOpt),
+    /// Rebuild the trace trees and report orphans, cycles, duplicate span ids, children starting before their parent and root counts
+    Validate(ValidateOpt),
+    /// Apply tail-based sampling policies (errors, latency, attribute value, trace id ratio) to decoded Arrow batches
+    Sample(SampleOpt),
+    /// Apply attribute rules (delete, rename, hash, redact, insert) per span and per column and compare their cost
+    Process(ProcessOpt),
+    /// Derive request rate, error count and duration histogram per service, span name, kind and status as OTLP metrics
+    Metrics(MetricsOpt),
+    /// Write the span, event and link record batches to Parquet files partitioned by time window
+    Parquet(ParquetOpt),
+    /// Re-run the deserialization and decoding benchmarks on the Arrow IPC files written with --dump-dir
+    Replay(ReplayOpt),
+    /// Run SQL queries over the span, event and link record batches of span files or Arrow IPC dumps
+    Query(QueryOpt),
+    /// Merge the Arrow payloads of small batches into fewer, larger payloads like a collector and report their sizes
+    Merge(MergeOpt),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkConfig {
+    pub compression: Compression,
+    pub encoding_options: EncodingOptions,
+    pub warmup: usize,
+    pub repetitions: usize,
+}
+
+#[derive(Debug, Serialize)]
 pub struct BenchmarkResult {
     batch_count: usize,
     row_count: usize,
+    total_span_copy_ns: u128,
     total_infer_schema_ns: u128,
+    total_data_source_conversion_ns: u128,
     total_buffer_creation_ns: u128,
     total_buffer_size: usize,
     total_buffer_serialization_ns: u128,
@@ -45,28 +169,92 @@ pub struct BenchmarkResult {
     total_compressed_buffer_size: usize,
     total_buffer_decompression_ns: u128,
     total_buffer_deserialization_ns: u128,
+    span_copy_allocs: AllocationStats,
+    infer_schema_allocs: AllocationStats,
+    data_source_conversion_allocs: AllocationStats,
+    buffer_creation_allocs: AllocationStats,
+    buffer_serialization_allocs: AllocationStats,
+    buffer_compression_allocs: AllocationStats,
+    buffer_decompression_allocs: AllocationStats,
+    buffer_deserialization_allocs: AllocationStats,
+    #[serde(skip)]
+    samples: PhaseSamples,
+}
+
+// Duration of every phase for each timed repetition of each batch.
+#[derive(Debug, Default)]
+pub struct PhaseSamples {
+    span_copy_ns: Vec<u128>,
+    infer_schema_ns: Vec<u128>,
+    data_source_conversion_ns: Vec<u128>,
+    buffer_creation_ns: Vec<u128>,
+    buffer_serialization_ns: Vec<u128>,
+    buffer_compression_ns: Vec<u128>,
+    buffer_decompression_ns: Vec<u128>,
+    buffer_deserialization_ns: Vec<u128>,
+}
+
+// The Arrow encoders of the benchmark, stateless or keeping their buffers between the batches (see `BufferPool`).
+pub struct ArrowEncoders {
+    row_oriented: Box<dyn ArrowTraceEncoder>,
+    col_oriented: Box<dyn ArrowTraceEncoder>,
+    builder: Box<dyn ArrowTraceEncoder>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ArrowVsProto {
     file: String,
     arrow_1: BenchmarkResult,
     arrow_2: BenchmarkResult,
+    arrow_3: BenchmarkResult,
     proto: BenchmarkResult,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opt = Opt::parse();
+
+    if let Some(command) = &opt.command {
+        return match command {
+            Command::Generate(generate_opt) => generator::generate(generate_opt),
+            Command::Sweep(sweep_opt) => sweep::sweep(sweep_opt),
+            Command::Pipeline(pipeline_opt) => pipeline::pipeline(pipeline_opt),
+            Command::Decode(decode_opt) => decode::decode(decode_opt),
+            Command::Validate(validate_opt) => validation::validate_files(validate_opt),
+            Command::Sample(sample_opt) => sampling::sample(sample_opt),
+            Command::Process(process_opt) => processor::process(process_opt),
+            Command::Metrics(metrics_opt) => metrics::metrics(metrics_opt),
+            Command::Parquet(parquet_opt) => parquet::export(parquet_opt),
+            Command::Replay(replay_opt) => dump::replay(replay_opt),
+            Command::Query(query_opt) => query::query(query_opt),
+            Command::Merge(merge_opt) => merger::merge(merge_opt),
+        };
+    }
+
+    let config = BenchmarkConfig {
+        compression: opt.compression,
+        encoding_options: EncodingOptions {
+            dictionary_threshold: opt.dictionary_threshold,
+            cardinality_estimator: opt.cardinality_estimator,
+            numeric_strings: opt.numeric_strings,
+        },
+        warmup: opt.warmup,
+        repetitions: opt.repetitions.max(1),
+    };
+    let normalizer = match &opt.semconv_table {
+        Some(path) => Some(AttributeNormalizer::from_file(path)?),
+        None => None,
+    };
     let mut bench_results = vec![];
 
-    opt.files.iter().for_each(|file| {
+    for file in opt.files.iter() {
         let filename = file.as_path().display().to_string();
-        let mut arrow_result_with_row_oriented_data_source = BenchmarkResult::new();
-        let mut arrow_result_with_column_oriented_data_source = BenchmarkResult::new();
-        let mut proto_result = BenchmarkResult::new();
+        let mut bench_result = ArrowVsProto::new(filename.clone());
+        let encoders = ArrowEncoders::new(opt.reuse_buffers);
 
-        print!("Processing file '{}'...", filename);
-        let reader = BufReader::new(File::open(file).unwrap());
+        eprint!("Processing file '{}'...", filename);
+        // the spans borrow their strings from the content of the file, which stays in memory (the size of the file) until
+        // all its batches are encoded
+        let input = fs::read_to_string(file)?;
 
         let mut arrow_row_oriented_stats_reporter = if opt.statistics {
             StatisticsReporter::new(&filename)
@@ -79,47 +267,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             StatisticsReporter::noop()
         };
 
-        serde_json::Deserializer::from_reader(reader)
-            .into_iter::<Span>()
-            .flat_map(|span| span.ok())
-            .chunks(opt.batch_size)
-            .into_iter()
-            .for_each(|chunk| {
-                let spans: Vec<_> = chunk.collect();
-                let row_oriented_batch_stats = arrow_row_oriented_stats_reporter.next_batch();
-                let col_oriented_batch_stats = arrow_col_oriented_stats_reporter.next_batch();
-
-                let result = bench_arrow_with_row_oriented_data_source(row_oriented_batch_stats, &spans, &mut arrow_result_with_row_oriented_data_source);
-                if result.is_err() {
-                    panic!("{:?}", result);
-                } else {
-                    arrow_result_with_row_oriented_data_source.batch_count += 1;
-                    arrow_result_with_row_oriented_data_source.row_count += spans.len();
-                }
-
-                let result = bench_arrow_with_column_oriented_data_source(col_oriented_batch_stats, &spans, &mut arrow_result_with_column_oriented_data_source);
-                if result.is_err() {
-                    panic!("{:?}", result);
-                } else {
-                    arrow_result_with_column_oriented_data_source.batch_count += 1;
-                    arrow_result_with_column_oriented_data_source.row_count += spans.len();
-                }
-
-                let result = bench_protobuf(&spans, &mut proto_result);
-                if result.is_err() {
-                    panic!("{:?}", result);
-                } else {
-                    proto_result.batch_count += 1;
-                    proto_result.row_count += spans.len();
-                }
-            });
-
-        bench_results.push(ArrowVsProto {
-            file: filename,
-            arrow_1: arrow_result_with_row_oriented_data_source,
-            arrow_2: arrow_result_with_column_oriented_data_source,
-            proto: proto_result,
-        });
+        for (batch_index, chunk) in importer::parse_spans(opt.input_format, &input).chunks(opt.batch_size).into_iter().enumerate() {
+            let mut spans: Vec<_> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+            if let Some(normalizer) = &normalizer {
+                normalizer.normalize_spans(&mut spans);
+            }
+            bench_result.bench_batch(
+                &encoders,
+                &spans,
+                &config,
+                arrow_row_oriented_stats_reporter.next_batch(),
+                arrow_col_oriented_stats_reporter.next_batch(),
+            );
+            if let Some(dump_dir) = &opt.dump_dir {
+                dump::dump_batch(dump_dir, file, batch_index, &spans, &config.encoding_options)?;
+            }
+        }
+
+        bench_results.push(bench_result);
 
         if opt.statistics {
             let data_filename = file.as_path().file_name().unwrap().to_str().unwrap();
@@ -135,10 +300,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .unwrap();
         }
 
-        println!("DONE.");
-    });
+        eprintln!("DONE.");
+    }
 
-    render_benchmark_results(bench_results);
+    match opt.format {
+        OutputFormat::Table => render_benchmark_results(bench_results),
+        OutputFormat::Json => report::write_json(&bench_results, std::io::stdout())?,
+        OutputFormat::Csv => report::write_csv(&bench_results, std::io::stdout())?,
+    }
 
     if opt.files.is_empty() {
         dump_sample_data();
@@ -147,12 +316,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+impl Default for BenchmarkResult {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BenchmarkResult {
     pub fn new() -> Self {
         Self {
             batch_count: 0,
             row_count: 0,
+            total_span_copy_ns: 0,
             total_infer_schema_ns: 0,
+            total_data_source_conversion_ns: 0,
             total_buffer_creation_ns: 0,
             total_buffer_size: 0,
             total_buffer_serialization_ns: 0,
@@ -160,35 +337,273 @@ impl BenchmarkResult {
             total_compressed_buffer_size: 0,
             total_buffer_decompression_ns: 0,
             total_buffer_deserialization_ns: 0,
+            span_copy_allocs: AllocationStats::default(),
+            infer_schema_allocs: AllocationStats::default(),
+            data_source_conversion_allocs: AllocationStats::default(),
+            buffer_creation_allocs: AllocationStats::default(),
+            buffer_serialization_allocs: AllocationStats::default(),
+            buffer_compression_allocs: AllocationStats::default(),
+            buffer_decompression_allocs: AllocationStats::default(),
+            buffer_deserialization_allocs: AllocationStats::default(),
+            samples: PhaseSamples::default(),
+        }
+    }
+
+    // Merges the results of another set of batches, e.g. processed by another worker.
+    fn merge(&mut self, other: &BenchmarkResult) {
+        self.batch_count += other.batch_count;
+        self.row_count += other.row_count;
+        self.total_buffer_size += other.total_buffer_size;
+        self.total_compressed_buffer_size += other.total_compressed_buffer_size;
+        self.total_span_copy_ns += other.total_span_copy_ns;
+        self.total_infer_schema_ns += other.total_infer_schema_ns;
+        self.total_data_source_conversion_ns += other.total_data_source_conversion_ns;
+        self.total_buffer_creation_ns += other.total_buffer_creation_ns;
+        self.total_buffer_serialization_ns += other.total_buffer_serialization_ns;
+        self.total_buffer_compression_ns += other.total_buffer_compression_ns;
+        self.total_buffer_decompression_ns += other.total_buffer_decompression_ns;
+        self.total_buffer_deserialization_ns += other.total_buffer_deserialization_ns;
+        self.span_copy_allocs.add(&other.span_copy_allocs);
+        self.infer_schema_allocs.add(&other.infer_schema_allocs);
+        self.data_source_conversion_allocs.add(&other.data_source_conversion_allocs);
+        self.buffer_creation_allocs.add(&other.buffer_creation_allocs);
+        self.buffer_serialization_allocs.add(&other.buffer_serialization_allocs);
+        self.buffer_compression_allocs.add(&other.buffer_compression_allocs);
+        self.buffer_decompression_allocs.add(&other.buffer_decompression_allocs);
+        self.buffer_deserialization_allocs.add(&other.buffer_deserialization_allocs);
+        self.samples.span_copy_ns.extend_from_slice(&other.samples.span_copy_ns);
+        self.samples.infer_schema_ns.extend_from_slice(&other.samples.infer_schema_ns);
+        self.samples.data_source_conversion_ns.extend_from_slice(&other.samples.data_source_conversion_ns);
+        self.samples.buffer_creation_ns.extend_from_slice(&other.samples.buffer_creation_ns);
+        self.samples.buffer_serialization_ns.extend_from_slice(&other.samples.buffer_serialization_ns);
+        self.samples.buffer_compression_ns.extend_from_slice(&other.samples.buffer_compression_ns);
+        self.samples.buffer_decompression_ns.extend_from_slice(&other.samples.buffer_decompression_ns);
+        self.samples.buffer_deserialization_ns.extend_from_slice(&other.samples.buffer_deserialization_ns);
+    }
+
+    pub fn allocations(&self) -> Vec<(&'static str, AllocationStats)> {
+        vec![
+            ("span copy", self.span_copy_allocs),
+            ("schema inference", self.infer_schema_allocs),
+            ("data source conversion", self.data_source_conversion_allocs),
+            ("buffer creation", self.buffer_creation_allocs),
+            ("buffer serialization", self.buffer_serialization_allocs),
+            ("buffer compression", self.buffer_compression_allocs),
+            ("buffer decompression", self.buffer_decompression_allocs),
+            ("buffer deserialization", self.buffer_deserialization_allocs),
+        ]
+    }
+
+    pub fn samples(&self) -> &PhaseSamples {
+        &self.samples
+    }
+
+    // Sizes are identical for every repetition, phase durations are averaged over the repetitions.
+    fn add_batch(&mut self, row_count: usize, runs: &[BenchmarkResult]) {
+        let repetitions = runs.len() as u128;
+
+        self.batch_count += 1;
+        self.row_count += row_count;
+        self.total_buffer_size += runs[0].total_buffer_size;
+        self.total_compressed_buffer_size += runs[0].total_compressed_buffer_size;
+        self.total_span_copy_ns += runs.iter().map(|run| run.total_span_copy_ns).sum::<u128>() / repetitions;
+        self.total_infer_schema_ns += runs.iter().map(|run| run.total_infer_schema_ns).sum::<u128>() / repetitions;
+        self.total_data_source_conversion_ns += runs.iter().map(|run| run.total_data_source_conversion_ns).sum::<u128>() / repetitions;
+        self.total_buffer_creation_ns += runs.iter().map(|run| run.total_buffer_creation_ns).sum::<u128>() / repetitions;
+        self.total_buffer_serialization_ns += runs.iter().map(|run| run.total_buffer_serialization_ns).sum::<u128>() / repetitions;
+        self.total_buffer_compression_ns += runs.iter().map(|run| run.total_buffer_compression_ns).sum::<u128>() / repetitions;
+        self.total_buffer_decompression_ns += runs.iter().map(|run| run.total_buffer_decompression_ns).sum::<u128>() / repetitions;
+        self.total_buffer_deserialization_ns += runs.iter().map(|run| run.total_buffer_deserialization_ns).sum::<u128>() / repetitions;
+
+        self.span_copy_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.span_copy_allocs)));
+        self.infer_schema_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.infer_schema_allocs)));
+        self.data_source_conversion_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.data_source_conversion_allocs)));
+        self.buffer_creation_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.buffer_creation_allocs)));
+        self.buffer_serialization_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.buffer_serialization_allocs)));
+        self.buffer_compression_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.buffer_compression_allocs)));
+        self.buffer_decompression_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.buffer_decompression_allocs)));
+        self.buffer_deserialization_allocs.add(&AllocationStats::mean(runs.iter().map(|run| &run.buffer_deserialization_allocs)));
+
+        for run in runs {
+            self.samples.span_copy_ns.push(run.total_span_copy_ns);
+            self.samples.infer_schema_ns.push(run.total_infer_schema_ns);
+            self.samples.data_source_conversion_ns.push(run.total_data_source_conversion_ns);
+            self.samples.buffer_creation_ns.push(run.total_buffer_creation_ns);
+            self.samples.buffer_serialization_ns.push(run.total_buffer_serialization_ns);
+            self.samples.buffer_compression_ns.push(run.total_buffer_compression_ns);
+            self.samples.buffer_decompression_ns.push(run.total_buffer_decompression_ns);
+            self.samples.buffer_deserialization_ns.push(run.total_buffer_deserialization_ns);
+        }
+    }
+
+    pub fn total_time_ns(&self) -> u128 {
+        self.total_infer_schema_ns
+            + self.total_data_source_conversion_ns
+            + self.total_buffer_creation_ns
+            + self.total_buffer_serialization_ns
+            + self.total_buffer_compression_ns
+            + self.total_buffer_decompression_ns
+            + self.total_buffer_deserialization_ns
+    }
+
+    // Total time of an encoder that would need owned spans, i.e. with the copy of the borrowed spans.
+    pub fn total_time_with_span_copy_ns(&self) -> u128 {
+        self.total_time_ns() + self.total_span_copy_ns
+    }
+}
+
+impl ArrowEncoders {
+    pub fn new(reuse_buffers: bool) -> Self {
+        if reuse_buffers {
+            Self {
+                row_oriented: Box::new(ReusingEncoder::new(RowOrientedEncoder)),
+                col_oriented: Box::new(ReusingEncoder::new(ColumnOrientedEncoder)),
+                builder: Box::new(ReusingEncoder::new(BuilderEncoder)),
+            }
+        } else {
+            Self {
+                row_oriented: Box::new(RowOrientedEncoder),
+                col_oriented: Box::new(ColumnOrientedEncoder),
+                builder: Box::new(BuilderEncoder),
+            }
+        }
+    }
+}
+
+impl ArrowVsProto {
+    pub fn new(file: String) -> Self {
+        Self {
+            file,
+            arrow_1: BenchmarkResult::new(),
+            arrow_2: BenchmarkResult::new(),
+            arrow_3: BenchmarkResult::new(),
+            proto: BenchmarkResult::new(),
+        }
+    }
+
+    pub fn bench_batch(
+        &mut self,
+        encoders: &ArrowEncoders,
+        spans: &[Span],
+        config: &BenchmarkConfig,
+        row_oriented_batch_stats: &mut BatchStatistics,
+        col_oriented_batch_stats: &mut BatchStatistics,
+    ) {
+        // The column statistics of a batch are collected once, on the first timed repetition.
+        let mut row_oriented_noop_stats = BatchStatistics::noop();
+        let mut col_oriented_noop_stats = BatchStatistics::noop();
+        for _ in 0..config.warmup {
+            run_encoders(0, encoders, spans, config, &mut row_oriented_noop_stats, &mut col_oriented_noop_stats);
         }
+
+        let mut arrow_1_runs = Vec::with_capacity(config.repetitions);
+        let mut arrow_2_runs = Vec::with_capacity(config.repetitions);
+        let mut arrow_3_runs = Vec::with_capacity(config.repetitions);
+        let mut proto_runs = Vec::with_capacity(config.repetitions);
+        for repetition in 0..config.repetitions.max(1) {
+            let (arrow_1, arrow_2, arrow_3, proto) = if repetition == 0 {
+                run_encoders(repetition, encoders, spans, config, row_oriented_batch_stats, col_oriented_batch_stats)
+            } else {
+                run_encoders(repetition, encoders, spans, config, &mut row_oriented_noop_stats, &mut col_oriented_noop_stats)
+            };
+            arrow_1_runs.push(arrow_1);
+            arrow_2_runs.push(arrow_2);
+            arrow_3_runs.push(arrow_3);
+            proto_runs.push(proto);
+        }
+
+        self.arrow_1.add_batch(spans.len(), &arrow_1_runs);
+        self.arrow_2.add_batch(spans.len(), &arrow_2_runs);
+        self.arrow_3.add_batch(spans.len(), &arrow_3_runs);
+        self.proto.add_batch(spans.len(), &proto_runs);
     }
 }
 
+impl PhaseSamples {
+    pub fn statistics(&self) -> Vec<(&'static str, PhaseStatistics)> {
+        vec![
+            ("span copy", PhaseStatistics::new(&self.span_copy_ns)),
+            ("schema inference", PhaseStatistics::new(&self.infer_schema_ns)),
+            ("data source conversion", PhaseStatistics::new(&self.data_source_conversion_ns)),
+            ("buffer creation", PhaseStatistics::new(&self.buffer_creation_ns)),
+            ("buffer serialization", PhaseStatistics::new(&self.buffer_serialization_ns)),
+            ("buffer compression", PhaseStatistics::new(&self.buffer_compression_ns)),
+            ("buffer decompression", PhaseStatistics::new(&self.buffer_decompression_ns)),
+            ("buffer deserialization", PhaseStatistics::new(&self.buffer_deserialization_ns)),
+        ]
+    }
+}
+
+// Runs the four encoders once on a batch. The starting encoder is rotated with `order` so that none of them
+// consistently benefits (or suffers) from the cache state left by the previous one. The column statistics are only
+// collected for the row-oriented and column-oriented encoders.
+fn run_encoders(
+    order: usize,
+    encoders: &ArrowEncoders,
+    spans: &[Span],
+    config: &BenchmarkConfig,
+    row_oriented_batch_stats: &mut BatchStatistics,
+    col_oriented_batch_stats: &mut BatchStatistics,
+) -> (BenchmarkResult, BenchmarkResult, BenchmarkResult, BenchmarkResult) {
+    let mut arrow_1 = BenchmarkResult::new();
+    let mut arrow_2 = BenchmarkResult::new();
+    let mut arrow_3 = BenchmarkResult::new();
+    let mut proto = BenchmarkResult::new();
+
+    // The encoders work from the spans borrowed from the input file, the copy into owned spans is measured once and
+    // reported separately for each of them.
+    let (span_copy_ns, span_copy_allocs) = bench_span_copy(spans);
+    for result in [&mut arrow_1, &mut arrow_2, &mut arrow_3, &mut proto].iter_mut() {
+        result.total_span_copy_ns = span_copy_ns;
+        result.span_copy_allocs = span_copy_allocs;
+    }
+
+    for i in 0..4 {
+        let result = match (order + i) % 4 {
+            0 => bench_arrow(encoders.row_oriented.as_ref(), row_oriented_batch_stats, spans, config, &mut arrow_1),
+            1 => bench_arrow(encoders.col_oriented.as_ref(), col_oriented_batch_stats, spans, config, &mut arrow_2),
+            2 => bench_arrow(encoders.builder.as_ref(), &mut BatchStatistics::noop(), spans, config, &mut arrow_3),
+            _ => bench_protobuf(spans, config, &mut proto),
+        };
+        if result.is_err() {
+            panic!("{:?}", result);
+        }
+    }
+
+    (arrow_1, arrow_2, arrow_3, proto)
+}
+
 impl Display for BenchmarkResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let total_span_copy_ms = self.total_span_copy_ns as f64 / 1000000.0;
         let total_infer_schema_ms = self.total_infer_schema_ns as f64 / 1000000.0;
+        let total_data_source_conversion_ms = self.total_data_source_conversion_ns as f64 / 1000000.0;
         let total_buffer_creation_ms = self.total_buffer_creation_ns as f64 / 1000000.0;
         let total_buffer_serialization_ms = self.total_buffer_serialization_ns as f64 / 1000000.0;
         let total_buffer_compression_ms = self.total_buffer_compression_ns as f64 / 1000000.0;
         let total_buffer_decompression_ms = self.total_buffer_decompression_ns as f64 / 1000000.0;
         let total_buffer_deserialization_ms = self.total_buffer_deserialization_ns as f64 / 1000000.0;
         let total_time_ms = total_infer_schema_ms
+            + total_data_source_conversion_ms
             + total_buffer_creation_ms
             + total_buffer_serialization_ms
             + total_buffer_compression_ms
             + total_buffer_decompression_ms
             + total_buffer_deserialization_ms;
         let result = format!(
-            " \n{}\n{}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{}\n{}",
+            " \n{}\n{}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{:.3}\n{}\n{}",
             self.batch_count,
             self.row_count,
+            total_span_copy_ms,
             total_infer_schema_ms,
+            total_data_source_conversion_ms,
             total_buffer_creation_ms,
             total_buffer_serialization_ms,
             total_buffer_compression_ms,
             total_buffer_decompression_ms,
             total_buffer_deserialization_ms,
             total_time_ms,
+            total_time_ms + total_span_copy_ms,
             self.total_buffer_size,
             self.total_compressed_buffer_size,
         );
@@ -196,58 +611,59 @@ impl Display for BenchmarkResult {
     }
 }
 
-fn bench_arrow_with_row_oriented_data_source(
+fn bench_arrow(
+    encoder: &dyn ArrowTraceEncoder,
     batch_stats: &mut BatchStatistics,
     spans: &[Span],
+    config: &BenchmarkConfig,
     bench_result: &mut BenchmarkResult,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let buf = arrow::serialize_row_oriented_data_source(batch_stats, spans, bench_result)?;
+    let buf = encoder.encode(batch_stats, spans, &config.encoding_options, bench_result)?;
     bench_result.total_buffer_size += buf.len();
     let start = Instant::now();
-    let compressed_buf = compress_prepend_size(&buf);
+    let probe = AllocationProbe::start();
+    let compressed_buf = config.compression.compress(&buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_compressed_buffer_size += compressed_buf.len();
     bench_result.total_buffer_compression_ns += elapse_time.as_nanos();
+    bench_result.buffer_compression_allocs.add(&probe.stop());
+    encoder.recycle(buf);
     let start = Instant::now();
-    let buf = decompress_size_prepended(&compressed_buf).unwrap();
+    let probe = AllocationProbe::start();
+    let buf = config.compression.decompress(&compressed_buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_decompression_ns += elapse_time.as_nanos();
+    bench_result.buffer_decompression_allocs.add(&probe.stop());
     arrow::deserialize(buf, bench_result);
     Ok(())
 }
 
-fn bench_arrow_with_column_oriented_data_source(
-    batch_stats: &mut BatchStatistics,
-    spans: &[Span],
-    bench_result: &mut BenchmarkResult,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let buf = arrow::serialize_column_oriented_data_source(batch_stats, spans, bench_result)?;
-    bench_result.total_buffer_size += buf.len();
+fn bench_span_copy(spans: &[Span]) -> (u128, AllocationStats) {
     let start = Instant::now();
-    let compressed_buf = compress_prepend_size(&buf);
+    let probe = AllocationProbe::start();
+    let owned_spans: Vec<Span<'static>> = spans.iter().cloned().map(Span::into_owned).collect();
     let elapse_time = Instant::now() - start;
-    bench_result.total_compressed_buffer_size += compressed_buf.len();
-    bench_result.total_buffer_compression_ns += elapse_time.as_nanos();
-    let start = Instant::now();
-    let buf = decompress_size_prepended(&compressed_buf).unwrap();
-    let elapse_time = Instant::now() - start;
-    bench_result.total_buffer_decompression_ns += elapse_time.as_nanos();
-    arrow::deserialize(buf, bench_result);
-    Ok(())
+    let allocs = probe.stop();
+    drop(owned_spans);
+    (elapse_time.as_nanos(), allocs)
 }
 
-fn bench_protobuf(spans: &[Span], bench_result: &mut BenchmarkResult) -> Result<(), Box<dyn std::error::Error>> {
+fn bench_protobuf(spans: &[Span], config: &BenchmarkConfig, bench_result: &mut BenchmarkResult) -> Result<(), Box<dyn std::error::Error>> {
     let buf = protobuf::serialize(spans, bench_result)?;
     bench_result.total_buffer_size += buf.len();
     let start = Instant::now();
-    let compressed_buf = compress_prepend_size(&buf);
+    let probe = AllocationProbe::start();
+    let compressed_buf = config.compression.compress(&buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_compressed_buffer_size += compressed_buf.len();
     bench_result.total_buffer_compression_ns += elapse_time.as_nanos();
+    bench_result.buffer_compression_allocs.add(&probe.stop());
     let start = Instant::now();
-    let buf = decompress_size_prepended(&compressed_buf).unwrap();
+    let probe = AllocationProbe::start();
+    let buf = config.compression.decompress(&compressed_buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_decompression_ns += elapse_time.as_nanos();
+    bench_result.buffer_decompression_allocs.add(&probe.stop());
     protobuf::deserialize(buf, bench_result);
     Ok(())
 }
@@ -259,31 +675,35 @@ fn dump_sample_data() {
     attributes.insert("label_3".into(), Value::String("<number>".into()));
 
     let span = Span {
-        trace_id: "<id>".to_string(),
-        span_id: "<id>".to_string(),
-        trace_state: Some("<state>".to_string()),
-        parent_span_id: Some("<id>".to_string()),
-        name: "<name>".to_string(),
-        kind: Some(0),
+        trace_id: "<id>".into(),
+        span_id: "<id>".into(),
+        trace_state: Some("<state>".into()),
+        parent_span_id: Some("<id>".into()),
+        name: "<name>".into(),
+        kind: Some(SpanKind::Unspecified),
         start_time_unix_nano: 1626371667388918000,
         end_time_unix_nano: Some(1626371667388918010),
         attributes: Some(attributes.clone()),
         dropped_attributes_count: Some(0),
         events: Some(vec![Event {
             time_unix_nano: 1626371667388918000,
-            name: "<event_name>".to_string(),
+            name: "<event_name>".into(),
             attributes: attributes.clone(),
             dropped_attributes_count: Some(0),
         }]),
         dropped_events_count: Some(0),
         links: Some(vec![Link {
-            trace_id: "<id>".to_string(),
-            span_id: "<id>".to_string(),
+            trace_id: "<id>".into(),
+            span_id: "<id>".into(),
             trace_state: Some("<state>".into()),
             attributes,
             dropped_attributes_count: Some(0),
         }]),
         dropped_links_count: Some(0),
+        status: Some(Status {
+            code: Some(StatusCode::Unset),
+            message: Some("<message>".into()),
+        }),
     };
 
     println!();
@@ -298,7 +718,7 @@ fn dump_sample_data() {
     println!("The following fields are optionals:");
     println!("- trace_state");
     println!("- parent_span_id");
-    println!("- kind");
+    println!("- kind (number or name, e.g. 2 or \"SPAN_KIND_SERVER\")");
     println!("- end_time_unix_nano");
     println!("- attributes");
     println!("- dropped_attributes_count (any level)");
@@ -306,18 +726,22 @@ fn dump_sample_data() {
     println!("- dropped_events_count");
     println!("- links");
     println!("- dropped_links_count");
+    println!("- status (code: 0 = unset, 1 = ok, 2 = error)");
 }
 
 fn render_benchmark_results(results: Vec<ArrowVsProto>) {
     let metric_labels = r#"  batch count
   row count
+  total span copy (ms)
   total schema inferrence (ms)
+  total data source conversion (ms)
   total buffer creation (ms)
   total buffer serialization (ms)
   total buffer compression (ms)
   total buffer decompression (ms)
   total buffer deserialization (ms)
   total time (ms)
+  total time with span copy (ms)
   total buffer size (bytes)
   total compressed buffer size (bytes)"#;
     let mut table = Table::new();
@@ -325,19 +749,88 @@ fn render_benchmark_results(results: Vec<ArrowVsProto>) {
         "File/Metrics",
         "Protobuf\nreference implementation",
         "Arrow\nschema inference\n+ with row-oriented data source",
-        "Arrow\nwith columnar-oriented data source",
+        "Arrow\nschema inference\n+ with columnar-oriented data source",
+        "Arrow\nincremental builder\n(SDK exporter)",
     ]);
 
-    for result in results {
+    for result in results.iter() {
         let mut columns = vec![];
 
         columns.push(format!("{}\n{}", result.file, metric_labels));
         columns.push(result.proto.to_string());
         columns.push(result.arrow_1.to_string());
         columns.push(result.arrow_2.to_string());
+        columns.push(result.arrow_3.to_string());
 
         table.add_row(columns);
     }
 
     println!("{}", table);
-}
\ No newline at end of file
+
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File/Phase\n(per batch, ms)\nmin / median / p95 / p99 / std dev",
+        "Protobuf\nreference implementation",
+        "Arrow\nschema inference\n+ with row-oriented data source",
+        "Arrow\nschema inference\n+ with columnar-oriented data source",
+        "Arrow\nincremental builder\n(SDK exporter)",
+    ]);
+
+    for result in results.iter() {
+        let phases: Vec<&str> = result.proto.samples().statistics().iter().map(|(phase, _)| *phase).collect();
+        table.add_row(vec![
+            format!("{}\n  {}", result.file, phases.join("\n  ")),
+            render_phase_statistics(&result.proto),
+            render_phase_statistics(&result.arrow_1),
+            render_phase_statistics(&result.arrow_2),
+            render_phase_statistics(&result.arrow_3),
+        ]);
+    }
+
+    println!("{}", table);
+
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File/Phase\nallocations / allocated bytes / peak live bytes",
+        "Protobuf\nreference implementation",
+        "Arrow\nschema inference\n+ with row-oriented data source",
+        "Arrow\nschema inference\n+ with columnar-oriented data source",
+        "Arrow\nincremental builder\n(SDK exporter)",
+    ]);
+
+    for result in results.iter() {
+        let phases: Vec<&str> = result.proto.allocations().iter().map(|(phase, _)| *phase).collect();
+        table.add_row(vec![
+            format!("{}\n  {}", result.file, phases.join("\n  ")),
+            render_allocations(&result.proto),
+            render_allocations(&result.arrow_1),
+            render_allocations(&result.arrow_2),
+            render_allocations(&result.arrow_3),
+        ]);
+    }
+
+    println!("{}", table);
+}
+
+fn render_allocations(result: &BenchmarkResult) -> String {
+    let mut lines = vec![" ".to_string()];
+    for (_, allocs) in result.allocations() {
+        lines.push(format!("{} / {} / {}", allocs.allocations, allocs.allocated_bytes, allocs.peak_live_bytes));
+    }
+    lines.join("\n")
+}
+
+fn render_phase_statistics(result: &BenchmarkResult) -> String {
+    let mut lines = vec![" ".to_string()];
+    for (_, stats) in result.samples().statistics() {
+        lines.push(format!(
+            "{:.3} / {:.3} / {:.3} / {:.3} / {:.3}",
+            stats.min_ns / 1000000.0,
+            stats.median_ns / 1000000.0,
+            stats.p95_ns / 1000000.0,
+            stats.p99_ns / 1000000.0,
+            stats.std_dev_ns / 1000000.0
+        ));
+    }
+    lines.join("\n")
+}
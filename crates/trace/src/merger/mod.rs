@@ -0,0 +1,101 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::{Clap, ValueHint};
+use comfy_table::Table;
+use itertools::Itertools;
+use prost::Message;
+
+use common::Span;
+
+use crate::arrow::merge::merge_payloads;
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::{ArrowTraceEncoder, ColumnOrientedEncoder, EncodingOptions, RowOrientedEncoder};
+use crate::dump::DumpEncoder;
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+#[derive(Clap, Debug)]
+pub struct MergeOpt {
+    /// Maximum batch size of the payloads received by the collector
+    #[clap(short, long, default_value = "100")]
+    pub batch_size: usize,
+
+    /// Number of received payloads merged into a forwarded payload
+    #[clap(short, long, default_value = "10")]
+    pub payloads_per_merge: usize,
+
+    /// Encoder producing the received payloads
+    #[clap(long, arg_enum, default_value = "arrow-column-oriented")]
+    pub encoder: DumpEncoder,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+// Simulates a collector receiving small OTLP-Arrow payloads (one per batch of spans) and forwarding fewer, larger ones:
+// the payloads are merged by groups of `--payloads-per-merge` and the payload counts, sizes and merge time are reported.
+pub fn merge(opt: &MergeOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if opt.payloads_per_merge == 0 {
+        return Err("the number of payloads per merge must be positive".into());
+    }
+    let encoder: &dyn ArrowTraceEncoder = match opt.encoder {
+        DumpEncoder::ArrowRowOriented => &RowOrientedEncoder,
+        DumpEncoder::ArrowColumnOriented => &ColumnOrientedEncoder,
+    };
+    let options = EncodingOptions::default();
+
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File",
+        "Payloads",
+        "Payload size",
+        "Merged payloads",
+        "Merged payload size",
+        "Merge time (ms)",
+    ]);
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Processing file '{}'...", filename);
+        let mut stats_reporter = StatisticsReporter::noop();
+        let mut payloads = vec![];
+
+        for chunk in importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .chunks(opt.batch_size)
+            .into_iter()
+        {
+            let spans: Vec<Span> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+            let buf = encoder.encode(stats_reporter.next_batch(), &spans, &options, &mut BenchmarkResult::new())?;
+            payloads.push(bytes::Bytes::from(buf));
+        }
+
+        let start = Instant::now();
+        let mut merged = vec![];
+        for group in payloads.chunks(opt.payloads_per_merge) {
+            merged.extend(merge_payloads(group, &options)?);
+        }
+        let elapse_time = Instant::now() - start;
+        eprintln!("DONE.");
+
+        table.add_row(vec![
+            filename,
+            payloads.len().to_string(),
+            payloads.iter().map(|payload| payload.len()).sum::<usize>().to_string(),
+            merged.len().to_string(),
+            merged.iter().map(|resource_events| resource_events.encoded_len()).sum::<usize>().to_string(),
+            format!("{:.3}", elapse_time.as_secs_f64() * 1000.0),
+        ]);
+    }
+
+    println!("{}", table);
+
+    Ok(())
+}
@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use arrow::array::Array;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use clap::{Clap, ValueHint};
+use comfy_table::Table;
+use itertools::Itertools;
+use prost::Message;
+
+use common::{Span, SpanKind, StatusCode};
+use oltp::opentelemetry::proto::common::v1::{any_value, AnyValue, InstrumentationLibrary, KeyValue};
+use oltp::opentelemetry::proto::metrics::v1::{
+    metric, number_data_point, AggregationTemporality, Gauge, Histogram, HistogramDataPoint, InstrumentationLibraryMetrics, Metric, NumberDataPoint,
+    ResourceMetrics, Sum,
+};
+use oltp::opentelemetry::proto::resource::v1::Resource;
+
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::{AttributeValue, TraceBatch};
+use crate::arrow::{serialize_column_oriented_data_source, EncodingOptions};
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+const UNKNOWN_SERVICE: &str = "unknown_service";
+const STATUS_CODE_ERROR: u8 = StatusCode::Error as u8;
+
+#[derive(Clap, Debug)]
+pub struct MetricsOpt {
+    /// Maximum batch size
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Span attribute containing the service name
+    #[clap(long, default_value = "service.name")]
+    pub service_attribute: String,
+
+    /// Upper bounds of the duration histogram buckets (in milliseconds, strictly increasing)
+    #[clap(long, default_values = &["5", "10", "25", "50", "100", "250", "500", "1000", "2500", "5000", "10000"], use_delimiter = true)]
+    pub buckets: Vec<f64>,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SeriesKey {
+    pub service: String,
+    pub name: String,
+    pub kind: Option<SpanKind>,
+    pub status_code: Option<u8>,
+}
+
+// Series key borrowing the service and span names from the aggregated batch.
+type BatchSeriesKey<'a> = (&'a str, &'a str, Option<SpanKind>, Option<u8>);
+
+#[derive(Debug, Clone, Default)]
+pub struct SeriesValue {
+    pub calls: u64,
+    pub errors: u64,
+    pub duration_sum_ms: f64,
+    pub bucket_counts: Vec<u64>,
+}
+
+// Request rate, error count and duration histogram (RED metrics) per service, span name, kind and status, aggregated
+// over span record batches and exported as OTLP metrics. Spans without an end time are not part of any series, so that
+// the calls, the errors and the histogram count the same spans.
+#[derive(Debug)]
+pub struct RedMetrics {
+    service_attribute: String,
+    bucket_bounds_ms: Vec<f64>,
+    series: HashMap<SeriesKey, SeriesValue>,
+    unfinished_spans: u64,
+    start_time_unix_nano: u64,
+    end_time_unix_nano: u64,
+}
+
+impl RedMetrics {
+    pub fn new(service_attribute: &str, bucket_bounds_ms: &[f64]) -> Result<Self, String> {
+        if let Some(bound) = bucket_bounds_ms.iter().find(|bound| !bound.is_finite()) {
+            return Err(format!("invalid bucket bound {}", bound));
+        }
+        if let Some(bounds) = bucket_bounds_ms.windows(2).find(|bounds| bounds[0] >= bounds[1]) {
+            return Err(format!(
+                "bucket bounds must be strictly increasing ({} is followed by {})",
+                bounds[0], bounds[1]
+            ));
+        }
+
+        Ok(Self {
+            service_attribute: service_attribute.to_string(),
+            bucket_bounds_ms: bucket_bounds_ms.to_vec(),
+            series: HashMap::new(),
+            unfinished_spans: 0,
+            start_time_unix_nano: u64::MAX,
+            end_time_unix_nano: 0,
+        })
+    }
+
+    pub fn series(&self) -> &HashMap<SeriesKey, SeriesValue> {
+        &self.series
+    }
+
+    pub fn unfinished_spans(&self) -> u64 {
+        self.unfinished_spans
+    }
+
+    // Aggregates a span record batch, series are grouped with keys borrowed from the batch columns, and only
+    // allocated once per batch and series.
+    pub fn aggregate(&mut self, spans: &RecordBatch) -> Result<(), ArrowError> {
+        let batch = TraceBatch {
+            spans: spans.clone(),
+            events: None,
+            links: None,
+        };
+        let view = batch.view()?;
+        let names = view.names();
+        let kinds = view.kinds();
+        let status_codes = view.status_codes();
+        let start_times = view.start_times_unix_nano();
+        let end_times = view.end_times_unix_nano();
+        let services = view.span_attribute(&self.service_attribute);
+        let bucket_count = self.bucket_bounds_ms.len() + 1;
+        let mut batch_series: HashMap<BatchSeriesKey, SeriesValue> = HashMap::new();
+
+        for row in 0..view.span_count() {
+            let duration_ns = match view.duration_ns(row) {
+                Some(duration_ns) => duration_ns,
+                None => {
+                    self.unfinished_spans += 1;
+                    continue;
+                }
+            };
+            let service = match services.as_ref().and_then(|services| services.value(row)) {
+                Some(AttributeValue::String(service)) => service,
+                _ => UNKNOWN_SERVICE,
+            };
+            let kind = kinds.and_then(|kinds| kinds.value(row)).and_then(SpanKind::from_name);
+            let status_code = status_codes
+                .filter(|status_codes| status_codes.is_valid(row))
+                .map(|status_codes| status_codes.value(row));
+            let key = (service, names.value(row).unwrap_or_default(), kind, status_code);
+            let value = batch_series.entry(key).or_insert_with(|| SeriesValue {
+                bucket_counts: vec![0; bucket_count],
+                ..Default::default()
+            });
+
+            value.calls += 1;
+            if status_code == Some(STATUS_CODE_ERROR) {
+                value.errors += 1;
+            }
+            let duration_ms = duration_ns as f64 / 1_000_000.0;
+            value.duration_sum_ms += duration_ms;
+            let bucket = self.bucket_bounds_ms.iter().position(|bound| duration_ms <= *bound).unwrap_or(bucket_count - 1);
+            value.bucket_counts[bucket] += 1;
+
+            self.start_time_unix_nano = self.start_time_unix_nano.min(start_times.value(row));
+            self.end_time_unix_nano = self.end_time_unix_nano.max(end_times.map_or(0, |end_times| end_times.value(row)));
+        }
+
+        for ((service, name, kind, status_code), batch_value) in batch_series {
+            let value = self
+                .series
+                .entry(SeriesKey {
+                    service: service.to_string(),
+                    name: name.to_string(),
+                    kind,
+                    status_code,
+                })
+                .or_insert_with(|| SeriesValue {
+                    bucket_counts: vec![0; bucket_count],
+                    ..Default::default()
+                });
+            value.calls += batch_value.calls;
+            value.errors += batch_value.errors;
+            value.duration_sum_ms += batch_value.duration_sum_ms;
+            value
+                .bucket_counts
+                .iter_mut()
+                .zip(batch_value.bucket_counts)
+                .for_each(|(count, batch_count)| *count += batch_count);
+        }
+
+        Ok(())
+    }
+
+    // Span count per second over the time range covered by the aggregated spans.
+    pub fn rate(&self, value: &SeriesValue) -> f64 {
+        let window_ns = self.end_time_unix_nano.saturating_sub(self.start_time_unix_nano).max(1);
+        value.calls as f64 * 1_000_000_000.0 / window_ns as f64
+    }
+
+    // One `ResourceMetrics` per service (`service.name` resource attribute) with the `span.calls`, `span.call_rate`,
+    // `span.errors` and `span.duration` metrics, one data point per span name, kind and status.
+    pub fn to_resource_metrics(&self) -> Vec<ResourceMetrics> {
+        let (start_time_unix_nano, time_unix_nano) = if self.series.is_empty() {
+            (0, 0)
+        } else {
+            (self.start_time_unix_nano, self.end_time_unix_nano)
+        };
+
+        self.series
+            .iter()
+            .sorted_by(|(key1, _), (key2, _)| {
+                (&key1.service, &key1.name, key1.kind, key1.status_code).cmp(&(&key2.service, &key2.name, key2.kind, key2.status_code))
+            })
+            .group_by(|(key, _)| key.service.clone())
+            .into_iter()
+            .map(|(service, series)| {
+                let mut calls = vec![];
+                let mut call_rates = vec![];
+                let mut errors = vec![];
+                let mut durations = vec![];
+
+                for (key, value) in series {
+                    let attributes = series_attributes(key);
+                    let data_point = |value: number_data_point::Value| NumberDataPoint {
+                        attributes: attributes.clone(),
+                        start_time_unix_nano,
+                        time_unix_nano,
+                        value: Some(value),
+                        ..Default::default()
+                    };
+
+                    calls.push(data_point(number_data_point::Value::AsInt(value.calls as i64)));
+                    call_rates.push(data_point(number_data_point::Value::AsDouble(self.rate(value))));
+                    errors.push(data_point(number_data_point::Value::AsInt(value.errors as i64)));
+                    durations.push(HistogramDataPoint {
+                        attributes: attributes.clone(),
+                        start_time_unix_nano,
+                        time_unix_nano,
+                        count: value.bucket_counts.iter().sum(),
+                        sum: value.duration_sum_ms,
+                        bucket_counts: value.bucket_counts.clone(),
+                        explicit_bounds: self.bucket_bounds_ms.clone(),
+                        ..Default::default()
+                    });
+                }
+
+                ResourceMetrics {
+                    resource: Some(Resource {
+                        attributes: vec![string_key_value("service.name", &service)],
+                        dropped_attributes_count: 0,
+                    }),
+                    instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                        instrumentation_library: Some(InstrumentationLibrary {
+                            name: "otel-arrow-red-metrics".into(),
+                            version: env!("CARGO_PKG_VERSION").into(),
+                        }),
+                        metrics: vec![
+                            red_metric("span.calls", "Number of spans", "1", sum(calls)),
+                            red_metric(
+                                "span.call_rate",
+                                "Number of spans per second over the aggregated time range",
+                                "1/s",
+                                metric::Data::Gauge(Gauge { data_points: call_rates }),
+                            ),
+                            red_metric("span.errors", "Number of spans with an error status", "1", sum(errors)),
+                            red_metric(
+                                "span.duration",
+                                "Duration of the spans",
+                                "ms",
+                                metric::Data::Histogram(Histogram {
+                                    data_points: durations,
+                                    aggregation_temporality: AggregationTemporality::Delta as i32,
+                                }),
+                            ),
+                        ],
+                        schema_url: "".into(),
+                    }],
+                    schema_url: "".into(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn red_metric(name: &str, description: &str, unit: &str, data: metric::Data) -> Metric {
+    Metric {
+        name: name.into(),
+        description: description.into(),
+        unit: unit.into(),
+        data: Some(data),
+    }
+}
+
+fn sum(data_points: Vec<NumberDataPoint>) -> metric::Data {
+    metric::Data::Sum(Sum {
+        data_points,
+        aggregation_temporality: AggregationTemporality::Delta as i32,
+        is_monotonic: true,
+    })
+}
+
+fn series_attributes(key: &SeriesKey) -> Vec<KeyValue> {
+    vec![
+        string_key_value("span.name", &key.name),
+        string_key_value("span.kind", kind_name(key.kind)),
+        string_key_value("status.code", status_code_name(key.status_code)),
+    ]
+}
+
+fn string_key_value(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.into())),
+        }),
+    }
+}
+
+fn kind_name(kind: Option<SpanKind>) -> &'static str {
+    kind.unwrap_or(SpanKind::Unspecified).name()
+}
+
+fn status_code_name(status_code: Option<u8>) -> &'static str {
+    status_code
+        .and_then(|status_code| StatusCode::from_i64(status_code as i64))
+        .unwrap_or(StatusCode::Unset)
+        .name()
+}
+
+pub fn metrics(opt: &MetricsOpt) -> Result<(), Box<dyn std::error::Error>> {
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Processing file '{}'...", filename);
+        let mut red_metrics = RedMetrics::new(&opt.service_attribute, &opt.buckets)?;
+        let mut stats_reporter = StatisticsReporter::noop();
+        let mut aggregation_ns = 0u128;
+        let mut span_count = 0;
+
+        for chunk in importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .chunks(opt.batch_size)
+            .into_iter()
+        {
+            let spans: Vec<Span> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+            let buf = serialize_column_oriented_data_source(stats_reporter.next_batch(), &spans, &EncodingOptions::default(), &mut BenchmarkResult::new())?;
+
+            for batch in TraceBatch::decode(buf.into())? {
+                let start = Instant::now();
+                red_metrics.aggregate(&batch.spans)?;
+                aggregation_ns += (Instant::now() - start).as_nanos();
+                span_count += batch.spans.num_rows();
+            }
+        }
+
+        let start = Instant::now();
+        let resource_metrics = red_metrics.to_resource_metrics();
+        let export_ns = (Instant::now() - start).as_nanos();
+        let encoded_len: usize = resource_metrics.iter().map(|resource_metrics| resource_metrics.encoded_len()).sum();
+        eprintln!("DONE.");
+
+        let mut table = Table::new();
+        table.set_header(vec![
+            "Service",
+            "Span name",
+            "Kind",
+            "Status",
+            "Calls",
+            "Calls/s",
+            "Errors",
+            "Mean duration (ms)",
+        ]);
+        for (key, value) in red_metrics
+            .series()
+            .iter()
+            .sorted_by(|(key1, _), (key2, _)| (&key1.service, &key1.name).cmp(&(&key2.service, &key2.name)))
+        {
+            table.add_row(vec![
+                key.service.clone(),
+                key.name.clone(),
+                kind_name(key.kind).to_string(),
+                status_code_name(key.status_code).to_string(),
+                value.calls.to_string(),
+                format!("{:.2}", red_metrics.rate(value)),
+                value.errors.to_string(),
+                format!("{:.3}", value.duration_sum_ms / value.calls.max(1) as f64),
+            ]);
+        }
+
+        println!("File: {}", filename);
+        println!("{}", table);
+        println!(
+            "{} spans aggregated in {:.3} ms ({:.1} ns/span, {} spans without end time skipped), {} series exported as {} ResourceMetrics ({} bytes) in {:.3} ms",
+            span_count,
+            aggregation_ns as f64 / 1000000.0,
+            aggregation_ns as f64 / span_count.max(1) as f64,
+            red_metrics.unfinished_spans(),
+            red_metrics.series().len(),
+            resource_metrics.len(),
+            encoded_len,
+            export_ns as f64 / 1000000.0
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::arrow::tests::{encode, spans};
+    use crate::arrow::ColumnOrientedEncoder;
+
+    use super::*;
+
+    #[test]
+    fn bucket_bounds_are_validated() {
+        assert!(RedMetrics::new("service.name", &[]).is_ok());
+        assert!(RedMetrics::new("service.name", &[5.0, 10.0]).is_ok());
+        assert!(RedMetrics::new("service.name", &[10.0, 5.0]).is_err());
+        assert!(RedMetrics::new("service.name", &[5.0, 5.0]).is_err());
+        assert!(RedMetrics::new("service.name", &[5.0, f64::NAN]).is_err());
+    }
+
+    #[test]
+    fn calls_errors_and_durations_count_the_same_spans() {
+        // span i lasts 2*i ms, every 4th span has no end time, the first 6 spans belong to `frontend`
+        let mut spans = spans(12);
+        for (i, span) in spans.iter_mut().enumerate() {
+            span.end_time_unix_nano = if i % 4 == 3 {
+                None
+            } else {
+                Some(span.start_time_unix_nano + i as u64 * 2_000_000)
+            };
+            if i < 6 {
+                span.attributes.as_mut().unwrap().insert("service.name".into(), json!("frontend"));
+            }
+        }
+        let batch = encode(&ColumnOrientedEncoder, &spans);
+
+        let mut red_metrics = RedMetrics::new("service.name", &[5.0, 10.0]).unwrap();
+        red_metrics.aggregate(&batch.spans).unwrap();
+        assert_eq!(red_metrics.unfinished_spans(), 3);
+
+        let values: Vec<&SeriesValue> = red_metrics.series().values().collect();
+        assert_eq!(values.iter().map(|value| value.calls).sum::<u64>(), 9);
+        // span 7 has an error status but no end time
+        assert_eq!(values.iter().map(|value| value.errors).sum::<u64>(), 1);
+        for value in values.iter() {
+            assert_eq!(value.bucket_counts.iter().sum::<u64>(), value.calls);
+        }
+        let bucket_counts = values.iter().fold(vec![0; 3], |mut counts, value| {
+            counts.iter_mut().zip(value.bucket_counts.iter()).for_each(|(count, value)| *count += value);
+            counts
+        });
+        assert_eq!(bucket_counts, vec![3, 2, 4]);
+        let duration_sum_ms: f64 = values.iter().map(|value| value.duration_sum_ms).sum();
+        assert!((duration_sum_ms - 90.0).abs() < 1e-6);
+
+        let resource_metrics = red_metrics.to_resource_metrics();
+        let services: Vec<String> = resource_metrics
+            .iter()
+            .map(|resource_metrics| match &resource_metrics.resource.as_ref().unwrap().attributes[0].value {
+                Some(AnyValue {
+                    value: Some(any_value::Value::StringValue(service)),
+                }) => service.clone(),
+                value => panic!("unexpected service {:?}", value),
+            })
+            .collect();
+        assert_eq!(services, vec!["frontend", UNKNOWN_SERVICE]);
+
+        for resource_metrics in resource_metrics.iter() {
+            let metrics = &resource_metrics.instrumentation_library_metrics[0].metrics;
+            let calls = match &metrics[0].data {
+                Some(metric::Data::Sum(sum)) => &sum.data_points,
+                data => panic!("unexpected span.calls data {:?}", data),
+            };
+            let durations = match &metrics[3].data {
+                Some(metric::Data::Histogram(histogram)) => &histogram.data_points,
+                data => panic!("unexpected span.duration data {:?}", data),
+            };
+            assert_eq!(calls.len(), durations.len());
+            for (calls, duration) in calls.iter().zip(durations.iter()) {
+                assert_eq!(calls.attributes, duration.attributes);
+                assert_eq!(calls.value, Some(number_data_point::Value::AsInt(duration.count as i64)));
+                assert_eq!(duration.explicit_bounds, vec![5.0, 10.0]);
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use arrow::record_batch::RecordBatch;
+use clap::{ArgEnum, Clap, ValueHint};
+use comfy_table::Table;
+use itertools::Itertools;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use parquet::schema::types::ColumnPath;
+
+use common::Span;
+
+use crate::arrow::merge::merge_trace_batches;
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::TraceBatch;
+use crate::arrow::{serialize_column_oriented_data_source, EncodingOptions};
+use crate::importer::{self, InputFormat};
+use crate::sampling::filter_trace_batch;
+use crate::BenchmarkResult;
+
+// High cardinality columns for which a Parquet dictionary would only add overhead.
+const NON_DICTIONARY_COLUMNS: [&str; 3] = ["trace_id", "span_id", "parent_span_id"];
+
+#[derive(Clap, Debug)]
+pub struct ParquetOpt {
+    /// Output directory
+    #[clap(short, long, parse(from_os_str), value_hint = ValueHint::DirPath)]
+    pub output: PathBuf,
+
+    /// Maximum batch size
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Duration of the time windows used to partition the spans (in seconds, based on the span start time)
+    #[clap(long, default_value = "3600")]
+    pub window_secs: u64,
+
+    /// Maximum number of rows per row group
+    #[clap(long, default_value = "65536")]
+    pub row_group_size: usize,
+
+    /// Maximum number of spans per span file (the batches of a time window are split across part files)
+    #[clap(long, default_value = "1000000")]
+    pub max_rows_per_file: usize,
+
+    /// Parquet compression codec
+    #[clap(long, arg_enum, default_value = "snappy")]
+    pub compression: ParquetCompression,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ParquetCompression {
+    None,
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+impl From<ParquetCompression> for Compression {
+    fn from(compression: ParquetCompression) -> Self {
+        match compression {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip => Compression::GZIP,
+            ParquetCompression::Zstd => Compression::ZSTD,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParquetSinkOptions {
+    pub window_ns: u64,
+    pub row_group_size: usize,
+    pub max_rows_per_file: usize,
+    pub compression: Compression,
+    pub encoding_options: EncodingOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct WrittenFile {
+    pub path: PathBuf,
+    pub rows: i64,
+    pub row_groups: usize,
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+struct WindowBuffer {
+    batches: Vec<TraceBatch>,
+    span_count: usize,
+    next_part: usize,
+}
+
+// Writes span, event and link record batches to Parquet files partitioned by time window:
+// `<dir>/window_start_unix_nano=<window start>/{spans,events,links}/part-<n>.parquet`. The batches of a window are
+// buffered and merged (schema union) before being written, so every file has a single schema. A part is written as soon
+// as its window has `max_rows_per_file` buffered spans, a batch crossing the limit is split. The event and link `id`
+// columns reference the span rows of the span file with the same part number.
+pub struct ParquetSink {
+    dir: PathBuf,
+    options: ParquetSinkOptions,
+    windows: BTreeMap<u64, WindowBuffer>,
+    written_files: Vec<WrittenFile>,
+}
+
+impl ParquetSink {
+    pub fn new(dir: &Path, options: ParquetSinkOptions) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            options,
+            windows: BTreeMap::new(),
+            written_files: vec![],
+        })
+    }
+
+    pub fn write(&mut self, batch: &TraceBatch) -> Result<(), Box<dyn std::error::Error>> {
+        let window_ns = self.options.window_ns.max(1);
+        let max_rows_per_file = self.options.max_rows_per_file.max(1);
+        let windows: Vec<u64> = batch
+            .view()?
+            .start_times_unix_nano()
+            .values()
+            .iter()
+            .map(|time| time - time % window_ns)
+            .collect();
+
+        for window in windows.iter().unique() {
+            let mut window_batch = if windows.iter().all(|other| other == window) {
+                batch.clone()
+            } else {
+                filter_trace_batch(batch, &windows.iter().map(|other| other == window).collect::<Vec<bool>>())?
+            };
+
+            loop {
+                let buffer = self.windows.entry(*window).or_default();
+                let free_rows = max_rows_per_file - buffer.span_count;
+                let row_count = window_batch.spans.num_rows();
+                if row_count < free_rows {
+                    buffer.span_count += row_count;
+                    buffer.batches.push(window_batch);
+                    break;
+                }
+
+                let (head, tail) = if row_count == free_rows {
+                    (window_batch, None)
+                } else {
+                    let head_mask: Vec<bool> = (0..row_count).map(|row| row < free_rows).collect();
+                    let tail_mask: Vec<bool> = head_mask.iter().map(|head| !head).collect();
+                    (
+                        filter_trace_batch(&window_batch, &head_mask)?,
+                        Some(filter_trace_batch(&window_batch, &tail_mask)?),
+                    )
+                };
+                buffer.span_count += free_rows;
+                buffer.batches.push(head);
+                self.flush_window(*window)?;
+
+                match tail {
+                    Some(tail) => window_batch = tail,
+                    None => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Vec<WrittenFile>, Box<dyn std::error::Error>> {
+        for window in self.windows.keys().copied().collect::<Vec<u64>>() {
+            self.flush_window(window)?;
+        }
+        Ok(self.written_files)
+    }
+
+    fn flush_window(&mut self, window: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let buffer = match self.windows.get_mut(&window) {
+            Some(buffer) if !buffer.batches.is_empty() => buffer,
+            _ => return Ok(()),
+        };
+        let batches = std::mem::take(&mut buffer.batches);
+        let part = buffer.next_part;
+        buffer.span_count = 0;
+        buffer.next_part += 1;
+
+        let merged = merge_trace_batches(&batches, &self.options.encoding_options)?;
+        let window_dir = self.dir.join(format!("window_start_unix_nano={}", window));
+        self.write_file(&window_dir.join("spans"), part, &merged.spans)?;
+        if let Some(events) = &merged.events {
+            self.write_file(&window_dir.join("events"), part, events)?;
+        }
+        if let Some(links) = &merged.links {
+            self.write_file(&window_dir.join("links"), part, links)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_file(&mut self, dir: &Path, part: usize, batch: &RecordBatch) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("part-{:05}.parquet", part));
+
+        let mut writer = ArrowWriter::try_new(File::create(&path)?, batch.schema(), Some(self.writer_properties()))?;
+        writer.write(batch)?;
+        let metadata = writer.close()?;
+
+        self.written_files.push(WrittenFile {
+            bytes: fs::metadata(&path)?.len(),
+            path,
+            rows: metadata.num_rows,
+            row_groups: metadata.row_groups.len(),
+        });
+        Ok(())
+    }
+
+    fn writer_properties(&self) -> WriterProperties {
+        let mut builder = WriterProperties::builder()
+            .set_compression(self.options.compression)
+            .set_max_row_group_size(self.options.row_group_size)
+            .set_dictionary_enabled(true)
+            .set_statistics_enabled(true);
+
+        for column in NON_DICTIONARY_COLUMNS.iter() {
+            builder = builder.set_column_dictionary_enabled(ColumnPath::from(*column), false);
+        }
+
+        builder.build()
+    }
+}
+
+pub fn export(opt: &ParquetOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let encoding_options = EncodingOptions::default();
+    let mut sink = ParquetSink::new(
+        &opt.output,
+        ParquetSinkOptions {
+            window_ns: opt.window_secs * 1_000_000_000,
+            row_group_size: opt.row_group_size,
+            max_rows_per_file: opt.max_rows_per_file,
+            compression: opt.compression.into(),
+            encoding_options,
+        },
+    )?;
+
+    let start = Instant::now();
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Processing file '{}'...", filename);
+        let mut stats_reporter = StatisticsReporter::noop();
+
+        for chunk in importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .chunks(opt.batch_size)
+            .into_iter()
+        {
+            let spans: Vec<Span> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+            let buf = serialize_column_oriented_data_source(stats_reporter.next_batch(), &spans, &encoding_options, &mut BenchmarkResult::new())?;
+
+            for batch in TraceBatch::decode(buf.into())? {
+                sink.write(&batch)?;
+            }
+        }
+        eprintln!("DONE.");
+    }
+    let written_files = sink.finish()?;
+    let elapse_time = Instant::now() - start;
+
+    let mut table = Table::new();
+    table.set_header(vec!["File", "Rows", "Row groups", "Size (bytes)"]);
+    for written_file in written_files.iter() {
+        table.add_row(vec![
+            written_file.path.display().to_string(),
+            written_file.rows.to_string(),
+            written_file.row_groups.to_string(),
+            written_file.bytes.to_string(),
+        ]);
+    }
+    println!("{}", table);
+    println!(
+        "{} Parquet files ({} bytes) written in {:.3} ms",
+        written_files.len(),
+        written_files.iter().map(|written_file| written_file.bytes).sum::<u64>(),
+        elapse_time.as_secs_f64() * 1000.0
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::UInt32Array;
+    use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
+    use parquet::file::reader::SerializedFileReader;
+    use serde_json::Value;
+
+    use crate::arrow::tests::{encode, spans};
+    use crate::arrow::ColumnOrientedEncoder;
+
+    use super::*;
+
+    fn read_parquet(path: &Path) -> Option<RecordBatch> {
+        if !path.exists() {
+            return None;
+        }
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        let mut batches: Vec<RecordBatch> = ParquetFileArrowReader::new(Arc::new(reader))
+            .get_record_reader(1024)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        Some(batches.remove(0))
+    }
+
+    fn to_values(spans: &[Span]) -> Vec<Value> {
+        spans.iter().map(|span| serde_json::to_value(span).unwrap()).collect()
+    }
+
+    #[test]
+    fn spans_are_partitioned_by_window_and_part() {
+        let dir = std::env::temp_dir().join(format!("trace-parquet-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // 3 windows of 4 spans, written as 2 batches, so the second window has spans from both batches
+        let mut spans = spans(12);
+        for (i, span) in spans.iter_mut().enumerate() {
+            span.start_time_unix_nano = i as u64 * 1000;
+        }
+        let mut sink = ParquetSink::new(
+            &dir,
+            ParquetSinkOptions {
+                window_ns: 4000,
+                row_group_size: 65536,
+                max_rows_per_file: 3,
+                compression: Compression::SNAPPY,
+                encoding_options: EncodingOptions::default(),
+            },
+        )
+        .unwrap();
+        sink.write(&encode(&ColumnOrientedEncoder, &spans[..6])).unwrap();
+        sink.write(&encode(&ColumnOrientedEncoder, &spans[6..])).unwrap();
+        let written_files = sink.finish().unwrap();
+
+        let span_files: Vec<&WrittenFile> = written_files.iter().filter(|file| file.path.parent().unwrap().ends_with("spans")).collect();
+        // the full parts are written as soon as they are complete, the last part of every window by `finish`
+        assert_eq!(span_files.iter().map(|file| file.rows).collect::<Vec<i64>>(), vec![3, 3, 3, 1, 1, 1]);
+
+        for (window, span_rows) in [(0, [0..3, 3..4]), (4000, [4..7, 7..8]), (8000, [8..11, 11..12])] {
+            let window_dir = dir.join(format!("window_start_unix_nano={}", window));
+            for (part, span_rows) in span_rows.iter().enumerate() {
+                let file_name = format!("part-{:05}.parquet", part);
+                let batch = TraceBatch {
+                    spans: read_parquet(&window_dir.join("spans").join(&file_name)).unwrap(),
+                    events: read_parquet(&window_dir.join("events").join(&file_name)),
+                    links: read_parquet(&window_dir.join("links").join(&file_name)),
+                };
+
+                // the event and link ids reference the span rows of their part
+                let events = batch.events.as_ref().unwrap();
+                let (id_index, _) = events.schema().column_with_name("id").unwrap();
+                let event_ids = events.column(id_index).as_any().downcast_ref::<UInt32Array>().unwrap();
+                assert_eq!(event_ids.values(), (0..span_rows.len() as u32).collect::<Vec<u32>>().as_slice());
+                assert_eq!(
+                    to_values(&batch.view().unwrap().materialize()),
+                    to_values(&spans[span_rows.clone()]),
+                    "window {} part {}",
+                    window,
+                    part
+                );
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
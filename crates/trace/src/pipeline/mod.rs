@@ -0,0 +1,276 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
+
+use clap::{ArgEnum, Clap, ValueHint};
+use comfy_table::Table;
+use crossbeam_channel::bounded;
+use itertools::Itertools;
+use serde::Serialize;
+
+use common::Span;
+
+use crate::arrow::cardinality::CardinalityEstimator;
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::{ArrowTraceEncoder, ColumnOrientedEncoder, EncodingOptions, RowOrientedEncoder};
+use crate::compression::Compression;
+use crate::importer::{self, InputFormat};
+use crate::protobuf;
+use crate::report::OutputFormat;
+use crate::{BenchmarkConfig, BenchmarkResult};
+
+#[derive(Clap, Debug)]
+pub struct PipelineOpt {
+    /// Maximum batch size
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Number of encoding workers (number of cores if not specified)
+    #[clap(short, long)]
+    pub workers: Option<usize>,
+
+    /// Capacity, in batches, of the channel between the reader and the workers
+    #[clap(long, default_value = "64")]
+    pub channel_capacity: usize,
+
+    /// Encoders to run, one pipeline per encoder
+    #[clap(short, long, arg_enum, use_delimiter = true, default_values = &["protobuf", "arrow-row-oriented", "arrow-column-oriented"])]
+    pub encoders: Vec<Encoder>,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// Compression codec applied to the serialized buffers
+    #[clap(short, long, arg_enum, default_value = "lz4")]
+    pub compression: Compression,
+
+    /// String columns with a cardinality/row count ratio below this threshold are dictionary encoded
+    #[clap(long, default_value = "0.2")]
+    pub dictionary_threshold: f64,
+
+    /// How the cardinality of the string columns is computed for the dictionary threshold (the early exit and HyperLogLog estimators don't keep a copy of the values)
+    #[clap(long, arg_enum, default_value = "exact")]
+    pub cardinality_estimator: CardinalityEstimator,
+
+    /// Format of the results
+    #[clap(long, arg_enum, default_value = "table")]
+    pub format: OutputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Encoder {
+    Protobuf,
+    ArrowRowOriented,
+    ArrowColumnOriented,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PipelineRecord {
+    file: String,
+    encoder: &'static str,
+    workers: usize,
+    batch_count: usize,
+    span_count: usize,
+    compressed_buffer_size: usize,
+    elapsed_ms: f64,
+    spans_per_sec: f64,
+    spans_per_sec_per_core: f64,
+    // Time spent by the workers encoding (serializing and compressing) batches vs. waiting for the reader, in percent.
+    worker_busy_pct: f64,
+}
+
+pub fn pipeline(opt: &PipelineOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let workers = opt.workers.unwrap_or_else(num_cpus::get).max(1);
+    let config = BenchmarkConfig {
+        compression: opt.compression,
+        encoding_options: EncodingOptions {
+            dictionary_threshold: opt.dictionary_threshold,
+            cardinality_estimator: opt.cardinality_estimator,
+            ..Default::default()
+        },
+        warmup: 0,
+        repetitions: 1,
+    };
+    let mut records = vec![];
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+
+        for encoder in opt.encoders.iter() {
+            eprint!("Processing file '{}' (encoder: {}, workers: {})...", filename, encoder.name(), workers);
+            let result = run_pipeline(file, *encoder, workers, opt, &config)?;
+            records.push(PipelineRecord::new(&filename, *encoder, workers, &result));
+            eprintln!("DONE.");
+        }
+    }
+
+    match opt.format {
+        OutputFormat::Table => render_pipeline_results(&records),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&records)?),
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for record in records.iter() {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+struct PipelineResult {
+    elapsed_ns: u128,
+    busy_ns: u128,
+    bench_result: BenchmarkResult,
+}
+
+// The reader thread parses the file and sends batches into a bounded channel consumed by a pool of workers.
+fn run_pipeline(
+    file: &PathBuf,
+    encoder: Encoder,
+    workers: usize,
+    opt: &PipelineOpt,
+    config: &BenchmarkConfig,
+) -> Result<PipelineResult, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(file)?);
+    let (sender, receiver) = bounded::<Vec<Span>>(opt.channel_capacity.max(1));
+    let input_format = opt.input_format;
+    let batch_size = opt.batch_size;
+    let start = Instant::now();
+
+    let reader_handle = thread::spawn(move || -> Result<(), serde_json::Error> {
+        for chunk in importer::read_spans(input_format, reader).chunks(batch_size).into_iter() {
+            if sender.send(chunk.collect::<Result<_, _>>()?).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let worker_handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let receiver = receiver.clone();
+            let config = *config;
+            thread::spawn(move || {
+                let mut bench_result = BenchmarkResult::new();
+                let mut stats_reporter = StatisticsReporter::noop();
+                let mut busy_ns = 0;
+
+                for spans in receiver.iter() {
+                    let mut batch_result = BenchmarkResult::new();
+                    // Only the encoding is timed (serialization and compression), the buffers aren't decoded.
+                    let start = Instant::now();
+                    let buf = match encoder {
+                        Encoder::Protobuf => protobuf::serialize(&spans, &mut batch_result).map_err(Box::from),
+                        Encoder::ArrowRowOriented => {
+                            RowOrientedEncoder.encode(stats_reporter.next_batch(), &spans, &config.encoding_options, &mut batch_result)
+                        }
+                        Encoder::ArrowColumnOriented => {
+                            ColumnOrientedEncoder.encode(stats_reporter.next_batch(), &spans, &config.encoding_options, &mut batch_result)
+                        }
+                    }
+                    .and_then(|buf| {
+                        batch_result.total_buffer_size += buf.len();
+                        Ok(config.compression.compress(&buf)?)
+                    });
+                    busy_ns += (Instant::now() - start).as_nanos();
+                    let compressed_buf = match buf {
+                        Ok(compressed_buf) => compressed_buf,
+                        Err(error) => panic!("{:?}", error),
+                    };
+                    batch_result.total_compressed_buffer_size += compressed_buf.len();
+                    bench_result.add_batch(spans.len(), &[batch_result]);
+                }
+
+                (bench_result, busy_ns)
+            })
+        })
+        .collect();
+    drop(receiver);
+
+    // the sender is dropped on an invalid span, the workers stop after the batches already sent
+    let read_result = reader_handle.join().expect("reader thread panicked");
+    let mut bench_result = BenchmarkResult::new();
+    let mut busy_ns = 0;
+    for handle in worker_handles {
+        let (worker_result, worker_busy_ns) = handle.join().expect("worker thread panicked");
+        busy_ns += worker_busy_ns;
+        bench_result.merge(&worker_result);
+    }
+    read_result.map_err(|error| importer::span_error(file, error))?;
+
+    Ok(PipelineResult {
+        elapsed_ns: (Instant::now() - start).as_nanos(),
+        busy_ns,
+        bench_result,
+    })
+}
+
+impl Encoder {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoder::Protobuf => "protobuf",
+            Encoder::ArrowRowOriented => "arrow_row_oriented",
+            Encoder::ArrowColumnOriented => "arrow_column_oriented",
+        }
+    }
+}
+
+impl PipelineRecord {
+    fn new(file: &str, encoder: Encoder, workers: usize, result: &PipelineResult) -> Self {
+        let elapsed_sec = result.elapsed_ns.max(1) as f64 / 1_000_000_000.0;
+        let spans_per_sec = result.bench_result.row_count as f64 / elapsed_sec;
+
+        Self {
+            file: file.into(),
+            encoder: encoder.name(),
+            workers,
+            batch_count: result.bench_result.batch_count,
+            span_count: result.bench_result.row_count,
+            compressed_buffer_size: result.bench_result.total_compressed_buffer_size,
+            elapsed_ms: result.elapsed_ns as f64 / 1_000_000.0,
+            spans_per_sec,
+            spans_per_sec_per_core: spans_per_sec / workers as f64,
+            worker_busy_pct: result.busy_ns as f64 / (result.elapsed_ns.max(1) * workers as u128) as f64 * 100.0,
+        }
+    }
+}
+
+fn render_pipeline_results(records: &[PipelineRecord]) {
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File",
+        "Encoder",
+        "Workers",
+        "Batches",
+        "Spans",
+        "Elapsed (ms)",
+        "Spans/sec",
+        "Spans/sec/core",
+        "Worker busy (%)",
+    ]);
+
+    for record in records {
+        table.add_row(vec![
+            record.file.clone(),
+            record.encoder.to_string(),
+            record.workers.to_string(),
+            record.batch_count.to_string(),
+            record.span_count.to_string(),
+            format!("{:.3}", record.elapsed_ms),
+            format!("{:.0}", record.spans_per_sec),
+            format!("{:.0}", record.spans_per_sec_per_core),
+            format!("{:.1}", record.worker_busy_pct),
+        ]);
+    }
+
+    println!("{}", table);
+}
@@ -0,0 +1,611 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::{Clap, ValueHint};
+use comfy_table::Table;
+use itertools::Itertools;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use common::{Attributes, Span};
+
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::TraceBatch;
+use crate::arrow::{serialize_data_columns, to_data_columns, DataColumn, DataColumns, EncodingOptions};
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+#[derive(Clap, Debug)]
+pub struct ProcessOpt {
+    /// JSON file containing the list of attribute rules
+    #[clap(short, long, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub rules: PathBuf,
+
+    /// Maximum batch size
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+// Attribute rule, applied to the span, event and link attributes. Example of configuration:
+// [
+//   {"action": "delete", "key": "net_peer_ip"},
+//   {"action": "hash", "key": "http_user_agent", "salt": "s3cr3t"},
+//   {"action": "rename", "key": "http_status", "new_key": "http.status_code"},
+//   {"action": "redact", "key": "http_url", "pattern": "token=[^&]*", "replacement": "token=***"},
+//   {"action": "insert", "key": "deployment.environment", "value": "production"}
+// ]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AttributeRule {
+    Delete {
+        key: String,
+    },
+    // Replaces the values of `new_key` where `key` is defined.
+    Rename {
+        key: String,
+        new_key: String,
+    },
+    // Replaces the values by the hex SHA-256 of the salt followed by the value (non-string values are hashed from their
+    // JSON representation).
+    Hash {
+        key: String,
+        salt: String,
+    },
+    // Replaces every match of the pattern in string values.
+    Redact {
+        key: String,
+        pattern: String,
+        #[serde(default = "default_replacement")]
+        replacement: String,
+    },
+    // Sets the value where the attribute is missing.
+    Insert {
+        key: String,
+        value: Value,
+    },
+}
+
+fn default_replacement() -> String {
+    "****".into()
+}
+
+#[derive(Debug)]
+pub struct AttributeProcessor {
+    rules: Vec<CompiledRule>,
+}
+
+#[derive(Debug)]
+enum CompiledRule {
+    Delete { key: String },
+    Rename { key: String, new_key: String },
+    Hash { key: String, salt: String },
+    Redact { key: String, regex: Regex, replacement: String },
+    Insert { key: String, value: Value },
+}
+
+impl AttributeProcessor {
+    pub fn new(rules: Vec<AttributeRule>) -> Result<Self, Box<dyn std::error::Error>> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| -> Result<CompiledRule, Box<dyn std::error::Error>> {
+                Ok(match rule {
+                    AttributeRule::Delete { key } => CompiledRule::Delete { key },
+                    AttributeRule::Rename { key, new_key } => CompiledRule::Rename { key, new_key },
+                    AttributeRule::Hash { key, salt } => CompiledRule::Hash { key, salt },
+                    AttributeRule::Redact { key, pattern, replacement } => CompiledRule::Redact {
+                        key,
+                        regex: Regex::new(&pattern)?,
+                        replacement,
+                    },
+                    AttributeRule::Insert { key, value } => {
+                        if !(value.is_number() || value.is_string() || value.is_boolean()) {
+                            return Err(format!("the value inserted for '{}' must be a number, a string or a boolean", key).into());
+                        }
+                        CompiledRule::Insert { key, value }
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let rules: Vec<AttributeRule> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Self::new(rules)
+    }
+
+    // Column level processing, every rule is applied to a whole attribute column.
+    pub fn process_data_columns(&self, data_columns: &mut DataColumns<'_>) {
+        for (row_count, attributes_column) in data_columns.attribute_columns_mut().iter_mut() {
+            for rule in self.rules.iter() {
+                apply_to_columns(rule, *row_count, attributes_column);
+            }
+        }
+    }
+
+    // Row level processing, every rule is applied to the attributes of every span, event and link.
+    pub fn process_spans(&self, spans: &mut [Span<'_>]) {
+        for span in spans.iter_mut() {
+            if let Some(attributes) = span.attributes.as_mut() {
+                self.process_attributes(attributes);
+            } else if self.rules.iter().any(|rule| matches!(rule, CompiledRule::Insert { .. })) {
+                let mut attributes = Attributes::default();
+                self.process_attributes(&mut attributes);
+                span.attributes = Some(attributes);
+            }
+            for event in span.events.iter_mut().flatten() {
+                self.process_attributes(&mut event.attributes);
+            }
+            for link in span.links.iter_mut().flatten() {
+                self.process_attributes(&mut link.attributes);
+            }
+        }
+    }
+
+    pub fn process_attributes(&self, attributes: &mut Attributes) {
+        for rule in self.rules.iter() {
+            match rule {
+                CompiledRule::Delete { key } => {
+                    attributes.remove(key);
+                }
+                CompiledRule::Rename { key, new_key } => {
+                    if let Some(value) = attributes.remove(key) {
+                        attributes.insert(new_key.clone(), value);
+                    }
+                }
+                CompiledRule::Hash { key, salt } => {
+                    if let Some(value) = attributes.get_mut(key) {
+                        if !value.is_null() {
+                            let hashed_value = hash(salt, &string_value(value));
+                            *value = Value::String(hashed_value);
+                        }
+                    }
+                }
+                CompiledRule::Redact { key, regex, replacement } => {
+                    if let Some(Value::String(string)) = attributes.get_mut(key) {
+                        *string = regex.replace_all(string, replacement.as_str()).into_owned();
+                    }
+                }
+                CompiledRule::Insert { key, value } => {
+                    let current = attributes.entry(key.clone()).or_insert(Value::Null);
+                    if current.is_null() {
+                        *current = value.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn apply_to_columns(rule: &CompiledRule, row_count: usize, attributes_column: &mut HashMap<String, DataColumn<'_>>) {
+    match rule {
+        CompiledRule::Delete { key } => {
+            attributes_column.remove(key);
+        }
+        CompiledRule::Rename { key, new_key } => {
+            if let Some(column) = attributes_column.remove(key) {
+                let column = match attributes_column.remove(new_key) {
+                    Some(target) => merge_columns(column, target),
+                    None => column,
+                };
+                attributes_column.insert(new_key.clone(), column);
+            }
+        }
+        CompiledRule::Hash { key, salt } => {
+            if let Some(column) = attributes_column.remove(key) {
+                let values = string_values(column)
+                    .into_iter()
+                    .map(|value| value.map(|value| Cow::Owned(hash(salt, &value))))
+                    .collect();
+                attributes_column.insert(key.clone(), string_column(values));
+            }
+        }
+        CompiledRule::Redact { key, regex, replacement } => {
+            if let Some(DataColumn::StringColumn { values, .. }) = attributes_column.get_mut(key) {
+                for value in values.iter_mut().flatten() {
+                    if regex.is_match(value) {
+                        *value = Cow::Owned(regex.replace_all(value, replacement.as_str()).into_owned());
+                    }
+                }
+            }
+        }
+        CompiledRule::Insert { key, value } => {
+            let column = attributes_column.remove(key).unwrap_or_else(|| null_column(value, row_count));
+            attributes_column.insert(key.clone(), fill_missing(column, value));
+        }
+    }
+}
+
+fn hash(salt: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(value.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Values of `source` where defined, otherwise values of `target`. Columns of different types are merged as strings.
+fn merge_columns<'a>(source: DataColumn<'a>, target: DataColumn<'a>) -> DataColumn<'a> {
+    fn merge<T>(source: Vec<Option<T>>, target: Vec<Option<T>>) -> Vec<Option<T>> {
+        source.into_iter().zip(target).map(|(source, target)| source.or(target)).collect()
+    }
+
+    match (source, target) {
+        (DataColumn::U64Column { values: source, .. }, DataColumn::U64Column { values: target, .. }) => u64_column(merge(source, target)),
+        (DataColumn::I64Column { values: source, .. }, DataColumn::I64Column { values: target, .. }) => i64_column(merge(source, target)),
+        (DataColumn::F64Column { values: source, .. }, DataColumn::F64Column { values: target, .. }) => f64_column(merge(source, target)),
+        (DataColumn::BoolColumn { values: source, .. }, DataColumn::BoolColumn { values: target, .. }) => bool_column(merge(source, target)),
+        (source, target) => string_column(merge(string_values(source), string_values(target))),
+    }
+}
+
+fn fill_missing<'a>(column: DataColumn<'a>, value: &Value) -> DataColumn<'a> {
+    fn fill<T: Clone>(mut values: Vec<Option<T>>, value: T) -> Vec<Option<T>> {
+        values.iter_mut().filter(|v| v.is_none()).for_each(|v| *v = Some(value.clone()));
+        values
+    }
+
+    // Like the row path, the column keeps its type when no value is inserted.
+    if column_missing(&column) == 0 {
+        return column;
+    }
+
+    match (column, value) {
+        (DataColumn::U64Column { values, .. }, Value::Number(number)) if number.is_u64() => u64_column(fill(values, number.as_u64().unwrap())),
+        (DataColumn::U64Column { values, .. }, Value::Number(number)) if number.is_i64() => {
+            i64_column(fill(convert(values, |v| v as i64), number.as_i64().unwrap()))
+        }
+        (DataColumn::U64Column { values, .. }, Value::Number(number)) => f64_column(fill(convert(values, |v| v as f64), number.as_f64().unwrap())),
+        (DataColumn::I64Column { values, .. }, Value::Number(number)) if number.as_i64().is_some() => i64_column(fill(values, number.as_i64().unwrap())),
+        (DataColumn::I64Column { values, .. }, Value::Number(number)) if number.is_f64() => {
+            f64_column(fill(convert(values, |v| v as f64), number.as_f64().unwrap()))
+        }
+        (DataColumn::F64Column { values, .. }, Value::Number(number)) => f64_column(fill(values, number.as_f64().unwrap())),
+        (DataColumn::BoolColumn { values, .. }, Value::Bool(boolean)) => bool_column(fill(values, *boolean)),
+        (DataColumn::StringColumn { values, .. }, Value::String(string)) => string_column(fill(values, Cow::Owned(string.clone()))),
+        (column, value) => string_column(fill(string_values(column), Cow::Owned(string_value(value)))),
+    }
+}
+
+// Same promotions as the attribute schema inference (u64 -> i64 -> f64).
+fn convert<T, U>(values: Vec<Option<T>>, f: impl Fn(T) -> U) -> Vec<Option<U>> {
+    values.into_iter().map(|value| value.map(&f)).collect()
+}
+
+fn null_column<'a>(value: &Value, row_count: usize) -> DataColumn<'a> {
+    match value {
+        Value::Number(number) if number.is_u64() => u64_column(vec![None; row_count]),
+        Value::Number(number) if number.is_i64() => i64_column(vec![None; row_count]),
+        Value::Number(_) => f64_column(vec![None; row_count]),
+        Value::Bool(_) => bool_column(vec![None; row_count]),
+        _ => string_column(vec![None; row_count]),
+    }
+}
+
+fn string_values(column: DataColumn<'_>) -> Vec<Option<Cow<'_, str>>> {
+    fn to_strings<'a, T: ToString>(values: Vec<Option<T>>) -> Vec<Option<Cow<'a, str>>> {
+        values.into_iter().map(|value| value.map(|value| Cow::Owned(value.to_string()))).collect()
+    }
+
+    match column {
+        DataColumn::U64Column { values, .. } => to_strings(values),
+        DataColumn::I64Column { values, .. } => to_strings(values),
+        DataColumn::F64Column { values, .. } => values
+            .into_iter()
+            .map(|value| value.map(|value| Cow::Owned(string_value(&Value::from(value)))))
+            .collect(),
+        DataColumn::BoolColumn { values, .. } => to_strings(values),
+        DataColumn::StringColumn { values, .. } => values,
+    }
+}
+
+// Same representation as `Value::to_string` for non-string values, so the row and column paths hash the same strings.
+fn string_value(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+// Counted from the values, the `missing` counter of the columns built from the spans only counts the rows without
+// attributes.
+fn column_missing(column: &DataColumn<'_>) -> usize {
+    match column {
+        DataColumn::U64Column { values, .. } => missing(values),
+        DataColumn::I64Column { values, .. } => missing(values),
+        DataColumn::F64Column { values, .. } => missing(values),
+        DataColumn::BoolColumn { values, .. } => missing(values),
+        DataColumn::StringColumn { values, .. } => missing(values),
+    }
+}
+
+fn missing<T>(values: &[Option<T>]) -> usize {
+    values.iter().filter(|value| value.is_none()).count()
+}
+
+fn u64_column<'a>(values: Vec<Option<u64>>) -> DataColumn<'a> {
+    DataColumn::U64Column {
+        missing: missing(&values),
+        values,
+    }
+}
+
+fn i64_column<'a>(values: Vec<Option<i64>>) -> DataColumn<'a> {
+    DataColumn::I64Column {
+        missing: missing(&values),
+        values,
+    }
+}
+
+fn f64_column<'a>(values: Vec<Option<f64>>) -> DataColumn<'a> {
+    DataColumn::F64Column {
+        missing: missing(&values),
+        values,
+    }
+}
+
+fn bool_column<'a>(values: Vec<Option<bool>>) -> DataColumn<'a> {
+    DataColumn::BoolColumn {
+        missing: missing(&values),
+        values,
+    }
+}
+
+fn string_column(values: Vec<Option<Cow<'_, str>>>) -> DataColumn<'_> {
+    DataColumn::StringColumn {
+        missing: missing(&values),
+        values,
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProcessResult {
+    span_count: usize,
+    row_ns: u128,
+    column_ns: u128,
+    identical_batches: usize,
+    batches: usize,
+}
+
+// Compares the attribute processor applied per span (as done before encoding the protobuf representation) with the
+// same rules applied to the attribute columns of the column-oriented representation.
+pub fn process(opt: &ProcessOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let processor = AttributeProcessor::from_file(&opt.rules)?;
+    let options = EncodingOptions::default();
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File",
+        "Spans",
+        "Per span (ms)",
+        "Per column (ms)",
+        "Per span ns/span",
+        "Per column ns/span",
+        "Identical batches",
+    ]);
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Processing file '{}'...", filename);
+        let mut result = ProcessResult::default();
+        let mut stats_reporter = StatisticsReporter::noop();
+
+        for chunk in importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .chunks(opt.batch_size)
+            .into_iter()
+        {
+            let spans: Vec<Span> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+            let mut data_columns = to_data_columns(&spans, &options);
+            // the data columns borrow the spans
+            let mut processed_spans = spans.clone();
+
+            let start = Instant::now();
+            processor.process_spans(&mut processed_spans);
+            result.row_ns += (Instant::now() - start).as_nanos();
+
+            let start = Instant::now();
+            processor.process_data_columns(&mut data_columns);
+            result.column_ns += (Instant::now() - start).as_nanos();
+
+            let row_buf = serialize_data_columns(
+                stats_reporter.next_batch(),
+                &to_data_columns(&processed_spans, &options),
+                &options,
+                &mut BenchmarkResult::new(),
+            )?;
+            let column_buf = serialize_data_columns(stats_reporter.next_batch(), &data_columns, &options, &mut BenchmarkResult::new())?;
+            if materialize(row_buf)? == materialize(column_buf)? {
+                result.identical_batches += 1;
+            }
+            result.batches += 1;
+            result.span_count += spans.len();
+        }
+
+        let span_count = result.span_count.max(1) as f64;
+        table.add_row(vec![
+            filename,
+            result.span_count.to_string(),
+            format!("{:.3}", result.row_ns as f64 / 1000000.0),
+            format!("{:.3}", result.column_ns as f64 / 1000000.0),
+            format!("{:.1}", result.row_ns as f64 / span_count),
+            format!("{:.1}", result.column_ns as f64 / span_count),
+            format!("{}/{}", result.identical_batches, result.batches),
+        ]);
+        eprintln!("DONE.");
+    }
+
+    println!("{}", table);
+    Ok(())
+}
+
+fn materialize(buf: Vec<u8>) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut spans = vec![];
+    for batch in TraceBatch::decode(buf.into())? {
+        spans.extend(batch.view()?.materialize());
+    }
+    Ok(serde_json::to_value(spans)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::arrow::tests::spans;
+
+    use super::*;
+
+    fn processor(rules: Value) -> AttributeProcessor {
+        AttributeProcessor::new(serde_json::from_value(rules).unwrap()).unwrap()
+    }
+
+    fn attributes(value: Value) -> Attributes {
+        serde_json::from_value(value).unwrap()
+    }
+
+    // Decoded spans of both paths: rules applied to the spans, and to the attribute columns.
+    fn process_both_ways(processor: &AttributeProcessor, spans: &[Span]) -> (Value, Value) {
+        let options = EncodingOptions::default();
+        let mut stats_reporter = StatisticsReporter::noop();
+        let mut processed_spans = spans.to_vec();
+        processor.process_spans(&mut processed_spans);
+        let mut data_columns = to_data_columns(spans, &options);
+        processor.process_data_columns(&mut data_columns);
+
+        let row_buf = serialize_data_columns(
+            stats_reporter.next_batch(),
+            &to_data_columns(&processed_spans, &options),
+            &options,
+            &mut BenchmarkResult::new(),
+        )
+        .unwrap();
+        let column_buf = serialize_data_columns(stats_reporter.next_batch(), &data_columns, &options, &mut BenchmarkResult::new()).unwrap();
+        (materialize(row_buf).unwrap(), materialize(column_buf).unwrap())
+    }
+
+    #[test]
+    fn rules_are_applied_to_attributes() {
+        let cases = vec![
+            (json!([{"action": "delete", "key": "a"}]), json!({"a": 1, "b": "x"}), json!({"b": "x"})),
+            (json!([{"action": "delete", "key": "c"}]), json!({"a": 1}), json!({"a": 1})),
+            (
+                json!([{"action": "rename", "key": "a", "new_key": "c"}]),
+                json!({"a": 1, "b": "x"}),
+                json!({"c": 1, "b": "x"}),
+            ),
+            (
+                json!([{"action": "rename", "key": "a", "new_key": "b"}]),
+                json!({"a": 1, "b": "x"}),
+                json!({"b": 1}),
+            ),
+            (json!([{"action": "rename", "key": "c", "new_key": "b"}]), json!({"b": "x"}), json!({"b": "x"})),
+            (
+                json!([{"action": "hash", "key": "a", "salt": "s"}, {"action": "hash", "key": "b", "salt": "s"}]),
+                json!({"a": "x", "b": 1, "c": "x"}),
+                json!({
+                    "a": "9740c9dcdb4487d8cd63d2a544d8e6573f082081c49aef08c7853ab0f99e019a",
+                    "b": "e8bc163c82eee18733288c7d4ac636db3a6deb013ef2d37b68322be20edc45cc",
+                    "c": "x"
+                }),
+            ),
+            (
+                json!([{"action": "redact", "key": "a", "pattern": "token=[^&]*", "replacement": "token=***"}]),
+                json!({"a": "/?token=abc&x=1", "b": "token=abc"}),
+                json!({"a": "/?token=***&x=1", "b": "token=abc"}),
+            ),
+            (
+                json!([{"action": "redact", "key": "a", "pattern": "[0-9]"}]),
+                json!({"a": 12}),
+                json!({"a": 12}),
+            ),
+            (
+                json!([{"action": "redact", "key": "a", "pattern": "[0-9]"}]),
+                json!({"a": "a1"}),
+                json!({"a": "a****"}),
+            ),
+            (
+                json!([
+                    {"action": "insert", "key": "a", "value": "v"},
+                    {"action": "insert", "key": "b", "value": 2},
+                    {"action": "insert", "key": "c", "value": true}
+                ]),
+                json!({"a": "x", "b": null}),
+                json!({"a": "x", "b": 2, "c": true}),
+            ),
+            // rules are applied in order
+            (
+                json!([{"action": "rename", "key": "a", "new_key": "b"}, {"action": "delete", "key": "a"}, {"action": "insert", "key": "a", "value": 0}]),
+                json!({"a": 1}),
+                json!({"a": 0, "b": 1}),
+            ),
+        ];
+
+        for (rules, input, expected) in cases {
+            let mut processed = attributes(input.clone());
+            processor(rules.clone()).process_attributes(&mut processed);
+            assert_eq!(processed, attributes(expected), "rules {} on {}", rules, input);
+        }
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for rules in [
+            json!([{"action": "insert", "key": "a", "value": {"b": 1}}]),
+            json!([{"action": "insert", "key": "a", "value": null}]),
+            json!([{"action": "redact", "key": "a", "pattern": "("}]),
+        ] {
+            assert!(AttributeProcessor::new(serde_json::from_value(rules.clone()).unwrap()).is_err(), "{}", rules);
+        }
+        assert!(serde_json::from_value::<Vec<AttributeRule>>(json!([{"action": "upsert", "key": "a"}])).is_err());
+    }
+
+    #[test]
+    fn row_and_column_paths_agree() {
+        let mut spans = spans(20);
+        for (i, span) in spans.iter_mut().enumerate() {
+            if i % 2 == 0 {
+                span.attributes.as_mut().unwrap().insert("code".into(), json!(500 + i));
+            }
+        }
+        let rule_sets = vec![
+            json!([{"action": "delete", "key": "user"}, {"action": "delete", "key": "exception.type"}]),
+            json!([{"action": "rename", "key": "http.status_code", "new_key": "status"}]),
+            // same types, the values of `code` replace the ones of `http.status_code` where defined
+            json!([{"action": "rename", "key": "code", "new_key": "http.status_code"}]),
+            json!([
+                {"action": "hash", "key": "user", "salt": "s"},
+                {"action": "hash", "key": "ratio", "salt": "s"},
+                {"action": "hash", "key": "retry", "salt": "s"}
+            ]),
+            json!([{"action": "redact", "key": "user", "pattern": "[0-9]+", "replacement": "#"}]),
+            json!([
+                {"action": "insert", "key": "env", "value": "prod"},
+                {"action": "insert", "key": "cached", "value": false},
+                {"action": "insert", "key": "code", "value": 0}
+            ]),
+            json!([
+                {"action": "rename", "key": "user", "new_key": "enduser.id"},
+                {"action": "hash", "key": "enduser.id", "salt": "s"},
+                {"action": "insert", "key": "retry", "value": 1.5},
+                {"action": "delete", "key": "http.method"}
+            ]),
+        ];
+
+        for rules in rule_sets {
+            let (row_output, column_output) = process_both_ways(&processor(rules.clone()), &spans);
+            assert_eq!(row_output, column_output, "{}", rules);
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::time::Instant;
 use prost::{EncodeError, Message};
 use serde_json::Value;
 
+use crate::allocator::AllocationProbe;
 use crate::BenchmarkResult;
 use common::{Attributes, Span};
 use oltp::opentelemetry::proto::common::v1::any_value;
@@ -11,8 +12,10 @@ use oltp::opentelemetry::proto::trace;
 use oltp::opentelemetry::proto::trace::v1::span::{Event, Link};
 use oltp::opentelemetry::proto::trace::v1::{InstrumentationLibrarySpans, ResourceSpans};
 
+// The prost messages own their fields, the strings of the spans are copied once into them (the spans are borrowed).
 pub fn serialize(spans: &[Span], bench_result: &mut BenchmarkResult) -> Result<Vec<u8>, EncodeError> {
     let start = Instant::now();
+    let probe = AllocationProbe::start();
 
     let resource_spans = ResourceSpans {
         resource: None,
@@ -21,12 +24,12 @@ pub fn serialize(spans: &[Span], bench_result: &mut BenchmarkResult) -> Result<V
             spans: spans
                 .iter()
                 .map(|span| trace::v1::Span {
-                    trace_id: span.trace_id.clone().into_bytes(),
-                    span_id: span.span_id.clone().into_bytes(),
-                    trace_state: span.trace_state.clone().unwrap_or_else(|| "".into()),
-                    parent_span_id: span.parent_span_id.clone().unwrap_or_else(|| "".into()).into_bytes(),
-                    name: span.name.clone(),
-                    kind: span.kind.unwrap_or(0),
+                    trace_id: span.trace_id.as_bytes().to_vec(),
+                    span_id: span.span_id.as_bytes().to_vec(),
+                    trace_state: span.trace_state.as_deref().map(str::to_string).unwrap_or_default(),
+                    parent_span_id: span.parent_span_id.as_deref().map(|id| id.as_bytes().to_vec()).unwrap_or_default(),
+                    name: span.name.to_string(),
+                    kind: span.kind.map_or(0, |kind| kind as i32),
                     start_time_unix_nano: span.start_time_unix_nano,
                     end_time_unix_nano: span.end_time_unix_nano.unwrap_or(0),
                     attributes: attributes(span.attributes.as_ref()),
@@ -38,7 +41,7 @@ pub fn serialize(spans: &[Span], bench_result: &mut BenchmarkResult) -> Result<V
                         .iter()
                         .map(|evt| Event {
                             time_unix_nano: evt.time_unix_nano,
-                            name: evt.name.clone(),
+                            name: evt.name.to_string(),
                             attributes: attributes(Some(&evt.attributes)),
                             dropped_attributes_count: evt.dropped_attributes_count.unwrap_or(0),
                         })
@@ -50,15 +53,19 @@ pub fn serialize(spans: &[Span], bench_result: &mut BenchmarkResult) -> Result<V
                         .unwrap_or(&vec![])
                         .iter()
                         .map(|link| Link {
-                            trace_id: link.trace_id.clone().into_bytes(),
-                            span_id: link.span_id.clone().into_bytes(),
-                            trace_state: link.trace_state.clone().unwrap_or_else(|| "".into()),
+                            trace_id: link.trace_id.as_bytes().to_vec(),
+                            span_id: link.span_id.as_bytes().to_vec(),
+                            trace_state: link.trace_state.as_deref().map(str::to_string).unwrap_or_default(),
                             attributes: attributes(Some(&link.attributes)),
                             dropped_attributes_count: link.dropped_attributes_count.unwrap_or(0),
                         })
                         .collect(),
                     dropped_links_count: span.dropped_links_count.unwrap_or(0),
-                    status: None,
+                    status: span.status.as_ref().map(|status| trace::v1::Status {
+                        message: status.message.as_deref().map(str::to_string).unwrap_or_default(),
+                        code: status.code.map_or(0, |code| code as i32),
+                        ..Default::default()
+                    }),
                 })
                 .collect(),
             schema_url: "".to_string(),
@@ -69,22 +76,27 @@ pub fn serialize(spans: &[Span], bench_result: &mut BenchmarkResult) -> Result<V
     // dbg!(&resource_spans);
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_creation_ns += elapse_time.as_nanos();
+    bench_result.buffer_creation_allocs.add(&probe.stop());
 
     let start = Instant::now();
+    let probe = AllocationProbe::start();
     let mut buf: Vec<u8> = Vec::new();
     resource_spans.encode(&mut buf)?;
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_serialization_ns += elapse_time.as_nanos();
+    bench_result.buffer_serialization_allocs.add(&probe.stop());
 
     Ok(buf)
 }
 
 pub fn deserialize(buf: Vec<u8>, bench_result: &mut BenchmarkResult) {
     let start = Instant::now();
+    let probe = AllocationProbe::start();
     let resource_spans = ResourceSpans::decode(bytes::Bytes::from(buf)).unwrap();
     assert_eq!(resource_spans.instrumentation_library_spans.len(), 1);
     let elapse_time = Instant::now() - start;
     bench_result.total_buffer_deserialization_ns += elapse_time.as_nanos();
+    bench_result.buffer_deserialization_allocs.add(&probe.stop());
 }
 
 fn attributes(attributes: Option<&Attributes>) -> Vec<KeyValue> {
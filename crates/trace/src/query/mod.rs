@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use arrow::array::{ArrayRef, UInt32Array};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use clap::{Clap, ValueHint};
+use comfy_table::Table;
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::ExecutionContext;
+use itertools::Itertools;
+
+use common::Span;
+
+use crate::arrow::merge::merge_trace_batches;
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::TraceBatch;
+use crate::arrow::{serialize_column_oriented_data_source, EncodingOptions};
+use crate::dump::{self, DumpEncoder};
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+#[derive(Clap, Debug)]
+pub struct QueryOpt {
+    /// SQL query to run over the `spans`, `events` and `links` tables (the table schemas are printed when omitted)
+    #[clap(short, long)]
+    pub sql: Option<String>,
+
+    /// Maximum batch size
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// Encoder whose batches are loaded from the Arrow IPC dump directories
+    #[clap(long, arg_enum, default_value = "arrow-column-oriented")]
+    pub encoder: DumpEncoder,
+
+    /// Span files or Arrow IPC dump directories (see the --dump-dir option of the benchmark)
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+// Registers the span, event and link record batches of the input files as the `spans`, `events` and `links` tables of
+// an embedded DataFusion context and runs a SQL query over them. The batches are merged into a single schema per table,
+// the `id` column of the `spans` table is the span row referenced by the `id` column of the `events` and `links` tables.
+pub fn query(opt: &QueryOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut batches = vec![];
+    for file in &opt.files {
+        eprint!("Loading '{}'...", file.display());
+        if file.is_dir() {
+            for batch_dir in dump::find_encoder_batch_dirs(file, opt.encoder)? {
+                batches.extend(dump::read_batch_dir(&batch_dir)?);
+            }
+        } else {
+            let mut stats_reporter = StatisticsReporter::noop();
+            for chunk in importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+                .chunks(opt.batch_size)
+                .into_iter()
+            {
+                let spans: Vec<Span> = chunk.collect::<Result<_, _>>().map_err(|error| importer::span_error(file, error))?;
+                let buf = serialize_column_oriented_data_source(stats_reporter.next_batch(), &spans, &EncodingOptions::default(), &mut BenchmarkResult::new())?;
+                batches.extend(TraceBatch::decode(buf.into())?);
+            }
+        }
+        eprintln!("DONE.");
+    }
+    if batches.is_empty() {
+        return Err("no span batch found in the input files".into());
+    }
+
+    let merged = merge_trace_batches(&batches, &EncodingOptions::default())?;
+    let mut ctx = ExecutionContext::new();
+    let tables = vec![
+        ("spans", Some(with_row_ids(&merged.spans)?)),
+        ("events", merged.events.as_ref().map(unpack_dictionaries).transpose()?),
+        ("links", merged.links.as_ref().map(unpack_dictionaries).transpose()?),
+    ];
+    for (name, batch) in tables.iter() {
+        if let Some(batch) = batch {
+            ctx.register_table(*name, Arc::new(MemTable::try_new(batch.schema(), vec![vec![batch.clone()]])?))?;
+        }
+    }
+
+    let sql = match &opt.sql {
+        Some(sql) => sql,
+        None => {
+            print_schemas(&tables);
+            return Ok(());
+        }
+    };
+
+    let start = Instant::now();
+    let data_frame = ctx.sql(sql)?;
+    let results = tokio::runtime::Runtime::new()?.block_on(data_frame.collect())?;
+    let elapse_time = Instant::now() - start;
+
+    println!("{}", pretty_format_batches(&results)?);
+    println!(
+        "{} rows in {:.3} ms",
+        results.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+        elapse_time.as_secs_f64() * 1000.0
+    );
+
+    Ok(())
+}
+
+fn print_schemas(tables: &[(&str, Option<RecordBatch>)]) {
+    let mut table = Table::new();
+    table.set_header(vec!["Table", "Rows", "Column", "Type"]);
+    for (name, batch) in tables.iter() {
+        if let Some(batch) = batch {
+            for field in batch.schema().fields() {
+                table.add_row(vec![
+                    name.to_string(),
+                    batch.num_rows().to_string(),
+                    field.name().clone(),
+                    format!("{:?}", field.data_type()),
+                ]);
+            }
+        }
+    }
+    println!("{}", table);
+}
+
+// Adds the row number of the spans as an `id` column (merged span batches have none) so events and links can be joined
+// to their span.
+fn with_row_ids(spans: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let spans = unpack_dictionaries(spans)?;
+    let mut fields = vec![Field::new("id", DataType::UInt32, false)];
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(UInt32Array::from_iter_values(0..spans.num_rows() as u32))];
+    fields.extend(spans.schema().fields().iter().cloned());
+    columns.extend(spans.columns().iter().cloned());
+    RecordBatch::try_new(Arc::new(Schema::new_with_metadata(fields, spans.schema().metadata().clone())), columns)
+}
+
+// Dictionary encoded columns are cast back to their value type, the support of dictionaries in the expressions of the
+// query engine is partial. The field and schema metadata are kept.
+fn unpack_dictionaries(batch: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let mut fields = vec![];
+    let mut columns = vec![];
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        match field.data_type() {
+            DataType::Dictionary(_, value_type) => {
+                let mut unpacked_field = Field::new(field.name(), value_type.as_ref().clone(), field.is_nullable());
+                unpacked_field.set_metadata(field.metadata().clone());
+                fields.push(unpacked_field);
+                columns.push(cast(column, value_type)?);
+            }
+            _ => {
+                fields.push(field.clone());
+                columns.push(column.clone());
+            }
+        }
+    }
+    RecordBatch::try_new(Arc::new(Schema::new_with_metadata(fields, batch.schema().metadata().clone())), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use arrow::array::{DictionaryArray, UInt64Array};
+    use arrow::datatypes::UInt8Type;
+
+    use crate::arrow::ORIGINAL_TYPE_KEY;
+
+    use super::*;
+
+    #[test]
+    fn row_ids_and_unpacked_dictionaries_keep_the_metadata() {
+        let metadata: BTreeMap<String, String> = vec![(ORIGINAL_TYPE_KEY.to_string(), "string".to_string())].into_iter().collect();
+        let mut name = Field::new("name", DataType::Dictionary(Box::new(DataType::UInt8), Box::new(DataType::Utf8)), false);
+        name.set_metadata(Some(metadata.clone()));
+        let mut status = Field::new("attributes_status", DataType::UInt64, false);
+        status.set_metadata(Some(metadata.clone()));
+        let schema_metadata: HashMap<String, String> = vec![("origin".to_string(), "test".to_string())].into_iter().collect();
+        let spans = RecordBatch::try_new(
+            Arc::new(Schema::new_with_metadata(vec![name, status], schema_metadata.clone())),
+            vec![
+                Arc::new(vec!["a", "b", "a"].into_iter().collect::<DictionaryArray<UInt8Type>>()),
+                Arc::new(UInt64Array::from(vec![200, 404, 200])),
+            ],
+        )
+        .unwrap();
+
+        let table = with_row_ids(&spans).unwrap();
+        let schema = table.schema();
+        assert_eq!(schema.metadata(), &schema_metadata);
+        assert_eq!(schema.field(0).name(), "id");
+        let name = schema.field_with_name("name").unwrap();
+        assert_eq!(name.data_type(), &DataType::Utf8);
+        assert_eq!(name.metadata(), &Some(metadata.clone()));
+        assert_eq!(schema.field_with_name("attributes_status").unwrap().metadata(), &Some(metadata));
+    }
+}
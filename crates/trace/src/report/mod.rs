@@ -0,0 +1,318 @@
+use std::io::Write;
+
+use clap::ArgEnum;
+use serde::Serialize;
+
+use crate::{ArrowVsProto, BenchmarkResult};
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct PhaseStatistics {
+    pub min_ns: f64,
+    pub median_ns: f64,
+    pub p95_ns: f64,
+    pub p99_ns: f64,
+    pub std_dev_ns: f64,
+}
+
+// One row per file and encoder, flat so it can be written as CSV as well as JSON.
+#[derive(Serialize, Debug)]
+pub struct EncoderMetrics {
+    pub file: String,
+    pub encoder: &'static str,
+    pub batch_count: usize,
+    pub span_count: usize,
+
+    pub span_copy_ns: u64,
+    pub infer_schema_ns: u64,
+    pub data_source_conversion_ns: u64,
+    pub buffer_creation_ns: u64,
+    pub buffer_serialization_ns: u64,
+    pub buffer_compression_ns: u64,
+    pub buffer_decompression_ns: u64,
+    pub buffer_deserialization_ns: u64,
+    pub total_ns: u64,
+    pub total_with_span_copy_ns: u64,
+
+    pub buffer_size: usize,
+    pub compressed_buffer_size: usize,
+    pub buffer_bytes_per_span: f64,
+    pub compressed_buffer_bytes_per_span: f64,
+    pub compression_ratio: f64,
+
+    pub span_copy_ns_per_span: f64,
+    pub infer_schema_ns_per_span: f64,
+    pub data_source_conversion_ns_per_span: f64,
+    pub buffer_creation_ns_per_span: f64,
+    pub buffer_serialization_ns_per_span: f64,
+    pub buffer_compression_ns_per_span: f64,
+    pub buffer_decompression_ns_per_span: f64,
+    pub buffer_deserialization_ns_per_span: f64,
+    pub total_ns_per_span: f64,
+    pub total_with_span_copy_ns_per_span: f64,
+
+    // Allocations over all batches, peak live bytes is the highest peak of a single batch.
+    pub span_copy_allocations: u64,
+    pub span_copy_allocated_bytes: u64,
+    pub span_copy_peak_live_bytes: u64,
+    pub infer_schema_allocations: u64,
+    pub infer_schema_allocated_bytes: u64,
+    pub infer_schema_peak_live_bytes: u64,
+    pub data_source_conversion_allocations: u64,
+    pub data_source_conversion_allocated_bytes: u64,
+    pub data_source_conversion_peak_live_bytes: u64,
+    pub buffer_creation_allocations: u64,
+    pub buffer_creation_allocated_bytes: u64,
+    pub buffer_creation_peak_live_bytes: u64,
+    pub buffer_serialization_allocations: u64,
+    pub buffer_serialization_allocated_bytes: u64,
+    pub buffer_serialization_peak_live_bytes: u64,
+    pub buffer_compression_allocations: u64,
+    pub buffer_compression_allocated_bytes: u64,
+    pub buffer_compression_peak_live_bytes: u64,
+    pub buffer_decompression_allocations: u64,
+    pub buffer_decompression_allocated_bytes: u64,
+    pub buffer_decompression_peak_live_bytes: u64,
+    pub buffer_deserialization_allocations: u64,
+    pub buffer_deserialization_allocated_bytes: u64,
+    pub buffer_deserialization_peak_live_bytes: u64,
+
+    // Distribution of the per batch phase durations over all batches and repetitions.
+    pub span_copy_min_ns: f64,
+    pub span_copy_median_ns: f64,
+    pub span_copy_p95_ns: f64,
+    pub span_copy_p99_ns: f64,
+    pub span_copy_std_dev_ns: f64,
+    pub infer_schema_min_ns: f64,
+    pub infer_schema_median_ns: f64,
+    pub infer_schema_p95_ns: f64,
+    pub infer_schema_p99_ns: f64,
+    pub infer_schema_std_dev_ns: f64,
+    pub data_source_conversion_min_ns: f64,
+    pub data_source_conversion_median_ns: f64,
+    pub data_source_conversion_p95_ns: f64,
+    pub data_source_conversion_p99_ns: f64,
+    pub data_source_conversion_std_dev_ns: f64,
+    pub buffer_creation_min_ns: f64,
+    pub buffer_creation_median_ns: f64,
+    pub buffer_creation_p95_ns: f64,
+    pub buffer_creation_p99_ns: f64,
+    pub buffer_creation_std_dev_ns: f64,
+    pub buffer_serialization_min_ns: f64,
+    pub buffer_serialization_median_ns: f64,
+    pub buffer_serialization_p95_ns: f64,
+    pub buffer_serialization_p99_ns: f64,
+    pub buffer_serialization_std_dev_ns: f64,
+    pub buffer_compression_min_ns: f64,
+    pub buffer_compression_median_ns: f64,
+    pub buffer_compression_p95_ns: f64,
+    pub buffer_compression_p99_ns: f64,
+    pub buffer_compression_std_dev_ns: f64,
+    pub buffer_decompression_min_ns: f64,
+    pub buffer_decompression_median_ns: f64,
+    pub buffer_decompression_p95_ns: f64,
+    pub buffer_decompression_p99_ns: f64,
+    pub buffer_decompression_std_dev_ns: f64,
+    pub buffer_deserialization_min_ns: f64,
+    pub buffer_deserialization_median_ns: f64,
+    pub buffer_deserialization_p95_ns: f64,
+    pub buffer_deserialization_p99_ns: f64,
+    pub buffer_deserialization_std_dev_ns: f64,
+
+    // Relative differences with the protobuf reference implementation, in percent (negative = smaller/faster).
+    pub buffer_size_vs_protobuf_pct: f64,
+    pub compressed_buffer_size_vs_protobuf_pct: f64,
+    pub total_time_vs_protobuf_pct: f64,
+    pub total_time_with_span_copy_vs_protobuf_pct: f64,
+}
+
+pub fn encoder_metrics(results: &[ArrowVsProto]) -> Vec<EncoderMetrics> {
+    results
+        .iter()
+        .flat_map(|result| {
+            vec![
+                EncoderMetrics::new(&result.file, "protobuf", &result.proto, &result.proto),
+                EncoderMetrics::new(&result.file, "arrow_row_oriented", &result.arrow_1, &result.proto),
+                EncoderMetrics::new(&result.file, "arrow_column_oriented", &result.arrow_2, &result.proto),
+                EncoderMetrics::new(&result.file, "arrow_builder", &result.arrow_3, &result.proto),
+            ]
+        })
+        .collect()
+}
+
+pub fn write_json<W: Write>(results: &[ArrowVsProto], mut writer: W) -> Result<(), Box<dyn std::error::Error>> {
+    serde_json::to_writer_pretty(&mut writer, &encoder_metrics(results))?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+pub fn write_csv<W: Write>(results: &[ArrowVsProto], writer: W) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for metrics in encoder_metrics(results) {
+        writer.serialize(metrics)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl EncoderMetrics {
+    fn new(file: &str, encoder: &'static str, result: &BenchmarkResult, reference: &BenchmarkResult) -> Self {
+        let span_count = result.row_count.max(1) as f64;
+        let samples = result.samples();
+        let span_copy = PhaseStatistics::new(&samples.span_copy_ns);
+        let infer_schema = PhaseStatistics::new(&samples.infer_schema_ns);
+        let data_source_conversion = PhaseStatistics::new(&samples.data_source_conversion_ns);
+        let buffer_creation = PhaseStatistics::new(&samples.buffer_creation_ns);
+        let buffer_serialization = PhaseStatistics::new(&samples.buffer_serialization_ns);
+        let buffer_compression = PhaseStatistics::new(&samples.buffer_compression_ns);
+        let buffer_decompression = PhaseStatistics::new(&samples.buffer_decompression_ns);
+        let buffer_deserialization = PhaseStatistics::new(&samples.buffer_deserialization_ns);
+
+        Self {
+            file: file.into(),
+            encoder,
+            batch_count: result.batch_count,
+            span_count: result.row_count,
+
+            span_copy_ns: result.total_span_copy_ns as u64,
+            infer_schema_ns: result.total_infer_schema_ns as u64,
+            data_source_conversion_ns: result.total_data_source_conversion_ns as u64,
+            buffer_creation_ns: result.total_buffer_creation_ns as u64,
+            buffer_serialization_ns: result.total_buffer_serialization_ns as u64,
+            buffer_compression_ns: result.total_buffer_compression_ns as u64,
+            buffer_decompression_ns: result.total_buffer_decompression_ns as u64,
+            buffer_deserialization_ns: result.total_buffer_deserialization_ns as u64,
+            total_ns: result.total_time_ns() as u64,
+            total_with_span_copy_ns: result.total_time_with_span_copy_ns() as u64,
+
+            buffer_size: result.total_buffer_size,
+            compressed_buffer_size: result.total_compressed_buffer_size,
+            buffer_bytes_per_span: result.total_buffer_size as f64 / span_count,
+            compressed_buffer_bytes_per_span: result.total_compressed_buffer_size as f64 / span_count,
+            compression_ratio: result.total_buffer_size as f64 / result.total_compressed_buffer_size.max(1) as f64,
+
+            span_copy_ns_per_span: result.total_span_copy_ns as f64 / span_count,
+            infer_schema_ns_per_span: result.total_infer_schema_ns as f64 / span_count,
+            data_source_conversion_ns_per_span: result.total_data_source_conversion_ns as f64 / span_count,
+            buffer_creation_ns_per_span: result.total_buffer_creation_ns as f64 / span_count,
+            buffer_serialization_ns_per_span: result.total_buffer_serialization_ns as f64 / span_count,
+            buffer_compression_ns_per_span: result.total_buffer_compression_ns as f64 / span_count,
+            buffer_decompression_ns_per_span: result.total_buffer_decompression_ns as f64 / span_count,
+            buffer_deserialization_ns_per_span: result.total_buffer_deserialization_ns as f64 / span_count,
+            total_ns_per_span: result.total_time_ns() as f64 / span_count,
+            total_with_span_copy_ns_per_span: result.total_time_with_span_copy_ns() as f64 / span_count,
+
+            span_copy_allocations: result.span_copy_allocs.allocations,
+            span_copy_allocated_bytes: result.span_copy_allocs.allocated_bytes,
+            span_copy_peak_live_bytes: result.span_copy_allocs.peak_live_bytes,
+            infer_schema_allocations: result.infer_schema_allocs.allocations,
+            infer_schema_allocated_bytes: result.infer_schema_allocs.allocated_bytes,
+            infer_schema_peak_live_bytes: result.infer_schema_allocs.peak_live_bytes,
+            data_source_conversion_allocations: result.data_source_conversion_allocs.allocations,
+            data_source_conversion_allocated_bytes: result.data_source_conversion_allocs.allocated_bytes,
+            data_source_conversion_peak_live_bytes: result.data_source_conversion_allocs.peak_live_bytes,
+            buffer_creation_allocations: result.buffer_creation_allocs.allocations,
+            buffer_creation_allocated_bytes: result.buffer_creation_allocs.allocated_bytes,
+            buffer_creation_peak_live_bytes: result.buffer_creation_allocs.peak_live_bytes,
+            buffer_serialization_allocations: result.buffer_serialization_allocs.allocations,
+            buffer_serialization_allocated_bytes: result.buffer_serialization_allocs.allocated_bytes,
+            buffer_serialization_peak_live_bytes: result.buffer_serialization_allocs.peak_live_bytes,
+            buffer_compression_allocations: result.buffer_compression_allocs.allocations,
+            buffer_compression_allocated_bytes: result.buffer_compression_allocs.allocated_bytes,
+            buffer_compression_peak_live_bytes: result.buffer_compression_allocs.peak_live_bytes,
+            buffer_decompression_allocations: result.buffer_decompression_allocs.allocations,
+            buffer_decompression_allocated_bytes: result.buffer_decompression_allocs.allocated_bytes,
+            buffer_decompression_peak_live_bytes: result.buffer_decompression_allocs.peak_live_bytes,
+            buffer_deserialization_allocations: result.buffer_deserialization_allocs.allocations,
+            buffer_deserialization_allocated_bytes: result.buffer_deserialization_allocs.allocated_bytes,
+            buffer_deserialization_peak_live_bytes: result.buffer_deserialization_allocs.peak_live_bytes,
+
+            span_copy_min_ns: span_copy.min_ns,
+            span_copy_median_ns: span_copy.median_ns,
+            span_copy_p95_ns: span_copy.p95_ns,
+            span_copy_p99_ns: span_copy.p99_ns,
+            span_copy_std_dev_ns: span_copy.std_dev_ns,
+            infer_schema_min_ns: infer_schema.min_ns,
+            infer_schema_median_ns: infer_schema.median_ns,
+            infer_schema_p95_ns: infer_schema.p95_ns,
+            infer_schema_p99_ns: infer_schema.p99_ns,
+            infer_schema_std_dev_ns: infer_schema.std_dev_ns,
+            data_source_conversion_min_ns: data_source_conversion.min_ns,
+            data_source_conversion_median_ns: data_source_conversion.median_ns,
+            data_source_conversion_p95_ns: data_source_conversion.p95_ns,
+            data_source_conversion_p99_ns: data_source_conversion.p99_ns,
+            data_source_conversion_std_dev_ns: data_source_conversion.std_dev_ns,
+            buffer_creation_min_ns: buffer_creation.min_ns,
+            buffer_creation_median_ns: buffer_creation.median_ns,
+            buffer_creation_p95_ns: buffer_creation.p95_ns,
+            buffer_creation_p99_ns: buffer_creation.p99_ns,
+            buffer_creation_std_dev_ns: buffer_creation.std_dev_ns,
+            buffer_serialization_min_ns: buffer_serialization.min_ns,
+            buffer_serialization_median_ns: buffer_serialization.median_ns,
+            buffer_serialization_p95_ns: buffer_serialization.p95_ns,
+            buffer_serialization_p99_ns: buffer_serialization.p99_ns,
+            buffer_serialization_std_dev_ns: buffer_serialization.std_dev_ns,
+            buffer_compression_min_ns: buffer_compression.min_ns,
+            buffer_compression_median_ns: buffer_compression.median_ns,
+            buffer_compression_p95_ns: buffer_compression.p95_ns,
+            buffer_compression_p99_ns: buffer_compression.p99_ns,
+            buffer_compression_std_dev_ns: buffer_compression.std_dev_ns,
+            buffer_decompression_min_ns: buffer_decompression.min_ns,
+            buffer_decompression_median_ns: buffer_decompression.median_ns,
+            buffer_decompression_p95_ns: buffer_decompression.p95_ns,
+            buffer_decompression_p99_ns: buffer_decompression.p99_ns,
+            buffer_decompression_std_dev_ns: buffer_decompression.std_dev_ns,
+            buffer_deserialization_min_ns: buffer_deserialization.min_ns,
+            buffer_deserialization_median_ns: buffer_deserialization.median_ns,
+            buffer_deserialization_p95_ns: buffer_deserialization.p95_ns,
+            buffer_deserialization_p99_ns: buffer_deserialization.p99_ns,
+            buffer_deserialization_std_dev_ns: buffer_deserialization.std_dev_ns,
+
+            buffer_size_vs_protobuf_pct: delta_pct(result.total_buffer_size as f64, reference.total_buffer_size as f64),
+            compressed_buffer_size_vs_protobuf_pct: delta_pct(result.total_compressed_buffer_size as f64, reference.total_compressed_buffer_size as f64),
+            total_time_vs_protobuf_pct: delta_pct(result.total_time_ns() as f64, reference.total_time_ns() as f64),
+            total_time_with_span_copy_vs_protobuf_pct: delta_pct(result.total_time_with_span_copy_ns() as f64, reference.total_time_with_span_copy_ns() as f64),
+        }
+    }
+}
+
+impl PhaseStatistics {
+    pub fn new(samples: &[u128]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted: Vec<f64> = samples.iter().map(|sample| *sample as f64).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mean = sorted.iter().sum::<f64>() / sorted.len() as f64;
+        let variance = sorted.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / sorted.len() as f64;
+
+        Self {
+            min_ns: sorted[0],
+            median_ns: percentile(&sorted, 50.0),
+            p95_ns: percentile(&sorted, 95.0),
+            p99_ns: percentile(&sorted, 99.0),
+            std_dev_ns: variance.sqrt(),
+        }
+    }
+}
+
+// Nearest-rank percentile of a sorted, non empty, slice.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn delta_pct(value: f64, reference: f64) -> f64 {
+    if reference == 0.0 {
+        0.0
+    } else {
+        (value - reference) / reference * 100.0
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, StringArray, UInt32Array};
+use arrow::compute::kernels::comparison::{eq_scalar, eq_utf8_scalar};
+use arrow::compute::{cast, filter_record_batch};
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use clap::{Clap, ValueHint};
+use comfy_table::Table;
+use twox_hash::XxHash64;
+
+use common::{Span, StatusCode};
+
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::TraceBatch;
+use crate::arrow::{serialize_column_oriented_data_source, EncodingOptions};
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+const STATUS_CODE_ERROR: u8 = StatusCode::Error as u8;
+
+#[derive(Clap, Debug)]
+pub struct SampleOpt {
+    /// Maximum batch size, traces are never split across batches (a larger trace gets its own batch)
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Keep the traces containing a span with an error status
+    #[clap(long)]
+    pub errors: bool,
+
+    /// Keep the traces containing a span lasting more than this threshold (in milliseconds)
+    #[clap(long)]
+    pub latency_threshold_ms: Option<f64>,
+
+    /// Keep the traces containing a span with this attribute value, as `key=value` (can be repeated)
+    #[clap(long = "attribute", number_of_values = 1, multiple_occurrences = true)]
+    pub attributes: Vec<String>,
+
+    /// Keep this ratio of the traces, selected by trace id hash [0..1]
+    #[clap(long)]
+    pub probability: Option<f64>,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SamplingPolicy {
+    // Status code ERROR.
+    Error,
+    // `end_time_unix_nano - start_time_unix_nano` above the threshold.
+    Latency { threshold_ns: u64 },
+    // Attribute value equal to `value` once converted to a string.
+    Attribute { key: String, value: String },
+    // Trace id hash below `ratio` of the hash range, so a trace gets the same decision in every batch.
+    Probabilistic { ratio: f64 },
+}
+
+// Tail-based sampler. A trace of a batch is kept when at least one of its spans matches a policy, or when it is selected
+// by the probabilistic policy. Decisions are taken batch by batch, the batches must contain whole traces (see
+// `trace_batches`).
+#[derive(Debug, Clone)]
+pub struct TailSampler {
+    policies: Vec<SamplingPolicy>,
+}
+
+impl TailSampler {
+    pub fn new(policies: Vec<SamplingPolicy>) -> Self {
+        Self { policies }
+    }
+
+    pub fn sample(&self, batch: &TraceBatch) -> Result<TraceBatch, ArrowError> {
+        let view = batch.view()?;
+        let span_count = view.span_count();
+        let mut matches = vec![false; span_count];
+        let mut ratio = None;
+
+        for policy in self.policies.iter() {
+            match policy {
+                SamplingPolicy::Error => {
+                    if let Some(status_codes) = view.status_codes() {
+                        mark_matches(&eq_scalar(status_codes, STATUS_CODE_ERROR)?, &mut matches);
+                    }
+                }
+                SamplingPolicy::Latency { threshold_ns } => {
+                    for (row, matched) in matches.iter_mut().enumerate() {
+                        *matched |= view.duration_ns(row).is_some_and(|duration| duration > *threshold_ns);
+                    }
+                }
+                SamplingPolicy::Attribute { key, value } => {
+                    if let Some(column) = column(&batch.spans, &format!("attributes_{}", key)) {
+                        let strings = cast(column, &DataType::Utf8)?;
+                        let strings = strings.as_any().downcast_ref::<StringArray>().expect("cast to Utf8 must return a StringArray");
+                        mark_matches(&eq_utf8_scalar(strings, value)?, &mut matches);
+                    }
+                }
+                SamplingPolicy::Probabilistic { ratio: policy_ratio } => ratio = Some(ratio.unwrap_or(0.0f64).max(*policy_ratio)),
+            }
+        }
+
+        let trace_ids = view.trace_ids();
+        let mut sampled_traces: HashMap<&[u8], bool> = HashMap::new();
+        for (row, matched) in matches.iter().enumerate() {
+            let trace_id = trace_ids.value(row);
+            let sampled = sampled_traces
+                .entry(trace_id)
+                .or_insert_with(|| ratio.is_some_and(|ratio| sampled_by_hash(trace_id, ratio)));
+            *sampled |= *matched;
+        }
+
+        let span_mask: Vec<bool> = (0..span_count).map(|row| sampled_traces[trace_ids.value(row)]).collect();
+        filter_trace_batch(batch, &span_mask)
+    }
+}
+
+fn mark_matches(mask: &BooleanArray, matches: &mut [bool]) {
+    for (row, matched) in matches.iter_mut().enumerate() {
+        *matched |= mask.is_valid(row) && mask.value(row);
+    }
+}
+
+fn sampled_by_hash(trace_id: &[u8], ratio: f64) -> bool {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(trace_id);
+    (hasher.finish() as f64 / u64::MAX as f64) < ratio
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Option<&'a ArrayRef> {
+    batch.schema().column_with_name(name).map(|(index, _)| batch.column(index))
+}
+
+// Filters the spans with the mask, then the events and links of the remaining spans. When the span batch has no `id`
+// column, events and links reference the span row, so their `id` column is rewritten with the new span rows.
+pub fn filter_trace_batch(batch: &TraceBatch, span_mask: &[bool]) -> Result<TraceBatch, ArrowError> {
+    let spans = filter_record_batch(&batch.spans, &BooleanArray::from(span_mask.to_vec()))?;
+    let span_ids = column(&batch.spans, "id").and_then(|ids| ids.as_any().downcast_ref::<UInt32Array>());
+
+    let mut new_ids: HashMap<u32, u32> = HashMap::new();
+    for (row, _) in span_mask.iter().enumerate().filter(|(_, keep)| **keep) {
+        match span_ids {
+            Some(span_ids) => new_ids.insert(span_ids.value(row), span_ids.value(row)),
+            None => new_ids.insert(row as u32, new_ids.len() as u32),
+        };
+    }
+
+    Ok(TraceBatch {
+        spans,
+        events: filter_by_span_id(batch.events.as_ref(), &new_ids)?,
+        links: filter_by_span_id(batch.links.as_ref(), &new_ids)?,
+    })
+}
+
+fn filter_by_span_id(batch: Option<&RecordBatch>, new_ids: &HashMap<u32, u32>) -> Result<Option<RecordBatch>, ArrowError> {
+    let batch = match batch {
+        Some(batch) => batch,
+        None => return Ok(None),
+    };
+    let id_index = batch.schema().index_of("id")?;
+    let ids = batch
+        .column(id_index)
+        .as_any()
+        .downcast_ref::<UInt32Array>()
+        .ok_or_else(|| ArrowError::SchemaError("invalid id column".into()))?;
+
+    let mask: BooleanArray = ids.iter().map(|id| Some(id.is_some_and(|id| new_ids.contains_key(&id)))).collect();
+    let filtered = filter_record_batch(batch, &mask)?;
+    if filtered.num_rows() == 0 {
+        return Ok(None);
+    }
+
+    let filtered_ids = filtered
+        .column(id_index)
+        .as_any()
+        .downcast_ref::<UInt32Array>()
+        .expect("id column must be a UInt32Array");
+    let remapped_ids: Vec<u32> = filtered_ids.values().iter().map(|id| new_ids[id]).collect();
+    let mut columns = filtered.columns().to_vec();
+    columns[id_index] = Arc::new(UInt32Array::from(remapped_ids));
+
+    Ok(Some(RecordBatch::try_new(filtered.schema(), columns)?))
+}
+
+pub fn sample(opt: &SampleOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let sampler = TailSampler::new(policies(opt)?);
+    let mut table = Table::new();
+    table.set_header(vec![
+        "File",
+        "Spans (in/out)",
+        "Events (in/out)",
+        "Links (in/out)",
+        "Sampling (ms)",
+        "Sampling ns/span",
+    ]);
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Processing file '{}'...", filename);
+        let mut stats_reporter = StatisticsReporter::noop();
+        let mut counts_in = [0usize; 3];
+        let mut counts_out = [0usize; 3];
+        let mut sampling_ns = 0u128;
+
+        let spans: Vec<Span> = importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .collect::<Result<_, _>>()
+            .map_err(|error| importer::span_error(file, error))?;
+
+        for spans in trace_batches(spans, opt.batch_size) {
+            let buf = serialize_column_oriented_data_source(stats_reporter.next_batch(), &spans, &EncodingOptions::default(), &mut BenchmarkResult::new())?;
+
+            for batch in TraceBatch::decode(buf.into())? {
+                let start = Instant::now();
+                let sampled_batch = sampler.sample(&batch)?;
+                sampling_ns += (Instant::now() - start).as_nanos();
+
+                add_counts(&batch, &mut counts_in);
+                add_counts(&sampled_batch, &mut counts_out);
+            }
+        }
+
+        table.add_row(vec![
+            filename,
+            format!("{}/{}", counts_in[0], counts_out[0]),
+            format!("{}/{}", counts_in[1], counts_out[1]),
+            format!("{}/{}", counts_in[2], counts_out[2]),
+            format!("{:.3}", sampling_ns as f64 / 1000000.0),
+            format!("{:.1}", sampling_ns as f64 / counts_in[0].max(1) as f64),
+        ]);
+        eprintln!("DONE.");
+    }
+
+    println!("{}", table);
+    Ok(())
+}
+
+// Groups the spans by trace (in order of first appearance) and packs whole traces into batches of at most `batch_size`
+// spans, a trace larger than `batch_size` gets its own batch.
+fn trace_batches(spans: Vec<Span>, batch_size: usize) -> Vec<Vec<Span>> {
+    let mut traces: Vec<Vec<Span>> = vec![];
+    let mut trace_index: HashMap<String, usize> = HashMap::new();
+    for span in spans {
+        let index = *trace_index.entry(span.trace_id.to_string()).or_insert_with(|| {
+            traces.push(vec![]);
+            traces.len() - 1
+        });
+        traces[index].push(span);
+    }
+
+    let mut batches = vec![];
+    let mut batch: Vec<Span> = vec![];
+    for trace in traces {
+        if !batch.is_empty() && batch.len() + trace.len() > batch_size {
+            batches.push(std::mem::take(&mut batch));
+        }
+        batch.extend(trace);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+fn policies(opt: &SampleOpt) -> Result<Vec<SamplingPolicy>, String> {
+    let mut policies = vec![];
+
+    if opt.errors {
+        policies.push(SamplingPolicy::Error);
+    }
+    if let Some(threshold_ms) = opt.latency_threshold_ms {
+        policies.push(SamplingPolicy::Latency {
+            threshold_ns: (threshold_ms * 1_000_000.0) as u64,
+        });
+    }
+    for attribute in opt.attributes.iter() {
+        let (key, value) = attribute
+            .split_once('=')
+            .ok_or_else(|| format!("invalid attribute policy '{}', expected key=value", attribute))?;
+        policies.push(SamplingPolicy::Attribute {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+    }
+    if let Some(ratio) = opt.probability {
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err("probability must be in [0..1]".into());
+        }
+        policies.push(SamplingPolicy::Probabilistic { ratio });
+    }
+
+    if policies.is_empty() {
+        return Err("at least one sampling policy must be specified".into());
+    }
+    Ok(policies)
+}
+
+fn add_counts(batch: &TraceBatch, counts: &mut [usize; 3]) {
+    counts[0] += batch.spans.num_rows();
+    counts[1] += batch.events.as_ref().map(|events| events.num_rows()).unwrap_or(0);
+    counts[2] += batch.links.as_ref().map(|links| links.num_rows()).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::arrow::tests::{encode, spans};
+    use crate::arrow::ColumnOrientedEncoder;
+
+    use super::*;
+
+    fn to_values(spans: &[Span]) -> Vec<Value> {
+        spans.iter().map(|span| serde_json::to_value(span).unwrap()).collect()
+    }
+
+    #[test]
+    fn events_and_links_follow_their_spans() {
+        let spans = spans(20);
+        let batch = encode(&ColumnOrientedEncoder, &spans);
+
+        // keeps every other span, events and links must still belong to the same spans
+        let span_mask: Vec<bool> = (0..spans.len()).map(|row| row % 2 == 1).collect();
+        let filtered = filter_trace_batch(&batch, &span_mask).unwrap();
+        let expected: Vec<Span> = spans
+            .iter()
+            .zip(span_mask.iter())
+            .filter(|(_, keep)| **keep)
+            .map(|(span, _)| span.clone())
+            .collect();
+        assert_eq!(to_values(&filtered.view().unwrap().materialize()), to_values(&expected));
+
+        // trace-0, trace-1 and trace-3 contain a span with an error status (spans 0, 7 and 14)
+        let sampled = TailSampler::new(vec![SamplingPolicy::Error]).sample(&batch).unwrap();
+        let expected: Vec<Span> = spans
+            .iter()
+            .filter(|span| ["trace-0", "trace-1", "trace-3"].contains(&span.trace_id.as_ref()))
+            .cloned()
+            .collect();
+        assert_eq!(to_values(&sampled.view().unwrap().materialize()), to_values(&expected));
+        assert_eq!(sampled.events.as_ref().unwrap().num_rows(), expected.len());
+        assert_eq!(
+            sampled.links.as_ref().unwrap().num_rows(),
+            expected.iter().filter(|span| span.links.is_some()).count()
+        );
+
+        let dropped = filter_trace_batch(&batch, &vec![false; spans.len()]).unwrap();
+        assert_eq!(dropped.spans.num_rows(), 0);
+        assert!(dropped.events.is_none() && dropped.links.is_none());
+    }
+
+    #[test]
+    fn traces_are_not_split_across_batches() {
+        let mut spans = spans(10);
+        // a span of trace-2 comes before the end of trace-0
+        spans.swap(1, 8);
+        let trace_ids = |batch: &[Span]| batch.iter().map(|span| span.trace_id.to_string()).collect::<Vec<_>>();
+
+        let batches = trace_batches(spans, 3);
+        assert_eq!(
+            batches.iter().map(|batch| trace_ids(batch)).collect::<Vec<_>>(),
+            vec![vec!["trace-0"; 4], vec!["trace-2"; 2], vec!["trace-1"; 4]]
+        );
+    }
+}
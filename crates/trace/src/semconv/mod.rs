@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{Number, Value};
+
+use common::{Attributes, Span};
+
+// Semantic convention attribute and the non-standard keys mapped to it. Example of configuration (see
+// `data/semconv.json`):
+// [
+//   {"key": "http.method", "aliases": ["http_method", "method"]},
+//   {"key": "http.status_code", "aliases": ["http_status_code", "response_code"], "type": "int"}
+// ]
+#[derive(Debug, Clone, Deserialize)]
+pub struct SemconvAttribute {
+    pub key: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    // Values are kept as they are when not defined.
+    #[serde(rename = "type")]
+    pub value_type: Option<SemconvType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SemconvType {
+    String,
+    Int,
+    Double,
+    Boolean,
+}
+
+// Renames the aliases of the semantic convention attributes and casts their values, applied to the span, event and
+// link attributes before encoding. For every attribute of the table, the first non-null value that can be cast (the
+// attribute itself, then its aliases in order) is kept under the semantic convention key. Null aliases are removed,
+// the other aliases (value that can't be cast or attribute already defined) keep their key so no value is lost and an
+// attribute never gets values of different types.
+#[derive(Debug)]
+pub struct AttributeNormalizer {
+    attributes: Vec<SemconvAttribute>,
+    // index of the table entry of every key and alias
+    keys: HashMap<String, usize>,
+}
+
+impl AttributeNormalizer {
+    pub fn new(attributes: Vec<SemconvAttribute>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut keys = HashMap::new();
+        for (index, attribute) in attributes.iter().enumerate() {
+            for key in std::iter::once(&attribute.key).chain(attribute.aliases.iter()) {
+                if keys.insert(key.clone(), index).is_some() {
+                    return Err(format!("'{}' is defined more than once in the semantic convention table", key).into());
+                }
+            }
+        }
+
+        Ok(Self { attributes, keys })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let attributes: Vec<SemconvAttribute> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Self::new(attributes)
+    }
+
+    pub fn normalize_spans(&self, spans: &mut [Span<'_>]) {
+        for span in spans.iter_mut() {
+            if let Some(attributes) = span.attributes.as_mut() {
+                self.normalize_attributes(attributes);
+            }
+            for event in span.events.iter_mut().flatten() {
+                self.normalize_attributes(&mut event.attributes);
+            }
+            for link in span.links.iter_mut().flatten() {
+                self.normalize_attributes(&mut link.attributes);
+            }
+        }
+    }
+
+    pub fn normalize_attributes(&self, attributes: &mut Attributes) {
+        let mut matching_attributes: Vec<usize> = attributes.keys().filter_map(|key| self.keys.get(key).copied()).collect();
+        if matching_attributes.is_empty() {
+            return;
+        }
+        matching_attributes.sort_unstable();
+        matching_attributes.dedup();
+
+        for index in matching_attributes {
+            let attribute = &self.attributes[index];
+
+            let mut defined = match attributes.get_mut(&attribute.key) {
+                Some(value) if !value.is_null() => match cast(value, attribute.value_type) {
+                    Some(cast_value) => {
+                        *value = cast_value;
+                        true
+                    }
+                    // left as is, the aliases are not renamed into an attribute with values of another type
+                    None => continue,
+                },
+                _ => false,
+            };
+
+            for alias in attribute.aliases.iter() {
+                let value = match attributes.get(alias) {
+                    None => continue,
+                    Some(value) => value,
+                };
+                if value.is_null() {
+                    attributes.remove(alias);
+                } else if !defined {
+                    if let Some(cast_value) = cast(value, attribute.value_type) {
+                        attributes.remove(alias);
+                        attributes.insert(attribute.key.clone(), cast_value);
+                        defined = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn cast(value: &Value, value_type: Option<SemconvType>) -> Option<Value> {
+    match (value_type, value) {
+        (None, value) => Some(value.clone()),
+        (Some(SemconvType::String), Value::String(_)) => Some(value.clone()),
+        (Some(SemconvType::String), Value::Number(number)) => Some(Value::String(number.to_string())),
+        (Some(SemconvType::String), Value::Bool(boolean)) => Some(Value::String(boolean.to_string())),
+        (Some(SemconvType::Int), Value::Number(number)) if number.is_i64() || number.is_u64() => Some(value.clone()),
+        (Some(SemconvType::Int), Value::Number(number)) => {
+            let number = number.as_f64()?;
+            if number.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(&number) {
+                Some(Value::from(number as i64))
+            } else {
+                None
+            }
+        }
+        (Some(SemconvType::Int), Value::String(string)) => {
+            let string = string.trim();
+            string.parse::<i64>().map(Value::from).or_else(|_| string.parse::<u64>().map(Value::from)).ok()
+        }
+        (Some(SemconvType::Double), Value::Number(number)) => Some(Value::from(number.as_f64()?)),
+        (Some(SemconvType::Double), Value::String(string)) => string.trim().parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number),
+        (Some(SemconvType::Boolean), Value::Bool(_)) => Some(value.clone()),
+        (Some(SemconvType::Boolean), Value::String(string)) => match string.trim() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use common::Attributes;
+
+    use crate::semconv::{AttributeNormalizer, SemconvAttribute, SemconvType};
+
+    fn normalizer() -> AttributeNormalizer {
+        AttributeNormalizer::new(vec![
+            SemconvAttribute {
+                key: "http.method".into(),
+                aliases: vec!["http_method".into(), "method".into()],
+                value_type: None,
+            },
+            SemconvAttribute {
+                key: "http.status_code".into(),
+                aliases: vec!["http_status_code".into()],
+                value_type: Some(SemconvType::Int),
+            },
+        ])
+        .unwrap()
+    }
+
+    fn attributes(value: serde_json::Value) -> Attributes {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn aliases_are_renamed_and_cast() {
+        let mut attrs = attributes(json!({"http_method": "GET", "method": null, "http_status_code": "200", "env": "prod"}));
+        normalizer().normalize_attributes(&mut attrs);
+        assert_eq!(attrs, attributes(json!({"http.method": "GET", "http.status_code": 200, "env": "prod"})));
+    }
+
+    #[test]
+    fn values_are_never_lost() {
+        // the attribute is already defined, and a value that can't be cast
+        let mut attrs = attributes(json!({"http.method": "GET", "method": "POST", "http_status_code": "OK"}));
+        normalizer().normalize_attributes(&mut attrs);
+        assert_eq!(attrs, attributes(json!({"http.method": "GET", "method": "POST", "http_status_code": "OK"})));
+
+        let mut attrs = attributes(json!({"http.status_code": "500", "http_status_code": "200"}));
+        normalizer().normalize_attributes(&mut attrs);
+        assert_eq!(attrs, attributes(json!({"http.status_code": 500, "http_status_code": "200"})));
+    }
+
+    #[test]
+    fn keys_are_defined_once() {
+        let table = vec![
+            SemconvAttribute {
+                key: "http.method".into(),
+                aliases: vec!["method".into()],
+                value_type: None,
+            },
+            SemconvAttribute {
+                key: "rpc.method".into(),
+                aliases: vec!["method".into()],
+                value_type: None,
+            },
+        ];
+        assert!(AttributeNormalizer::new(table).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+
+use clap::{ArgEnum, Clap, ValueHint};
+use serde::Serialize;
+
+use common::Span;
+
+use crate::arrow::cardinality::CardinalityEstimator;
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::EncodingOptions;
+use crate::compression::Compression;
+use crate::importer::{self, InputFormat};
+use crate::{ArrowEncoders, ArrowVsProto, BenchmarkConfig, BenchmarkResult};
+
+#[derive(Clap, Debug)]
+pub struct SweepOpt {
+    /// Batch sizes, as comma separated values or inclusive ranges `start..end[:step]` where step is `+n` or `*n` (default `*2`)
+    #[clap(short, long, default_value = "100..10000:*10")]
+    pub batch_sizes: String,
+
+    /// Compression codecs
+    #[clap(short, long, arg_enum, use_delimiter = true, default_values = &["none", "lz4", "gzip", "zstd"])]
+    pub compressions: Vec<Compression>,
+
+    /// Dictionary thresholds, as comma separated values or inclusive ranges `start..end[:step]` (e.g. `0.1..0.5:+0.1`)
+    #[clap(short, long, default_value = "0.2")]
+    pub dictionary_thresholds: String,
+
+    /// Cardinality estimators of the string columns
+    #[clap(long, arg_enum, use_delimiter = true, default_values = &["exact"])]
+    pub cardinality_estimators: Vec<CardinalityEstimator>,
+
+    /// Number of untimed warm-up iterations per batch
+    #[clap(long, default_value = "0")]
+    pub warmup: usize,
+
+    /// Number of timed repetitions per batch (totals are averaged over the repetitions)
+    #[clap(long, default_value = "1")]
+    pub repetitions: usize,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// Format of the report
+    #[clap(short, long, arg_enum, default_value = "csv")]
+    pub format: ReportFormat,
+
+    /// Report file (stdout if not specified)
+    #[clap(short, long, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SweepRecord {
+    file: String,
+    encoder: &'static str,
+    batch_size: usize,
+    compression: Compression,
+    dictionary_threshold: f64,
+    cardinality_estimator: CardinalityEstimator,
+    batch_count: usize,
+    span_count: usize,
+    total_buffer_size: usize,
+    total_compressed_buffer_size: usize,
+    buffer_bytes_per_span: f64,
+    compressed_buffer_bytes_per_span: f64,
+    infer_schema_ns_per_span: f64,
+    encoding_ns_per_span: f64,
+    decoding_ns_per_span: f64,
+    total_ns_per_span: f64,
+}
+
+pub fn sweep(opt: &SweepOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let batch_sizes = parse_batch_sizes(&opt.batch_sizes)?;
+    let dictionary_thresholds = parse_values(&opt.dictionary_thresholds)?;
+    let mut records = vec![];
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        let spans: Vec<Span> = importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .collect::<Result<_, _>>()
+            .map_err(|error| importer::span_error(file, error))?;
+
+        for batch_size in batch_sizes.iter() {
+            for compression in opt.compressions.iter() {
+                for dictionary_threshold in dictionary_thresholds.iter() {
+                    for cardinality_estimator in opt.cardinality_estimators.iter() {
+                        eprint!(
+                            "Processing file '{}' (batch size: {}, compression: {}, dictionary threshold: {}, cardinality estimator: {})...",
+                            filename, batch_size, compression, dictionary_threshold, cardinality_estimator
+                        );
+
+                        let config = BenchmarkConfig {
+                            compression: *compression,
+                            encoding_options: EncodingOptions {
+                                dictionary_threshold: *dictionary_threshold,
+                                cardinality_estimator: *cardinality_estimator,
+                                ..Default::default()
+                            },
+                            warmup: opt.warmup,
+                            repetitions: opt.repetitions.max(1),
+                        };
+                        let mut bench_result = ArrowVsProto::new(filename.clone());
+                        let mut arrow_row_oriented_stats_reporter = StatisticsReporter::noop();
+                        let mut arrow_col_oriented_stats_reporter = StatisticsReporter::noop();
+                        let encoders = ArrowEncoders::new(false);
+
+                        spans.chunks(*batch_size).for_each(|spans| {
+                            bench_result.bench_batch(
+                                &encoders,
+                                spans,
+                                &config,
+                                arrow_row_oriented_stats_reporter.next_batch(),
+                                arrow_col_oriented_stats_reporter.next_batch(),
+                            );
+                        });
+
+                        records.push(SweepRecord::new(&filename, "protobuf", *batch_size, &config, &bench_result.proto));
+                        records.push(SweepRecord::new(&filename, "arrow_row_oriented", *batch_size, &config, &bench_result.arrow_1));
+                        records.push(SweepRecord::new(
+                            &filename,
+                            "arrow_column_oriented",
+                            *batch_size,
+                            &config,
+                            &bench_result.arrow_2,
+                        ));
+                        records.push(SweepRecord::new(&filename, "arrow_builder", *batch_size, &config, &bench_result.arrow_3));
+
+                        eprintln!("DONE.");
+                    }
+                }
+            }
+        }
+    }
+
+    let writer: Box<dyn Write> = match &opt.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+
+    match opt.format {
+        ReportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for record in records.iter() {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+        ReportFormat::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, &records)?;
+            writeln!(writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl SweepRecord {
+    fn new(file: &str, encoder: &'static str, batch_size: usize, config: &BenchmarkConfig, result: &BenchmarkResult) -> Self {
+        let span_count = result.row_count.max(1) as f64;
+        let encoding_ns = result.total_infer_schema_ns
+            + result.total_data_source_conversion_ns
+            + result.total_buffer_creation_ns
+            + result.total_buffer_serialization_ns
+            + result.total_buffer_compression_ns;
+        let decoding_ns = result.total_buffer_decompression_ns + result.total_buffer_deserialization_ns;
+
+        Self {
+            file: file.into(),
+            encoder,
+            batch_size,
+            compression: config.compression,
+            dictionary_threshold: config.encoding_options.dictionary_threshold,
+            cardinality_estimator: config.encoding_options.cardinality_estimator,
+            batch_count: result.batch_count,
+            span_count: result.row_count,
+            total_buffer_size: result.total_buffer_size,
+            total_compressed_buffer_size: result.total_compressed_buffer_size,
+            buffer_bytes_per_span: result.total_buffer_size as f64 / span_count,
+            compressed_buffer_bytes_per_span: result.total_compressed_buffer_size as f64 / span_count,
+            infer_schema_ns_per_span: result.total_infer_schema_ns as f64 / span_count,
+            encoding_ns_per_span: encoding_ns as f64 / span_count,
+            decoding_ns_per_span: decoding_ns as f64 / span_count,
+            total_ns_per_span: (encoding_ns + decoding_ns) as f64 / span_count,
+        }
+    }
+}
+
+fn parse_batch_sizes(spec: &str) -> Result<Vec<usize>, String> {
+    let mut batch_sizes: Vec<usize> = parse_values(spec)?.iter().map(|value| value.round() as usize).collect();
+    batch_sizes.dedup();
+    if batch_sizes.contains(&0) {
+        return Err(format!("invalid batch sizes '{}', a batch size must be greater than 0", spec));
+    }
+    Ok(batch_sizes)
+}
+
+// Parses comma separated values and ranges `start..end[:step]`, e.g. `100,200`, `100..10000:*10` or `0.1..0.3:+0.1`.
+// A range stops at the last step not greater than `end`.
+fn parse_values(spec: &str) -> Result<Vec<f64>, String> {
+    let mut values = vec![];
+
+    for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        match item.split_once("..") {
+            None => values.push(parse_number(item)?),
+            Some((start, rest)) => {
+                let (end, step) = rest.split_once(':').unwrap_or((rest, "*2"));
+                let start = parse_number(start)?;
+                let end = parse_number(end)?;
+                let (multiplicative, step) = match step.strip_prefix('*') {
+                    Some(factor) => (true, parse_number(factor)?),
+                    None => (false, parse_number(step.strip_prefix('+').unwrap_or(step))?),
+                };
+                if start > end || (multiplicative && (step <= 1.0 || start <= 0.0)) || (!multiplicative && step <= 0.0) {
+                    return Err(format!("invalid range '{}'", item));
+                }
+
+                let epsilon = (end - start).abs() * 1e-9;
+                let mut value = start;
+                while value <= end + epsilon {
+                    // Snaps the last step onto `end` to absorb floating point drift.
+                    values.push(if (value - end).abs() <= epsilon { end } else { value });
+                    value = if multiplicative { value * step } else { value + step };
+                }
+            }
+        }
+    }
+
+    if values.is_empty() {
+        return Err(format!("no value defined in '{}'", spec));
+    }
+    Ok(values)
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value.trim().parse::<f64>().map_err(|_| format!("invalid number '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_only_include_values_on_the_grid() {
+        assert_eq!(parse_values("100,200").unwrap(), vec![100.0, 200.0]);
+        assert_eq!(parse_values("1..10:+4").unwrap(), vec![1.0, 5.0, 9.0]);
+        assert_eq!(parse_values("1..9:+4").unwrap(), vec![1.0, 5.0, 9.0]);
+        assert_eq!(parse_values("100..10000:*10").unwrap(), vec![100.0, 1000.0, 10000.0]);
+        assert_eq!(parse_values("100..5000:*10").unwrap(), vec![100.0, 1000.0]);
+        assert_eq!(parse_values("0.1..0.3:+0.1").unwrap(), vec![0.1, 0.2, 0.3]);
+        assert_eq!(parse_values("5..5").unwrap(), vec![5.0]);
+        assert_eq!(parse_values("1..8").unwrap(), vec![1.0, 2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        for spec in ["", "a", "10..1", "1..10:*1", "0..10:*2", "1..10:+0", "1..10:-1"].iter() {
+            assert!(parse_values(spec).is_err(), "'{}' should be rejected", spec);
+        }
+        assert!(parse_batch_sizes("0,10").is_err());
+        assert_eq!(parse_batch_sizes("1000..4000:+1500").unwrap(), vec![1000, 2500, 4000]);
+    }
+}
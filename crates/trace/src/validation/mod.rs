@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use clap::{ArgEnum, Clap, ValueHint};
+use itertools::Itertools;
+use serde::Serialize;
+
+use common::Span;
+
+use crate::arrow::statistics::StatisticsReporter;
+use crate::arrow::view::{SpanView, TraceBatch};
+use crate::arrow::{serialize_column_oriented_data_source, EncodingOptions};
+use crate::importer::{self, InputFormat};
+use crate::BenchmarkResult;
+
+#[derive(Clap, Debug)]
+pub struct ValidateOpt {
+    /// Validate the spans decoded from the Arrow encoding of the input instead of the input spans
+    #[clap(long)]
+    pub decoded: bool,
+
+    /// Maximum batch size (only used with --decoded)
+    #[clap(short, long, default_value = "1000")]
+    pub batch_size: usize,
+
+    /// Format of the input files
+    #[clap(long, arg_enum, default_value = "json")]
+    pub input_format: InputFormat,
+
+    /// Format of the validation report
+    #[clap(short, long, arg_enum, default_value = "text")]
+    pub format: ValidationFormat,
+
+    /// Maximum number of issues listed per file in the text report
+    #[clap(long, default_value = "20")]
+    pub max_issues: usize,
+
+    /// JSON files to process
+    #[clap(name = "FILE", parse(from_os_str), value_hint = ValueHint::AnyPath, required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ValidationFormat {
+    Text,
+    Json,
+}
+
+// Fields of a span needed to rebuild the trace trees, borrowed from `common::Span`s or from a `TraceBatchView`.
+#[derive(Debug, Clone, Copy)]
+pub struct SpanRef<'a> {
+    pub trace_id: &'a [u8],
+    pub span_id: &'a [u8],
+    pub parent_span_id: Option<&'a [u8]>,
+    pub start_time_unix_nano: u64,
+}
+
+#[derive(Debug)]
+pub struct TraceTree<'a> {
+    pub trace_id: &'a [u8],
+    pub nodes: Vec<TreeNode<'a>>,
+    pub roots: Vec<usize>,
+    // Span ids seen more than once, only the first span is part of the tree.
+    pub duplicate_span_ids: Vec<&'a [u8]>,
+}
+
+#[derive(Debug)]
+pub struct TreeNode<'a> {
+    pub span_id: &'a [u8],
+    pub parent_span_id: Option<&'a [u8]>,
+    pub start_time_unix_nano: u64,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Issue {
+    DuplicateSpanId {
+        trace_id: String,
+        span_id: String,
+    },
+    Orphan {
+        trace_id: String,
+        span_id: String,
+        parent_span_id: String,
+    },
+    Cycle {
+        trace_id: String,
+        span_ids: Vec<String>,
+    },
+    ChildStartsBeforeParent {
+        trace_id: String,
+        span_id: String,
+        parent_span_id: String,
+        delta_ns: u64,
+    },
+    RootCount {
+        trace_id: String,
+        root_count: usize,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    pub span_count: usize,
+    pub root_count: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub span_count: usize,
+    pub trace_count: usize,
+    pub traces: Vec<TraceSummary>,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileReport {
+    file: String,
+    decoded: bool,
+    report: ValidationReport,
+}
+
+impl<'a> From<&'a Span<'_>> for SpanRef<'a> {
+    fn from(span: &'a Span<'_>) -> Self {
+        Self {
+            trace_id: span.trace_id.as_bytes(),
+            span_id: span.span_id.as_bytes(),
+            parent_span_id: span.parent_span_id.as_ref().map(|parent_span_id| parent_span_id.as_bytes()),
+            start_time_unix_nano: span.start_time_unix_nano,
+        }
+    }
+}
+
+impl<'a> From<SpanView<'a>> for SpanRef<'a> {
+    fn from(span: SpanView<'a>) -> Self {
+        Self {
+            trace_id: span.trace_id(),
+            span_id: span.span_id(),
+            parent_span_id: span.parent_span_id(),
+            start_time_unix_nano: span.start_time_unix_nano(),
+        }
+    }
+}
+
+// Groups the spans per trace and links every span to its parent. Traces are returned in order of first appearance.
+pub fn build_trace_trees<'a>(spans: impl IntoIterator<Item = SpanRef<'a>>) -> Vec<TraceTree<'a>> {
+    let mut trees: Vec<TraceTree<'a>> = vec![];
+    let mut tree_index: HashMap<&'a [u8], usize> = HashMap::new();
+    let mut node_indexes: Vec<HashMap<&'a [u8], usize>> = vec![];
+
+    for span in spans {
+        let tree = *tree_index.entry(span.trace_id).or_insert_with(|| {
+            trees.push(TraceTree {
+                trace_id: span.trace_id,
+                nodes: vec![],
+                roots: vec![],
+                duplicate_span_ids: vec![],
+            });
+            node_indexes.push(HashMap::new());
+            trees.len() - 1
+        });
+
+        if node_indexes[tree].contains_key(span.span_id) {
+            trees[tree].duplicate_span_ids.push(span.span_id);
+            continue;
+        }
+        node_indexes[tree].insert(span.span_id, trees[tree].nodes.len());
+        trees[tree].nodes.push(TreeNode {
+            span_id: span.span_id,
+            parent_span_id: span.parent_span_id.filter(|parent_span_id| !parent_span_id.is_empty()),
+            start_time_unix_nano: span.start_time_unix_nano,
+            parent: None,
+            children: vec![],
+        });
+    }
+
+    for (tree, node_index) in trees.iter_mut().zip(node_indexes.iter()) {
+        for i in 0..tree.nodes.len() {
+            match tree.nodes[i].parent_span_id {
+                None => tree.roots.push(i),
+                Some(parent_span_id) => {
+                    if let Some(parent) = node_index.get(parent_span_id) {
+                        tree.nodes[i].parent = Some(*parent);
+                        tree.nodes[*parent].children.push(i);
+                    }
+                }
+            }
+        }
+    }
+
+    trees
+}
+
+pub fn validate<'a>(spans: impl IntoIterator<Item = SpanRef<'a>>) -> ValidationReport {
+    validate_trees(&build_trace_trees(spans))
+}
+
+pub fn validate_trees(trees: &[TraceTree]) -> ValidationReport {
+    let mut report = ValidationReport {
+        trace_count: trees.len(),
+        ..Default::default()
+    };
+
+    for tree in trees {
+        let trace_id = id_to_string(tree.trace_id);
+        report.span_count += tree.nodes.len() + tree.duplicate_span_ids.len();
+        report.traces.push(TraceSummary {
+            trace_id: trace_id.clone(),
+            span_count: tree.nodes.len(),
+            root_count: tree.roots.len(),
+        });
+
+        for span_id in tree.duplicate_span_ids.iter() {
+            report.issues.push(Issue::DuplicateSpanId {
+                trace_id: trace_id.clone(),
+                span_id: id_to_string(span_id),
+            });
+        }
+
+        for node in tree.nodes.iter() {
+            match (node.parent_span_id, node.parent) {
+                (Some(parent_span_id), None) => report.issues.push(Issue::Orphan {
+                    trace_id: trace_id.clone(),
+                    span_id: id_to_string(node.span_id),
+                    parent_span_id: id_to_string(parent_span_id),
+                }),
+                (Some(parent_span_id), Some(parent)) if node.start_time_unix_nano < tree.nodes[parent].start_time_unix_nano => {
+                    report.issues.push(Issue::ChildStartsBeforeParent {
+                        trace_id: trace_id.clone(),
+                        span_id: id_to_string(node.span_id),
+                        parent_span_id: id_to_string(parent_span_id),
+                        delta_ns: tree.nodes[parent].start_time_unix_nano - node.start_time_unix_nano,
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        for cycle in find_cycles(tree) {
+            report.issues.push(Issue::Cycle {
+                trace_id: trace_id.clone(),
+                span_ids: cycle.iter().map(|node| id_to_string(tree.nodes[*node].span_id)).collect(),
+            });
+        }
+
+        if tree.roots.len() != 1 {
+            report.issues.push(Issue::RootCount {
+                trace_id: trace_id.clone(),
+                root_count: tree.roots.len(),
+            });
+        }
+    }
+
+    report
+}
+
+// Walks up the parent links of every node; reaching a node of the current walk again closes a cycle.
+fn find_cycles(tree: &TraceTree) -> Vec<Vec<usize>> {
+    const UNVISITED: u8 = 0;
+    const IN_PROGRESS: u8 = 1;
+    const DONE: u8 = 2;
+
+    let mut states = vec![UNVISITED; tree.nodes.len()];
+    let mut cycles = vec![];
+
+    for start in 0..tree.nodes.len() {
+        let mut path = vec![];
+        let mut current = Some(start);
+
+        while let Some(node) = current {
+            match states[node] {
+                DONE => break,
+                IN_PROGRESS => {
+                    let position = path.iter().position(|n| *n == node).expect("node in progress must be on the current path");
+                    cycles.push(path[position..].to_vec());
+                    break;
+                }
+                _ => {
+                    states[node] = IN_PROGRESS;
+                    path.push(node);
+                    current = tree.nodes[node].parent;
+                }
+            }
+        }
+
+        for node in path {
+            states[node] = DONE;
+        }
+    }
+
+    cycles
+}
+
+fn id_to_string(id: &[u8]) -> String {
+    String::from_utf8_lossy(id).to_string()
+}
+
+pub fn validate_files(opt: &ValidateOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reports = vec![];
+
+    for file in &opt.files {
+        let filename = file.as_path().display().to_string();
+        eprint!("Validating file '{}'...", filename);
+        let spans: Vec<Span> = importer::read_spans(opt.input_format, BufReader::new(File::open(file)?))
+            .collect::<Result<_, _>>()
+            .map_err(|error| importer::span_error(file, error))?;
+
+        let report = if opt.decoded {
+            let batches = encode_decode(&spans, opt.batch_size)?;
+            let views = batches.iter().map(|batch| batch.view()).collect::<Result<Vec<_>, _>>()?;
+            validate(views.iter().flat_map(|view| view.spans().map(SpanRef::from)))
+        } else {
+            validate(spans.iter().map(SpanRef::from))
+        };
+
+        reports.push(FileReport {
+            file: filename,
+            decoded: opt.decoded,
+            report,
+        });
+        eprintln!("DONE.");
+    }
+
+    match opt.format {
+        ValidationFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+        ValidationFormat::Text => {
+            for file_report in reports.iter() {
+                print_report(file_report, opt.max_issues);
+            }
+        }
+    }
+
+    let issue_count: usize = reports.iter().map(|file_report| file_report.report.issues.len()).sum();
+    if issue_count > 0 {
+        return Err(format!("{} issue(s) found", issue_count).into());
+    }
+    Ok(())
+}
+
+// Round trip through the column-oriented Arrow encoder, batch by batch.
+fn encode_decode(spans: &[Span], batch_size: usize) -> Result<Vec<TraceBatch>, Box<dyn std::error::Error>> {
+    let mut stats_reporter = StatisticsReporter::noop();
+    let mut batches = vec![];
+
+    for spans in spans.chunks(batch_size.max(1)) {
+        let buf = serialize_column_oriented_data_source(stats_reporter.next_batch(), spans, &EncodingOptions::default(), &mut BenchmarkResult::new())?;
+        batches.extend(TraceBatch::decode(buf.into())?);
+    }
+
+    Ok(batches)
+}
+
+fn print_report(file_report: &FileReport, max_issues: usize) {
+    let report = &file_report.report;
+    let root_counts = report.traces.iter().map(|trace| trace.root_count).counts();
+
+    println!("File '{}'{}", file_report.file, if file_report.decoded { " (decoded)" } else { "" });
+    println!("  spans: {}, traces: {}", report.span_count, report.trace_count);
+    for (root_count, trace_count) in root_counts.iter().sorted() {
+        println!("  traces with {} root(s): {}", root_count, trace_count);
+    }
+    println!("  issues: {}", report.issues.len());
+    for issue in report.issues.iter().take(max_issues) {
+        println!("    {}", issue);
+    }
+    if report.issues.len() > max_issues {
+        println!("    ... ({} more)", report.issues.len() - max_issues);
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::DuplicateSpanId { trace_id, span_id } => write!(f, "trace {}: duplicate span id {}", trace_id, span_id),
+            Issue::Orphan {
+                trace_id,
+                span_id,
+                parent_span_id,
+            } => write!(f, "trace {}: span {} references unknown parent {}", trace_id, span_id, parent_span_id),
+            Issue::Cycle { trace_id, span_ids } => write!(f, "trace {}: cycle {}", trace_id, span_ids.join(" -> ")),
+            Issue::ChildStartsBeforeParent {
+                trace_id,
+                span_id,
+                parent_span_id,
+                delta_ns,
+            } => write!(
+                f,
+                "trace {}: span {} starts {} ns before its parent {}",
+                trace_id, span_id, delta_ns, parent_span_id
+            ),
+            Issue::RootCount { trace_id, root_count } => write!(f, "trace {}: {} root spans", trace_id, root_count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // trace id, span id, parent span id, start time
+    type SpanRow = (&'static str, &'static str, Option<&'static str>, u64);
+
+    fn span_refs(rows: &[SpanRow]) -> Vec<SpanRef<'static>> {
+        rows.iter()
+            .map(|(trace_id, span_id, parent_span_id, start_time_unix_nano)| SpanRef {
+                trace_id: trace_id.as_bytes(),
+                span_id: span_id.as_bytes(),
+                parent_span_id: parent_span_id.map(str::as_bytes),
+                start_time_unix_nano: *start_time_unix_nano,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn issues_are_reported() {
+        let cases: Vec<(&str, Vec<SpanRow>, Vec<&str>)> = vec![
+            (
+                "valid",
+                vec![("t1", "a", None, 0), ("t1", "b", Some("a"), 1), ("t1", "c", Some("b"), 2)],
+                vec![],
+            ),
+            ("empty parent id", vec![("t1", "a", Some(""), 0)], vec![]),
+            (
+                "multiple roots",
+                vec![("t1", "a", None, 0), ("t1", "b", None, 0)],
+                vec!["trace t1: 2 root spans"],
+            ),
+            (
+                "orphan",
+                vec![("t1", "a", None, 0), ("t1", "b", Some("x"), 1)],
+                vec!["trace t1: span b references unknown parent x"],
+            ),
+            (
+                "self parent",
+                vec![("t1", "a", Some("a"), 0)],
+                vec!["trace t1: cycle a", "trace t1: 0 root spans"],
+            ),
+            (
+                "cycle",
+                vec![("t1", "r", None, 0), ("t1", "a", Some("b"), 1), ("t1", "b", Some("a"), 1)],
+                vec!["trace t1: cycle a -> b"],
+            ),
+            (
+                "child starts before parent",
+                vec![("t1", "a", None, 10), ("t1", "b", Some("a"), 4)],
+                vec!["trace t1: span b starts 6 ns before its parent a"],
+            ),
+            (
+                "duplicate span id",
+                vec![("t1", "a", None, 0), ("t1", "a", None, 5)],
+                vec!["trace t1: duplicate span id a"],
+            ),
+            (
+                "parents are looked up in the same trace",
+                vec![("t1", "a", None, 0), ("t2", "b", Some("a"), 1), ("t1", "b", Some("a"), 1)],
+                vec!["trace t2: span b references unknown parent a", "trace t2: 0 root spans"],
+            ),
+        ];
+
+        for (name, rows, expected_issues) in cases {
+            let report = validate(span_refs(&rows));
+            let issues: Vec<String> = report.issues.iter().map(|issue| issue.to_string()).collect();
+            assert_eq!(issues, expected_issues, "{}", name);
+            assert_eq!(report.span_count, rows.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn trees_link_spans_to_their_parent() {
+        let trees = build_trace_trees(span_refs(&[
+            ("t1", "a", None, 0),
+            ("t2", "x", None, 0),
+            ("t1", "b", Some("a"), 1),
+            ("t1", "c", Some("a"), 2),
+            ("t1", "b", Some("c"), 3),
+        ]));
+
+        assert_eq!(trees.iter().map(|tree| tree.trace_id).collect::<Vec<_>>(), vec![b"t1".as_ref(), b"t2".as_ref()]);
+        assert_eq!(trees[0].roots, vec![0]);
+        assert_eq!(trees[0].nodes[0].children, vec![1, 2]);
+        assert_eq!(trees[0].nodes[2].parent, Some(0));
+        assert_eq!(trees[0].duplicate_span_ids, vec![b"b".as_ref()]);
+        assert_eq!(trees[1].roots, vec![0]);
+
+        let report = validate_trees(&trees);
+        assert_eq!((report.span_count, report.trace_count), (5, 2));
+        assert_eq!(
+            report.traces.iter().map(|trace| (trace.span_count, trace.root_count)).collect::<Vec<_>>(),
+            vec![(3, 1), (1, 1)]
+        );
+    }
+
+    #[test]
+    fn cycles_are_reported_once() {
+        // a -> c -> b -> a, d is attached to the cycle, e is its own parent
+        let trees = build_trace_trees(span_refs(&[
+            ("t1", "a", Some("c"), 0),
+            ("t1", "b", Some("a"), 0),
+            ("t1", "c", Some("b"), 0),
+            ("t1", "d", Some("b"), 0),
+            ("t1", "e", Some("e"), 0),
+        ]));
+
+        assert_eq!(find_cycles(&trees[0]), vec![vec![0, 2, 1], vec![4]]);
+    }
+}